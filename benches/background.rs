@@ -1,8 +1,9 @@
+use std::hint::black_box;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
 use arc_swap::{ArcSwap, ArcSwapOption, Cache};
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 use crossbeam_utils::thread;
 use once_cell::sync::Lazy;
 
@@ -104,7 +105,7 @@ macro_rules! strategy {
 
             fn write() {
                 for _ in 0..ITERS {
-                    black_box(A.store(Arc::new(0)));
+                    A.store(Arc::new(0));
                 }
             }
 
@@ -142,7 +143,7 @@ mod arc_swap_option {
 
     fn write() {
         for _ in 0..ITERS {
-            black_box(A.store(Some(Arc::new(0))));
+            A.store(Some(Arc::new(0)));
         }
     }
 
@@ -176,7 +177,7 @@ mod arc_swap_cached {
 
     fn write() {
         for _ in 0..ITERS {
-            black_box(A.store(Arc::new(0)));
+            A.store(Arc::new(0));
         }
     }
 
@@ -208,7 +209,7 @@ mod mutex {
 
     fn write() {
         for _ in 0..ITERS {
-            black_box(*M.lock().unwrap() = Arc::new(42));
+            *M.lock().unwrap() = Arc::new(42);
         }
     }
 
@@ -242,7 +243,7 @@ mod parking_mutex {
 
     fn write() {
         for _ in 0..ITERS {
-            black_box(*M.lock() = Arc::new(42));
+            *M.lock() = Arc::new(42);
         }
     }
 
@@ -276,7 +277,7 @@ mod rwlock {
 
     fn write() {
         for _ in 0..ITERS {
-            black_box(*L.write().unwrap() = Arc::new(42));
+            *L.write().unwrap() = Arc::new(42);
         }
     }
 
@@ -310,7 +311,7 @@ mod parking_rwlock {
 
     fn write() {
         for _ in 0..ITERS {
-            black_box(*L.write() = Arc::new(42));
+            *L.write() = Arc::new(42);
         }
     }
 
@@ -1,12 +1,12 @@
 //! These are very minimal benchmarks ‒ reading and writing an integer shared in
 //! different ways. You can compare the times and see the characteristics.
 
+use std::hint::black_box;
 use std::io::{self, Write};
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::Instant;
 
 use arc_swap::ArcSwap;
-use criterion::black_box;
 use crossbeam_utils::thread;
 
 fn test_run<R, W>(
@@ -37,7 +37,7 @@ fn test_run<R, W>(
         for _ in 0..write_threads {
             scope.spawn(|_| {
                 for i in 0..iterations {
-                    black_box(w(i));
+                    w(i);
                 }
             });
         }
@@ -3,13 +3,14 @@
 //! Slightly based on the <background.rs> benchmarks, but simplified and stripped down to run
 //! reasonably fast.
 
+use std::hint::black_box;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use arc_swap::access::{Access, Map};
 use arc_swap::cache::Cache;
 use arc_swap::ArcSwap;
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 use crossbeam_utils::thread;
 
 /// Execute a group of measurements
@@ -41,7 +42,7 @@ fn batch(c: &mut Criterion, name: &str, shared_number: &ArcSwap<usize>) {
     });
     g.bench_function("store", |b| {
         b.iter(|| {
-            black_box(shared_number.store(Arc::new(42)));
+            shared_number.store(Arc::new(42));
         })
     });
     g.bench_function("cache", |b| {
@@ -96,6 +97,32 @@ fn utilities(c: &mut Criterion) {
             assert_eq!(42, *g);
         });
     });
+
+    // A thread going through many storages, with short-lived guards. Each load is of a different
+    // pointer than the previous one.
+    g.bench_function("load-cycle", |b| {
+        let storages = (0..256).map(ArcSwap::from_pointee).collect::<Vec<_>>();
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % storages.len();
+            black_box(**storages[i].load());
+        });
+    });
+    g.bench_function("store-idle-readers", |b| {
+        // Many threads that have a node, but hold nothing.
+        let shared = ArcSwap::from_pointee(42);
+        thread::scope(|s| {
+            for _ in 0..16 {
+                s.spawn(|_| {
+                    black_box(shared.load());
+                });
+            }
+        })
+        .unwrap();
+        b.iter(|| {
+            shared.store(Arc::new(42));
+        });
+    });
 }
 
 fn benchmark(c: &mut Criterion) {
@@ -104,7 +131,7 @@ fn benchmark(c: &mut Criterion) {
         black_box(s.load());
     });
     with_background(c, "concurrent_store", 1, |s| {
-        black_box(s.store(Arc::new(42)));
+        s.store(Arc::new(42));
     });
     utilities(c);
 }
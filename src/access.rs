@@ -12,7 +12,8 @@
 //!
 //! Note that the [`cache`][crate::cache] module has its own [`Access`][crate::cache::Access] trait
 //! that serves a similar purpose, but with cached access. The signatures are different, therefore
-//! an incompatible trait.
+//! an incompatible trait. The [`FromCache`] and [`cache::FromAccess`][crate::cache::FromAccess]
+//! adaptors convert between the two.
 //!
 //! # The general idea
 //!
@@ -30,6 +31,42 @@
 //! there's the [`Constant`] helper type, which is useful mostly for testing (it doesn't allow
 //! reloading).
 //!
+//! Multiple sources can be combined ‒ [`Zip`] provides two values at once, [`Fallback`] allows
+//! layering optional values over some defaults and [`FlatMap`] reaches into nested storages.
+//!
+//! # Writing
+//!
+//! The [`Map`] created from a plain projection can only read. If the component should be able to
+//! also update its own part, it can get a [`WriteAccess`] instead. That one is implemented by the
+//! [`ArcSwapAny`] and by a [`Map`] created from a [`Lens`] ‒ a projection that also knows how to
+//! create a copy of the bigger structure with the part replaced. An update of the part then
+//! does a [`rcu`][ArcSwapAny::rcu] on the whole structure.
+//!
+//! ```rust
+//! use arc_swap::ArcSwap;
+//! use arc_swap::access::{Access, Lens, Map, WriteAccess};
+//!
+//! #[derive(Clone)]
+//! struct Cfg {
+//!     name: String,
+//!     port: u16,
+//! }
+//!
+//! fn bump_port<A: WriteAccess<u16>>(a: A) {
+//!     a.rcu(|port| port + 1);
+//! }
+//!
+//! let cfg = ArcSwap::from_pointee(Cfg { name: "server".to_owned(), port: 80 });
+//! let port = Map::with_lens(
+//!     &cfg,
+//!     Lens::new(|cfg: &Cfg| &cfg.port, |cfg: &Cfg, port| Cfg { port, ..cfg.clone() }),
+//! );
+//! bump_port(&port);
+//! assert_eq!(81, *port.load());
+//! assert_eq!(81, cfg.load().port);
+//! assert_eq!("server", cfg.load().name);
+//! ```
+//!
 //! # Performance
 //!
 //! In general, these utilities use [`ArcSwapAny::load`] internally and then apply the provided
@@ -82,13 +119,20 @@
 //! // Passing a constant that can't change. Useful mostly for testing purposes.
 //! work_with_usize(Constant(42)).join().unwrap();
 //! ```
+use core::fmt::Debug;
+#[cfg(not(feature = "custom-thread-local"))]
+use core::fmt::{Formatter, Result as FmtResult};
 use core::marker::PhantomData;
 use core::ops::Deref;
 
 use crate::imports::{Arc, Box, Rc};
+#[cfg(not(feature = "custom-thread-local"))]
+use crate::per_thread::PerThread;
 
+use super::lazy::{LazyArcSwap, LazyGuard};
 use super::ref_cnt::RefCnt;
-use super::strategy::Strategy;
+use super::replicated::Replicated;
+use super::strategy::{CaS, Strategy};
 use super::{ArcSwapAny, Guard};
 
 /// Abstracts over ways code can get access to a value of type `T`.
@@ -112,6 +156,7 @@ pub trait Access<T> {
 
 impl<T, A: Access<T> + ?Sized, P: Deref<Target = A>> Access<T> for P {
     type Guard = A::Guard;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn load(&self) -> Self::Guard {
         self.deref().load()
     }
@@ -120,6 +165,7 @@ impl<T, A: Access<T> + ?Sized, P: Deref<Target = A>> Access<T> for P {
 impl<T> Access<T> for dyn DynAccess<T> + '_ {
     type Guard = DynGuard<T>;
 
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn load(&self) -> Self::Guard {
         self.load()
     }
@@ -128,6 +174,7 @@ impl<T> Access<T> for dyn DynAccess<T> + '_ {
 impl<T> Access<T> for dyn DynAccess<T> + '_ + Send {
     type Guard = DynGuard<T>;
 
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn load(&self) -> Self::Guard {
         self.load()
     }
@@ -136,6 +183,7 @@ impl<T> Access<T> for dyn DynAccess<T> + '_ + Send {
 impl<T> Access<T> for dyn DynAccess<T> + '_ + Sync + Send {
     type Guard = DynGuard<T>;
 
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn load(&self) -> Self::Guard {
         self.load()
     }
@@ -144,6 +192,7 @@ impl<T> Access<T> for dyn DynAccess<T> + '_ + Sync + Send {
 impl<T: RefCnt, S: Strategy<T>> Access<T> for ArcSwapAny<T, S> {
     type Guard = Guard<T, S>;
 
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn load(&self) -> Self::Guard {
         self.load()
     }
@@ -162,6 +211,7 @@ impl<T, S: Strategy<Arc<T>>> Deref for DirectDeref<Arc<T>, S> {
 
 impl<T, S: Strategy<Arc<T>>> Access<T> for ArcSwapAny<Arc<T>, S> {
     type Guard = DirectDeref<Arc<T>, S>;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn load(&self) -> Self::Guard {
         DirectDeref(self.load())
     }
@@ -176,6 +226,83 @@ impl<T, S: Strategy<Rc<T>>> Deref for DirectDeref<Rc<T>, S> {
 
 impl<T, S: Strategy<Rc<T>>> Access<T> for ArcSwapAny<Rc<T>, S> {
     type Guard = DirectDeref<Rc<T>, S>;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        DirectDeref(self.load())
+    }
+}
+
+impl<T: RefCnt, S: Strategy<T>> Access<T> for Replicated<T, S> {
+    type Guard = Guard<T, S>;
+
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        self.load()
+    }
+}
+
+impl<T, S: Strategy<Arc<T>>> Access<T> for Replicated<Arc<T>, S> {
+    type Guard = DirectDeref<Arc<T>, S>;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        DirectDeref(self.load())
+    }
+}
+
+impl<T, S: Strategy<Rc<T>>> Access<T> for Replicated<Rc<T>, S> {
+    type Guard = DirectDeref<Rc<T>, S>;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        DirectDeref(self.load())
+    }
+}
+
+impl<T, F, S> Access<T> for LazyArcSwap<T, F, S>
+where
+    F: Fn() -> T,
+    S: CaS<Option<Arc<T>>>,
+{
+    type Guard = LazyGuard<T, S>;
+
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        self.load()
+    }
+}
+
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+impl<T> Access<Arc<T>> for crate::history::HistoryArcSwap<T> {
+    type Guard = Guard<Arc<T>>;
+
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        self.load()
+    }
+}
+
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+impl<T> Access<T> for crate::history::HistoryArcSwap<T> {
+    type Guard = DirectDeref<Arc<T>, crate::DefaultStrategy>;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        DirectDeref(self.load())
+    }
+}
+
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+impl<T, S: Strategy<Arc<T>>> Access<Arc<T>> for crate::reclaim::ReclaimingArcSwap<T, S> {
+    type Guard = Guard<Arc<T>, S>;
+
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        self.load()
+    }
+}
+
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+impl<T, S: Strategy<Arc<T>>> Access<T> for crate::reclaim::ReclaimingArcSwap<T, S> {
+    type Guard = DirectDeref<Arc<T>, S>;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn load(&self) -> Self::Guard {
         DirectDeref(self.load())
     }
@@ -221,6 +348,7 @@ where
     A: Access<T>,
     A::Guard: 'static,
 {
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn load(&self) -> DynGuard<T> {
         DynGuard(Box::new(Access::load(self)))
     }
@@ -284,11 +412,49 @@ where
 {
     type Guard = DynGuard<T>;
 
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn load(&self) -> Self::Guard {
         self.0.load()
     }
 }
 
+/// Abstracts over ways code can update a value of type `T`.
+///
+/// This is the writing counterpart of [`Access`]. See the [module documentation](index.html) for
+/// details.
+pub trait WriteAccess<T>: Access<T> {
+    /// Updates the value, in the read-copy-update fashion.
+    ///
+    /// The closure gets the current value and returns the new one. Just like with
+    /// [`ArcSwapAny::rcu`], it may be called multiple times if the value changes concurrently,
+    /// until the update succeeds.
+    fn rcu<F: FnMut(&T) -> T>(&self, f: F);
+}
+
+impl<T, A: WriteAccess<T> + ?Sized, P: Deref<Target = A>> WriteAccess<T> for P {
+    fn rcu<F: FnMut(&T) -> T>(&self, f: F) {
+        self.deref().rcu(f)
+    }
+}
+
+impl<T: RefCnt, S: CaS<T>> WriteAccess<T> for ArcSwapAny<T, S> {
+    fn rcu<F: FnMut(&T) -> T>(&self, f: F) {
+        ArcSwapAny::rcu(self, f);
+    }
+}
+
+impl<T, S: CaS<Arc<T>>> WriteAccess<T> for ArcSwapAny<Arc<T>, S> {
+    fn rcu<F: FnMut(&T) -> T>(&self, mut f: F) {
+        ArcSwapAny::rcu(self, |cur: &Arc<T>| Arc::new(f(cur)));
+    }
+}
+
+impl<T, S: CaS<Rc<T>>> WriteAccess<T> for ArcSwapAny<Rc<T>, S> {
+    fn rcu<F: FnMut(&T) -> T>(&self, mut f: F) {
+        ArcSwapAny::rcu(self, |cur: &Rc<T>| Rc::new(f(cur)));
+    }
+}
+
 #[doc(hidden)]
 #[derive(Copy, Clone, Debug)]
 pub struct MapGuard<G, F, T, R> {
@@ -349,6 +515,7 @@ where
     F: Fn(&T) -> &R + Clone,
 {
     type Guard = MapGuard<A::Guard, F, T, R>;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn load(&self) -> Self::Guard {
         let guard = self.access.load();
         MapGuard {
@@ -359,6 +526,80 @@ where
     }
 }
 
+/// A projection into a part of larger structure that can also replace the part.
+///
+/// It consists of two functions:
+///
+/// * The getter, providing a reference into the bigger structure (the same as the projection
+///   passed to [`Map::new`]).
+/// * The setter, creating a copy of the bigger structure with the part replaced by the new value.
+///
+/// It is used with [`Map::with_lens`], to get a [`WriteAccess`] to the part.
+#[derive(Copy, Clone, Debug)]
+pub struct Lens<G, S> {
+    get: G,
+    set: S,
+}
+
+impl<G, S> Lens<G, S> {
+    /// Creates the lens from the getter and the setter.
+    pub fn new<T, R>(get: G, set: S) -> Self
+    where
+        G: Fn(&T) -> &R + Clone,
+        S: Fn(&T, R) -> T,
+    {
+        Lens { get, set }
+    }
+}
+
+impl<A, T, G, S> Map<A, T, Lens<G, S>> {
+    /// Creates a new instance from a [`Lens`].
+    ///
+    /// This is like [`new`][Map::new], but the result also allows writing into the part if the
+    /// `access` allows writing into the bigger structure.
+    pub fn with_lens<R>(access: A, lens: Lens<G, S>) -> Self
+    where
+        G: Fn(&T) -> &R + Clone,
+        S: Fn(&T, R) -> T,
+    {
+        Map {
+            access,
+            projection: lens,
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<A, T, G, S, R> Access<R> for Map<A, T, Lens<G, S>>
+where
+    A: Access<T>,
+    G: Fn(&T) -> &R + Clone,
+{
+    type Guard = MapGuard<A::Guard, G, T, R>;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        let guard = self.access.load();
+        MapGuard {
+            guard,
+            projection: self.projection.get.clone(),
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<A, T, G, S, R> WriteAccess<R> for Map<A, T, Lens<G, S>>
+where
+    A: WriteAccess<T>,
+    G: Fn(&T) -> &R + Clone,
+    S: Fn(&T, R) -> T,
+{
+    fn rcu<F: FnMut(&R) -> R>(&self, mut f: F) {
+        let lens = &self.projection;
+        self.access
+            .rcu(|whole: &T| (lens.set)(whole, f((lens.get)(whole))));
+    }
+}
+
 #[doc(hidden)]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct ConstantDeref<T>(T);
@@ -388,6 +629,354 @@ impl<T: Clone> Access<T> for Constant<T> {
     }
 }
 
+/// Access to two values at once.
+///
+/// This loads both sources and provides a [`ZipGuard`] holding both of their guards, so the values
+/// are borrowed from them (nothing gets cloned).
+///
+/// Because an [`ArcSwap`][crate::ArcSwap] provides access both to the `Arc` and the value inside,
+/// the types of the values often need to be specified when loading.
+///
+/// ```rust
+/// use arc_swap::ArcSwap;
+/// use arc_swap::access::{Access, Zip};
+///
+/// fn address<A: Access<String>, B: Access<u16>>(a: &Zip<A, B>) -> String {
+///     let guard = a.load::<String, u16>();
+///     let (name, port) = guard.pair();
+///     format!("{}:{}", name, port)
+/// }
+///
+/// let name = ArcSwap::from_pointee("server".to_owned());
+/// let port = ArcSwap::from_pointee(80);
+/// let both = Zip::new(&name, &port);
+/// assert_eq!("server:80", address(&both));
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct Zip<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Zip<A, B> {
+    /// Creates a new instance from the two sources.
+    pub fn new(first: A, second: B) -> Self {
+        Zip { first, second }
+    }
+
+    /// Loads both values.
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    pub fn load<T, U>(&self) -> ZipGuard<A::Guard, B::Guard>
+    where
+        A: Access<T>,
+        B: Access<U>,
+    {
+        ZipGuard {
+            first: self.first.load(),
+            second: self.second.load(),
+        }
+    }
+}
+
+/// The guard of the [`Zip`], holding the guards of both the sources.
+#[derive(Debug)]
+pub struct ZipGuard<GA, GB> {
+    first: GA,
+    second: GB,
+}
+
+impl<GA: Deref, GB: Deref> ZipGuard<GA, GB> {
+    /// The value of the first source.
+    pub fn first(&self) -> &GA::Target {
+        &self.first
+    }
+
+    /// The value of the second source.
+    pub fn second(&self) -> &GB::Target {
+        &self.second
+    }
+
+    /// Both the values.
+    pub fn pair(&self) -> (&GA::Target, &GB::Target) {
+        (&self.first, &self.second)
+    }
+
+    /// Splits it into the guards of the two sources.
+    pub fn into_inner(self) -> (GA, GB) {
+        (self.first, self.second)
+    }
+}
+
+/// An [`Access`] made from a [`cache::Access`][crate::cache::Access].
+///
+/// This allows passing a cache (like [`Cache`][crate::Cache] or [`MapCache`]) to code expecting
+/// the [`Access`] trait.
+///
+/// As the cache is not thread safe, each thread that uses this gets its own clone of it, made on
+/// the first use. The clones are kept until this is dropped (even after their threads terminate)
+/// and finding the one of the current thread walks through them, so this is meant for a moderate
+/// number of threads.
+///
+/// The guard can't borrow from the cache, so it holds a clone of the value. It is therefore useful
+/// mostly for values that are cheap to clone, like the `Arc` itself.
+///
+/// For the opposite direction, see [`cache::FromAccess`][crate::cache::FromAccess].
+///
+/// Not available with the `custom-thread-local` feature. The storage provided there may be shared
+/// by several threads taking turns (like a per-CPU one), so it can't tell the threads apart.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use arc_swap::{ArcSwap, Cache};
+/// use arc_swap::access::{Access, FromCache};
+///
+/// fn get<A: Access<Arc<usize>>>(a: &A) -> usize {
+///     **a.load()
+/// }
+///
+/// let shared = ArcSwap::from_pointee(42);
+/// // The projection makes the cache provide the Arc, not the value inside.
+/// let cached = FromCache::new(Cache::new(&shared).map(|arc: &Arc<usize>| arc));
+/// assert_eq!(42, get(&cached));
+/// shared.store(Arc::new(24));
+/// assert_eq!(24, get(&cached));
+/// ```
+///
+/// [`MapCache`]: crate::cache::MapCache
+#[cfg(not(feature = "custom-thread-local"))]
+pub struct FromCache<C> {
+    /// The cache as passed in, the threads get clones of it.
+    prototype: C,
+    caches: PerThread<C>,
+}
+
+#[cfg(not(feature = "custom-thread-local"))]
+impl<C> FromCache<C> {
+    /// Wraps the cache.
+    pub fn new(cache: C) -> Self {
+        FromCache {
+            prototype: cache,
+            caches: PerThread::new(),
+        }
+    }
+
+    /// Provides the wrapped cache back.
+    ///
+    /// This is the cache as it was passed in, the clones used by the threads are dropped.
+    pub fn into_inner(self) -> C {
+        self.prototype
+    }
+}
+
+#[cfg(not(feature = "custom-thread-local"))]
+impl<C: Clone> Clone for FromCache<C> {
+    fn clone(&self) -> Self {
+        FromCache::new(self.prototype.clone())
+    }
+}
+
+#[cfg(not(feature = "custom-thread-local"))]
+impl<C: Debug> Debug for FromCache<C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_tuple("FromCache").field(&self.prototype).finish()
+    }
+}
+
+#[cfg(not(feature = "custom-thread-local"))]
+impl<C, T> Access<T> for FromCache<C>
+where
+    C: crate::cache::Access<T> + Clone,
+    T: Clone,
+{
+    type Guard = ConstantDeref<T>;
+    fn load(&self) -> Self::Guard {
+        let cache = self.caches.get_or(|| self.prototype.clone());
+        match cache.and_then(|cache| cache.try_borrow_mut().ok()) {
+            Some(mut cache) => ConstantDeref(cache.load().clone()),
+            // Either a thread without its local storage (like during its shutdown), or a load
+            // nested in the load of the same cache. Rare, so a one-off clone is good enough.
+            None => ConstantDeref(self.prototype.clone().load().clone()),
+        }
+    }
+}
+
+// The P is here only so the compiler knows it lives as long as the guard.
+#[doc(hidden)]
+pub enum FallbackGuard<GA, GB, P> {
+    Primary(GA, PhantomData<fn() -> P>),
+    Secondary(GB),
+}
+
+impl<GA, GB, P, T> Deref for FallbackGuard<GA, GB, P>
+where
+    GA: Deref<Target = Option<P>>,
+    P: Deref<Target = T>,
+    GB: Deref<Target = T>,
+{
+    type Target = T;
+    fn deref(&self) -> &T {
+        match self {
+            FallbackGuard::Primary(guard, _) => guard.as_ref().expect("Checked on load"),
+            FallbackGuard::Secondary(guard) => guard,
+        }
+    }
+}
+
+/// Access to an optional value, falling back to another source if it is not set.
+///
+/// The primary source is something like an [`ArcSwapOption`][crate::ArcSwapOption] ‒ anything
+/// providing an `Option` of a pointer (`P`, the `Arc` by default) to the value. If it holds a
+/// value, that one is used. If it is empty, the secondary source is used.
+///
+/// As the `Fallback` provides access to the inner value (not the `Option`), it can be used as the
+/// secondary source of another `Fallback`, forming a chain of layers.
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use arc_swap::{ArcSwap, ArcSwapOption};
+/// use arc_swap::access::{Access, Fallback};
+///
+/// let defaults = ArcSwap::from_pointee(1);
+/// let file = ArcSwapOption::empty();
+/// let overrides = ArcSwapOption::empty();
+/// let effective = Fallback::new(&overrides, Fallback::new(&file, &defaults));
+///
+/// assert_eq!(1, *effective.load());
+/// file.store(Some(Arc::new(2)));
+/// assert_eq!(2, *effective.load());
+/// overrides.store(Some(Arc::new(3)));
+/// assert_eq!(3, *effective.load());
+/// overrides.store(None);
+/// assert_eq!(2, *effective.load());
+/// ```
+#[derive(Debug)]
+pub struct Fallback<A, B, T, P = Arc<T>> {
+    primary: A,
+    secondary: B,
+    _t: PhantomData<fn() -> (T, P)>,
+}
+
+// Not derived, the T and P don't need to be Clone for this to be.
+impl<A: Clone, B: Clone, T, P> Clone for Fallback<A, B, T, P> {
+    fn clone(&self) -> Self {
+        Fallback {
+            primary: self.primary.clone(),
+            secondary: self.secondary.clone(),
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<A: Copy, B: Copy, T, P> Copy for Fallback<A, B, T, P> {}
+
+impl<A, B, T, P> Fallback<A, B, T, P> {
+    /// Creates a new instance from the primary and secondary source.
+    pub fn new(primary: A, secondary: B) -> Self
+    where
+        A: Access<Option<P>>,
+        P: Deref<Target = T>,
+        B: Access<T>,
+    {
+        Fallback {
+            primary,
+            secondary,
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<A, B, T, P> Access<T> for Fallback<A, B, T, P>
+where
+    A: Access<Option<P>>,
+    P: Deref<Target = T>,
+    B: Access<T>,
+{
+    type Guard = FallbackGuard<A::Guard, B::Guard, P>;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        let primary = self.primary.load();
+        if primary.is_some() {
+            FallbackGuard::Primary(primary, PhantomData)
+        } else {
+            FallbackGuard::Secondary(self.secondary.load())
+        }
+    }
+}
+
+/// Access to a value behind another level of indirection.
+///
+/// This is mostly for nested storages, like `ArcSwap<ArcSwap<T>>`. The outer one allows
+/// swapping the whole inner storage, while the inner one allows updating the value in it.
+///
+/// The projection selects the inner source from the outer value (it can be a field of a bigger
+/// structure, or just the identity for directly nested storages). This is the same as with
+/// [`Map`], except the projection points to something that provides the [`Access`], instead of
+/// the value.
+///
+/// Note that the guard holds onto only the inner value, not the whole outer structure.
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use arc_swap::ArcSwap;
+/// use arc_swap::access::{Access, FlatMap};
+///
+/// fn get<A: Access<usize>>(a: &A) -> usize {
+///     *a.load()
+/// }
+///
+/// let nested = ArcSwap::from_pointee(ArcSwap::from_pointee(1));
+/// let inner = FlatMap::new(&nested, |inner: &ArcSwap<usize>| inner);
+/// assert_eq!(1, get(&inner));
+///
+/// nested.load().store(Arc::new(2));
+/// assert_eq!(2, get(&inner));
+///
+/// nested.store(Arc::new(ArcSwap::from_pointee(3)));
+/// assert_eq!(3, get(&inner));
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct FlatMap<A, T, F> {
+    access: A,
+    projection: F,
+    _t: PhantomData<fn() -> T>,
+}
+
+impl<A, T, F> FlatMap<A, T, F> {
+    /// Creates a new instance.
+    ///
+    /// The `access` provides the outer value, the `projection` selects the inner source in it.
+    pub fn new<I>(access: A, projection: F) -> Self
+    where
+        F: Fn(&T) -> &I,
+    {
+        FlatMap {
+            access,
+            projection,
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<A, T, F, I, R> Access<R> for FlatMap<A, T, F>
+where
+    A: Access<T>,
+    F: Fn(&T) -> &I,
+    I: Access<R>,
+{
+    type Guard = I::Guard;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        let outer = self.access.load();
+        // The guard can't borrow from the inner source, so it is fine to let go of the outer one.
+        (self.projection)(&outer).load()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::{ArcSwap, ArcSwapOption};
@@ -496,6 +1085,179 @@ mod tests {
         assert_eq!(42, *Access::load(&map));
     }
 
+    #[derive(Clone, Debug, PartialEq)]
+    struct Outer {
+        name: &'static str,
+        inner: Cfg2,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Cfg2 {
+        value: usize,
+        other: usize,
+    }
+
+    fn inner_lens() -> Lens<impl Fn(&Outer) -> &Cfg2 + Clone, impl Fn(&Outer, Cfg2) -> Outer> {
+        Lens::new(
+            |outer: &Outer| &outer.inner,
+            |outer: &Outer, inner| Outer {
+                inner,
+                ..outer.clone()
+            },
+        )
+    }
+
+    /// Writing through a lens updates only the part in the whole structure.
+    #[test]
+    fn lens_write() {
+        let a = ArcSwap::from_pointee(Outer {
+            name: "outer",
+            inner: Cfg2 { value: 1, other: 2 },
+        });
+        let inner = Map::with_lens(&a, inner_lens());
+        inner.rcu(|inner| Cfg2 {
+            value: inner.value + 1,
+            ..inner.clone()
+        });
+        assert_eq!(2, Access::load(&inner).value);
+        assert_eq!(
+            Outer {
+                name: "outer",
+                inner: Cfg2 { value: 2, other: 2 },
+            },
+            **a.load()
+        );
+    }
+
+    /// Lenses can be stacked on top of each other.
+    #[test]
+    fn lens_nested() {
+        let a = ArcSwap::from_pointee(Outer {
+            name: "outer",
+            inner: Cfg2 { value: 1, other: 2 },
+        });
+        let inner = Map::with_lens(&a, inner_lens());
+        let value = Map::with_lens(
+            &inner,
+            Lens::new(
+                |inner: &Cfg2| &inner.value,
+                |inner: &Cfg2, value| Cfg2 {
+                    value,
+                    ..inner.clone()
+                },
+            ),
+        );
+        assert_eq!(1, *Access::load(&value));
+        value.rcu(|v| v + 41);
+        check_static_dispatch_direct(&value);
+        check_dyn_dispatch_direct(&value);
+        assert_eq!(42, a.load_full().inner.value);
+        assert_eq!(2, a.load_full().inner.other);
+    }
+
+    #[test]
+    fn zip() {
+        let a = ArcSwap::from_pointee(1);
+        let b = ArcSwapOption::from_pointee(2);
+        let zip = Zip::new(&a, &b);
+        a.store(Arc::new(3));
+        let guard = zip.load::<usize, Option<Arc<usize>>>();
+        let (first, second) = guard.pair();
+        assert_eq!(3, *first);
+        assert_eq!(2, **second.as_ref().unwrap());
+        // The values are borrowed from the guards of the sources, not cloned.
+        assert!(core::ptr::eq(first, &**a.load()));
+        let (first_guard, _) = guard.into_inner();
+        assert_eq!(3, *first_guard);
+        // Other access types can be zipped too.
+        let zip = Zip::new(Constant(1), Map::new(&a, |v: &usize| v));
+        assert_eq!((&1, &3), zip.load::<usize, usize>().pair());
+    }
+
+    /// The fallback switches between the sources as the primary gets set and unset.
+    #[test]
+    fn fallback() {
+        let primary = ArcSwapOption::empty();
+        let secondary = ArcSwap::from_pointee(42);
+        let fallback = Fallback::new(&primary, &secondary);
+        check_static_dispatch_direct(fallback);
+        primary.store(Some(Arc::new(1)));
+        assert_eq!(1, *Access::load(&fallback));
+        primary.store(None);
+        check_dyn_dispatch_direct(&fallback);
+    }
+
+    /// The primary source can hold other pointers than the `Arc`.
+    #[test]
+    fn fallback_rc() {
+        let primary = ArcSwapAny::<Option<Rc<usize>>>::new(None);
+        let secondary = ArcSwap::from_pointee(42);
+        let fallback = Fallback::new(&primary, &secondary);
+        check_static_dispatch_direct(fallback);
+        primary.store(Some(Rc::new(1)));
+        assert_eq!(1, *Access::load(&fallback));
+    }
+
+    #[test]
+    fn flat_map() {
+        let nested = ArcSwap::from_pointee(ArcSwap::from_pointee(0));
+        let inner = FlatMap::new(&nested, |inner: &ArcSwap<usize>| inner);
+        nested.load().store(Arc::new(42));
+        check_static_dispatch(&inner);
+        nested.store(Arc::new(ArcSwap::from_pointee(42)));
+        check_static_dispatch_direct(&inner);
+    }
+
+    /// Projecting into a field holding the inner storage.
+    #[test]
+    fn flat_map_field() {
+        struct Outer {
+            inner: ArcSwap<usize>,
+        }
+
+        let outer = ArcSwap::from_pointee(Outer {
+            inner: ArcSwap::from_pointee(42),
+        });
+        let inner = FlatMap::new(&outer, |outer: &Outer| &outer.inner);
+        check_static_dispatch_direct(&inner);
+        check_dyn_dispatch(&inner);
+    }
+
+    #[cfg(not(feature = "custom-thread-local"))]
+    #[test]
+    fn from_cache() {
+        let a = ArcSwap::from_pointee(Cfg { value: 42 });
+        let cached = FromCache::new(crate::Cache::new(&a).map(|cfg: &Arc<Cfg>| &cfg.value));
+        check_static_dispatch_direct(&cached);
+        check_dyn_dispatch_direct(&cached);
+        a.store(Arc::new(Cfg { value: 0 }));
+        assert_eq!(0, *Access::load(&cached));
+    }
+
+    /// The FromCache can be shared between threads, each gets its own copy of the cache.
+    #[cfg(not(feature = "custom-thread-local"))]
+    #[test]
+    fn from_cache_threads() {
+        extern crate std;
+
+        let a = ArcSwap::from_pointee(42);
+        let cached = FromCache::new(crate::Cache::new(&a));
+        let load = || *Access::<usize>::load(&cached);
+        assert_eq!(42, load());
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let before = load();
+                    a.rcu(|v| **v + 1);
+                    assert!(load() > before);
+                });
+            }
+        });
+        assert_eq!(46, load());
+        let cache = cached.into_inner();
+        assert_eq!(46, **cache.arc_swap().load());
+    }
+
     // Compile tests for dynamic access
     fn _expect_access<T>(_: impl Access<T>) {}
 
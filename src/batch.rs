@@ -0,0 +1,193 @@
+//! Storing into multiple values at once.
+//!
+//! Each [`store`][ArcSwapAny::store] needs to make sure no reader still uses the old value. It
+//! does so by walking through the bookkeeping data of all the threads that ever used the library.
+//! That's fine for an occasional update, but if a burst of them is done (for example when reloading
+//! a configuration split into dozens of [`ArcSwap`][crate::ArcSwap]s), it adds up.
+//!
+//! The [`batch`] function allows storing into any number of them and does the walk only once, at
+//! the end, for all of them together. The new values are visible to the readers right away, only
+//! the old values are kept alive until the batch ends.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::sync::Arc;
+//!
+//! use arc_swap::ArcSwap;
+//!
+//! let name = ArcSwap::from_pointee("old".to_owned());
+//! let port = ArcSwap::from_pointee(80);
+//!
+//! arc_swap::batch(|b| {
+//!     b.store(&name, Arc::new("new".to_owned()));
+//!     b.store(&port, Arc::new(8080));
+//! });
+//!
+//! assert_eq!("new", **name.load());
+//! assert_eq!(8080, **port.load());
+//! ```
+
+use core::sync::atomic::Ordering;
+
+use crate::debt::Retirements;
+use crate::ref_cnt::RefCnt;
+use crate::strategy::Strategy;
+use crate::ArcSwapAny;
+
+/// A batch of stores.
+///
+/// This is available only inside the [`batch`] closure. See the [module
+/// documentation](index.html).
+pub struct Batch<'a> {
+    retirements: Retirements<'a>,
+}
+
+impl<'a> Batch<'a> {
+    /// Replaces the value inside the storage.
+    ///
+    /// The new value is visible to further loads right away, but the old one is released only at
+    /// the end of the batch.
+    ///
+    /// Storing into the same storage multiple times within the batch is fine, each of the
+    /// replaced values is held until the end.
+    ///
+    /// Strategies that don't support batching (the lock based ones, for example) simply do a
+    /// normal [`store`][ArcSwapAny::store].
+    pub fn store<T: RefCnt, S: Strategy<T>>(&mut self, storage: &'a ArcSwapAny<T, S>, val: T) {
+        let new = unsafe { storage.strategy.enter_storage(T::into_ptr(val)) };
+        // Same as in ArcSwapAny::swap.
+        let old = storage.ptr.swap(new, Ordering::SeqCst);
+        crate::notify_waiters(&storage.ptr);
+        unsafe {
+            let old = T::from_ptr(storage.strategy.leave_storage(old));
+            storage
+                .strategy
+                .retire(old, &storage.ptr, &mut self.retirements);
+        }
+    }
+}
+
+impl Drop for Batch<'_> {
+    fn drop(&mut self) {
+        // Also on panic, the old values must not go away while readers still use them.
+        self.retirements.pay();
+    }
+}
+
+/// Runs the closure with a [`Batch`] to do multiple stores in bulk.
+///
+/// Once the closure finishes, the old values are released.
+///
+/// Doing other operations (even the usual [`store`][ArcSwapAny::store]) inside the closure is
+/// fine, but it doesn't take part in the batching.
+///
+/// See the [module documentation](index.html) for an example.
+pub fn batch<'a, R, F: FnOnce(&mut Batch<'a>) -> R>(f: F) -> R {
+    let mut batch = Batch {
+        retirements: Retirements::default(),
+    };
+    f(&mut batch)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use alloc::sync::Arc;
+
+    use super::*;
+    use crate::{ArcSwap, ArcSwapOption};
+
+    #[test]
+    fn store_multiple() {
+        let a = ArcSwap::from_pointee(1);
+        let b = ArcSwapOption::from_pointee(2);
+        let old_a = a.load_full();
+        let guard = b.load();
+        batch(|batch| {
+            batch.store(&a, Arc::new(3));
+            batch.store(&b, None);
+            // Visible right away, but the old ones are not released yet.
+            assert_eq!(3, **a.load());
+            assert!(b.load().is_none());
+            assert!(Arc::strong_count(&old_a) > 1);
+        });
+        assert_eq!(1, Arc::strong_count(&old_a));
+        assert_eq!(2, **guard.as_ref().unwrap());
+        drop(guard);
+    }
+
+    /// The guards get their debts paid by the batch, so they don't rely on the value held by it.
+    #[test]
+    fn pays_guards() {
+        let shared = ArcSwap::from_pointee(0);
+        let old = shared.load_full();
+        let guards = (0..20)
+            .map(|_| shared.load())
+            .collect::<alloc::vec::Vec<_>>();
+        batch(|batch| {
+            batch.store(&shared, Arc::new(1));
+            batch.store(&shared, Arc::new(2));
+        });
+        assert!(guards.iter().all(|g| ***g == 0));
+        assert_eq!(1 + guards.len(), Arc::strong_count(&old));
+        drop(guards);
+        assert_eq!(1, Arc::strong_count(&old));
+        assert_eq!(2, **shared.load());
+    }
+
+    /// Many storages, some of them sharing the same value, with guards on some of them.
+    #[test]
+    fn pays_many() {
+        let shared = Arc::new(0);
+        let storages = (0..50)
+            .map(|i| {
+                if i % 2 == 0 {
+                    ArcSwap::new(Arc::clone(&shared))
+                } else {
+                    ArcSwap::from_pointee(i)
+                }
+            })
+            .collect::<alloc::vec::Vec<_>>();
+        let guards = storages
+            .iter()
+            .step_by(3)
+            .map(|s| s.load())
+            .collect::<alloc::vec::Vec<_>>();
+        let expected = guards.iter().map(|g| ***g).collect::<alloc::vec::Vec<_>>();
+        batch(|batch| {
+            for (i, storage) in storages.iter().enumerate() {
+                batch.store(storage, Arc::new(i + 100));
+            }
+        });
+        let values = guards.iter().map(|g| ***g).collect::<alloc::vec::Vec<_>>();
+        assert_eq!(expected, values);
+        let shared_guards = guards.iter().filter(|g| Arc::ptr_eq(g, &shared)).count();
+        assert_eq!(1 + shared_guards, Arc::strong_count(&shared));
+        drop(guards);
+        assert_eq!(1, Arc::strong_count(&shared));
+        assert!(storages
+            .iter()
+            .enumerate()
+            .all(|(i, s)| **s.load() == i + 100));
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+    fn panic_inside() {
+        use std::panic;
+
+        let shared = ArcSwap::from_pointee(0);
+        let old = shared.load_full();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            batch(|batch| {
+                batch.store(&shared, Arc::new(1));
+                panic!("Oops");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(1, Arc::strong_count(&old));
+        assert_eq!(1, **shared.load());
+    }
+}
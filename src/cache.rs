@@ -9,6 +9,7 @@
 use core::ops::Deref;
 use core::sync::atomic::Ordering;
 
+use super::access;
 use super::ref_cnt::RefCnt;
 use super::strategy::Strategy;
 use super::ArcSwapAny;
@@ -162,6 +163,7 @@ where
         // it synchronized in self.cache. We just want to check if it changed, if it did, the
         // load_full will be responsible for any synchronization needed.
         let shared_ptr = self.arc_swap.ptr.load(Ordering::Relaxed);
+        let shared_ptr = self.arc_swap.strategy.peek_storage(shared_ptr);
         if cached_ptr != shared_ptr {
             self.cached = self.arc_swap.load_full();
         }
@@ -270,6 +272,75 @@ where
     }
 }
 
+/// A cache [`Access`] made from an [`access::Access`].
+///
+/// This allows passing anything implementing the other [`Access`][crate::access::Access] trait
+/// (like [`access::Map`] or [`Layered`][crate::layered::Layered]) to code
+/// expecting the cache one.
+///
+/// Note that this doesn't cache anything by itself. Each [`load`][Access::load] loads the value
+/// anew, this only keeps the guard of the last one around, to be able to provide the reference.
+/// Therefore, the limitations about holding guards for too long apply.
+///
+/// For the opposite direction, see [`access::FromCache`].
+///
+/// # Examples
+///
+/// ```rust
+/// use arc_swap::ArcSwap;
+/// use arc_swap::access::Map;
+/// use arc_swap::cache::{Access, FromAccess};
+///
+/// struct Cfg {
+///     value: usize,
+/// }
+///
+/// fn use_value<A: Access<usize>>(cache: &mut A) -> usize {
+///     *cache.load()
+/// }
+///
+/// let cfg = ArcSwap::from_pointee(Cfg { value: 42 });
+/// let mut value = FromAccess::new(Map::new(&cfg, |cfg: &Cfg| &cfg.value));
+/// assert_eq!(42, use_value(&mut value));
+/// ```
+pub struct FromAccess<A, T>
+where
+    A: access::Access<T>,
+{
+    access: A,
+    guard: Option<A::Guard>,
+}
+
+impl<A, T> FromAccess<A, T>
+where
+    A: access::Access<T>,
+{
+    /// Wraps the access.
+    pub fn new(access: A) -> Self {
+        FromAccess {
+            access,
+            guard: None,
+        }
+    }
+
+    /// Provides the wrapped access back.
+    pub fn into_inner(self) -> A {
+        self.access
+    }
+}
+
+impl<A, T> Access<T> for FromAccess<A, T>
+where
+    A: access::Access<T>,
+{
+    fn load(&mut self) -> &T {
+        // Release the old one first, it may free a slot for the new one.
+        self.guard = None;
+        let access = &self.access;
+        self.guard.get_or_insert_with(|| access.load())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use alloc::sync::Arc;
@@ -318,6 +389,28 @@ mod tests {
         inner: Inner,
     }
 
+    fn use_answer<A: Access<usize>>(cache: &mut A) -> usize {
+        *cache.load()
+    }
+
+    /// The adaptor from the other Access trait sees the updates.
+    #[test]
+    fn from_access() {
+        let a = ArcSwap::from_pointee(Outer {
+            inner: Inner { answer: 42 },
+        });
+        let mut cache = Cache::new(&a).map(|outer: &Arc<Outer>| &outer.inner.answer);
+        let mut adapted = FromAccess::new(a.map(|outer: &Outer| &outer.inner.answer));
+        assert_eq!(42, use_answer(&mut cache));
+        assert_eq!(42, use_answer(&mut adapted));
+
+        a.store(Arc::new(Outer {
+            inner: Inner { answer: 24 },
+        }));
+        assert_eq!(24, use_answer(&mut cache));
+        assert_eq!(24, use_answer(&mut adapted));
+    }
+
     #[test]
     fn map_cache() {
         let a = ArcSwap::from_pointee(Outer {
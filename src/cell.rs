@@ -0,0 +1,314 @@
+//! Single-threaded storages without any atomics.
+//!
+//! The [`ArcSwapAny`][crate::ArcSwapAny] can hold an [`Rc`], but it brings little because it
+//! still pays for the atomic operations and the thread-local bookkeeping even though the value
+//! can't leave the thread anyway. The [`CellSwap`] has the same API (so the code written against
+//! the [`Access`][access::Access] traits works with both), but it is only a cell with the value
+//! inside. Loading just increments the reference count.
+//!
+//! It uses no atomics and no thread-local storage, so it is usable in single-threaded contexts
+//! (and on targets without them, like some wasm ones).
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arc_swap::cell::RcSwap;
+//!
+//! let config = RcSwap::from_pointee(42);
+//! assert_eq!(42, **config.load());
+//! config.rcu(|c| **c + 1);
+//! assert_eq!(43, *config.load_full());
+//! ```
+
+use core::cell::UnsafeCell;
+use core::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use core::mem;
+use core::ops::Deref;
+
+use crate::access::{self, Map};
+use crate::as_raw::AsRaw;
+use crate::cache;
+use crate::imports::{Arc, Rc};
+use crate::ref_cnt::RefCnt;
+
+/// A single-threaded storage for a reference counted smart pointer.
+///
+/// This mirrors the API of the [`ArcSwapAny`][crate::ArcSwapAny], but without any atomics or
+/// thread-local data. It is never [`Sync`], so it can't be shared between threads. Like a
+/// [`Cell`][core::cell::Cell], it is [`Send`] if the pointer inside is (the [`RcSwap`] is not, a
+/// `CellSwap<Arc<T>>` can be moved to another thread). See the [module
+/// documentation](index.html).
+pub struct CellSwap<T: RefCnt> {
+    // Invariant: no reference into it lives outside of our methods, except through &mut self.
+    val: UnsafeCell<T>,
+}
+
+/// A single-threaded storage for an [`Rc`].
+pub type RcSwap<T> = CellSwap<Rc<T>>;
+
+/// A single-threaded storage for an optional [`Rc`].
+pub type RcSwapOption<T> = CellSwap<Option<Rc<T>>>;
+
+/// A loaded value from the [`CellSwap`].
+///
+/// It holds a full reference (the load already incremented the reference count). It exists to
+/// mirror the [`Guard`][crate::Guard] of the [`ArcSwapAny`][crate::ArcSwapAny].
+#[derive(Clone, Debug, Default)]
+pub struct CellGuard<T>(T);
+
+impl<T> CellGuard<T> {
+    /// Converts it into the held value.
+    pub fn into_inner(guard: Self) -> T {
+        guard.0
+    }
+}
+
+impl<T> Deref for CellGuard<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Display> Display for CellGuard<T> {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        self.0.fmt(formatter)
+    }
+}
+
+impl<T: RefCnt> CellSwap<T> {
+    /// Constructs a new storage.
+    pub fn new(val: T) -> Self {
+        CellSwap {
+            val: UnsafeCell::new(val),
+        }
+    }
+
+    /// Extracts the value inside.
+    pub fn into_inner(self) -> T {
+        self.val.into_inner()
+    }
+
+    /// Loads the value.
+    ///
+    /// This only increments the reference count.
+    #[inline]
+    pub fn load(&self) -> CellGuard<T> {
+        CellGuard(self.load_full())
+    }
+
+    /// Loads the value as the smart pointer itself.
+    #[inline]
+    pub fn load_full(&self) -> T {
+        // The reference doesn't outlive this line and no one can change the value in the
+        // meantime, we are single-threaded and the clone doesn't call back into us.
+        let val = unsafe { &*self.val.get() };
+        T::clone(val)
+    }
+
+    /// Replaces the value.
+    pub fn store(&self, val: T) {
+        drop(self.swap(val));
+    }
+
+    /// Exchanges the value.
+    pub fn swap(&self, new: T) -> T {
+        // The old value is returned and dropped only after we stop touching the cell, so its
+        // destructor can access us.
+        mem::replace(unsafe { &mut *self.val.get() }, new)
+    }
+
+    /// Swaps the value if it is the `current` one.
+    ///
+    /// Returns the previous value. If it is equal to the `current`, the swap happened. See
+    /// [`ArcSwapAny::compare_and_swap`][crate::ArcSwapAny::compare_and_swap].
+    pub fn compare_and_swap<C>(&self, current: C, new: T) -> CellGuard<T>
+    where
+        C: AsRaw<T::Base>,
+    {
+        let previous = self.load_full();
+        if T::as_ptr(&previous) == current.as_raw() {
+            drop(self.swap(new));
+        }
+        CellGuard(previous)
+    }
+
+    /// Read-Copy-Update of the value.
+    ///
+    /// Unlike the [`ArcSwapAny::rcu`][crate::ArcSwapAny::rcu], the closure is called exactly
+    /// once (unless it changes the storage itself). Returns the previous value.
+    pub fn rcu<R, F>(&self, mut f: F) -> T
+    where
+        F: FnMut(&T) -> R,
+        R: Into<T>,
+    {
+        let mut cur = self.load();
+        loop {
+            let new = f(&cur).into();
+            let prev = self.compare_and_swap(&*cur, new);
+            if T::as_ptr(&cur) == T::as_ptr(&prev) {
+                return CellGuard::into_inner(prev);
+            }
+            cur = prev;
+        }
+    }
+
+    // UnsafeCell::get_mut needs a newer compiler.
+    fn get_mut(&mut self) -> &mut T {
+        // The exclusive borrow of self makes sure no one else looks into the cell.
+        unsafe { &mut *self.val.get() }
+    }
+
+    /// Provides an access to an up to date projection of the carried data.
+    ///
+    /// See [`ArcSwapAny::map`][crate::ArcSwapAny::map].
+    pub fn map<I, R, F>(&self, f: F) -> Map<&Self, I, F>
+    where
+        F: Fn(&I) -> &R + Clone,
+        Self: access::Access<I>,
+    {
+        Map::new(self, f)
+    }
+}
+
+impl<T> CellSwap<Rc<T>> {
+    /// A convenience constructor directly from the pointed-to value.
+    pub fn from_pointee(val: T) -> Self {
+        Self::new(Rc::new(val))
+    }
+}
+
+impl<T> CellSwap<Option<Rc<T>>> {
+    /// A convenience constructor directly from a pointed-to value.
+    pub fn from_pointee<V: Into<Option<T>>>(val: V) -> Self {
+        Self::new(val.into().map(Rc::new))
+    }
+
+    /// A convenience constructor for an empty value.
+    pub fn empty() -> Self {
+        Self::new(None)
+    }
+}
+
+impl<T: RefCnt> From<T> for CellSwap<T> {
+    fn from(val: T) -> Self {
+        Self::new(val)
+    }
+}
+
+impl<T: RefCnt + Default> Default for CellSwap<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: RefCnt + Debug> Debug for CellSwap<T> {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        formatter
+            .debug_tuple("CellSwap")
+            .field(&self.load_full())
+            .finish()
+    }
+}
+
+impl<T: RefCnt + Display> Display for CellSwap<T> {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        self.load_full().fmt(formatter)
+    }
+}
+
+impl<T: RefCnt> access::Access<T> for CellSwap<T> {
+    type Guard = CellGuard<T>;
+    fn load(&self) -> Self::Guard {
+        self.load()
+    }
+}
+
+impl<T> access::Access<T> for CellSwap<Rc<T>> {
+    type Guard = Rc<T>;
+    fn load(&self) -> Self::Guard {
+        self.load_full()
+    }
+}
+
+impl<T> access::Access<T> for CellSwap<Arc<T>> {
+    type Guard = Arc<T>;
+    fn load(&self) -> Self::Guard {
+        self.load_full()
+    }
+}
+
+impl<T: RefCnt> access::WriteAccess<T> for CellSwap<T> {
+    fn rcu<F: FnMut(&T) -> T>(&self, f: F) {
+        CellSwap::rcu(self, f);
+    }
+}
+
+impl<T> access::WriteAccess<T> for CellSwap<Rc<T>> {
+    fn rcu<F: FnMut(&T) -> T>(&self, mut f: F) {
+        CellSwap::rcu(self, |cur: &Rc<T>| Rc::new(f(cur)));
+    }
+}
+
+impl<T: RefCnt> cache::Access<T> for CellSwap<T> {
+    /// No caching needed, the exclusive borrow makes sure the value can't change.
+    fn load(&mut self) -> &T {
+        self.get_mut()
+    }
+}
+
+impl<T> cache::Access<T> for CellSwap<Rc<T>> {
+    fn load(&mut self) -> &T {
+        self.get_mut()
+    }
+}
+
+impl<T> cache::Access<T> for CellSwap<Arc<T>> {
+    fn load(&mut self) -> &T {
+        self.get_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access_value<A: access::Access<usize>>(a: &A) -> usize {
+        *a.load()
+    }
+
+    fn cache_value<A: cache::Access<usize>>(a: &mut A) -> usize {
+        *a.load()
+    }
+
+    #[test]
+    fn operations() {
+        let first = Rc::new(1);
+        let shared = RcSwap::new(Rc::clone(&first));
+        assert_eq!(1, **shared.load());
+        let swapped = shared.compare_and_swap(&first, Rc::new(2));
+        assert!(Rc::ptr_eq(&first, &swapped));
+        drop(swapped);
+        let previous = shared.compare_and_swap(&first, Rc::new(3));
+        assert_eq!(2, **previous);
+        assert_eq!(2, *shared.swap(Rc::new(4)));
+        assert_eq!(4, *shared.rcu(|v| **v + 1));
+        assert_eq!(5, *shared.into_inner());
+        drop(previous);
+        assert_eq!(1, Rc::strong_count(&first));
+    }
+
+    #[test]
+    fn accesses() {
+        let mut shared = RcSwap::from_pointee(42);
+        assert_eq!(42, access_value(&shared));
+        assert_eq!(42, cache_value(&mut shared));
+        access::WriteAccess::rcu(&shared, |v: &usize| v + 1);
+        let mapped = shared.map(|v: &usize| v);
+        assert_eq!(43, access_value(&mapped));
+
+        let mut option = RcSwapOption::<usize>::empty();
+        assert!(cache::Access::<Option<Rc<usize>>>::load(&mut option).is_none());
+    }
+}
@@ -91,3 +91,39 @@
 //! let a: ArcSwapAny<Rc<usize>> = ArcSwapAny::new(Rc::new(42));
 //! std::thread::spawn(move || drop(a));
 //! ```
+//!
+//! The guards of a [`with_local`][crate::with_local] scope can't leave it.
+//! ```rust,compile_fail
+//! let shared = arc_swap::ArcSwap::from_pointee(42);
+//! let guard = arc_swap::with_local(|scope| scope.load(&shared));
+//! ```
+//!
+//! ```rust
+//! use arc_swap::scope::LocalGuard;
+//!
+//! let shared = arc_swap::ArcSwap::from_pointee(42);
+//! let full = arc_swap::with_local(|scope| LocalGuard::into_inner(scope.load(&shared)));
+//! assert_eq!(42, *full);
+//! ```
+//!
+//! The [`CellSwap`][crate::cell::CellSwap] can be sent to another thread (if the pointer can), but
+//! not shared.
+//! ```rust
+//! use std::sync::Arc;
+//! use arc_swap::cell::CellSwap;
+//!
+//! let a = CellSwap::new(Arc::new(42));
+//! std::thread::spawn(move || drop(a)).join().unwrap();
+//! ```
+//!
+//! ```rust,compile_fail
+//! use std::sync::Arc;
+//! use arc_swap::cell::CellSwap;
+//!
+//! let a = CellSwap::new(Arc::new(42));
+//! crossbeam_utils::thread::scope(|scope| {
+//!     scope.spawn(|_| {
+//!         let _ = &a;
+//!     });
+//! }).unwrap();
+//! ```
@@ -16,7 +16,7 @@
 
 use core::cell::Cell;
 use core::slice::Iter;
-use core::sync::atomic::Ordering::*;
+use core::sync::atomic::Ordering::{self, *};
 
 use super::Debt;
 
@@ -31,6 +31,14 @@ pub(super) struct Local {
     offset: Cell<usize>,
 }
 
+impl Local {
+    pub(super) const fn new() -> Self {
+        Local {
+            offset: Cell::new(0),
+        }
+    }
+}
+
 /// Bunch of fast debt slots.
 #[derive(Default)]
 pub(super) struct Slots([Debt; DEBT_SLOT_CNT]);
@@ -40,7 +48,7 @@ impl Slots {
     ///
     /// Fails if there are no free slots.
     #[inline]
-    pub(super) fn get_debt(&self, ptr: usize, local: &Local) -> Option<&Debt> {
+    pub(super) fn get_debt(&self, ptr: usize, local: &Local, order: Ordering) -> Option<&Debt> {
         // Trick with offsets: we rotate through the slots (save the value from last time)
         // so successive leases are likely to succeed on the first attempt (or soon after)
         // instead of going through the list of already held ones.
@@ -54,8 +62,9 @@ impl Slots {
             if slot.0.load(Relaxed) == Debt::NONE {
                 // We are allowed to split into the check and acquiring the debt. That's because we
                 // are the only ones allowed to change NONE to something else. But we still need a
-                // read-write operation with SeqCst on it :-(
-                let old = slot.0.swap(ptr, SeqCst);
+                // read-write operation with SeqCst on it :-( (unless the caller takes care of the
+                // ordering in some other way)
+                let old = slot.0.swap(ptr, order);
                 debug_assert_eq!(Debt::NONE, old);
                 local.offset.set(i + 1);
                 return Some(&self.0[i]);
@@ -109,9 +109,9 @@
 use core::cell::Cell;
 use core::ptr;
 use core::sync::atomic::Ordering::*;
-use core::sync::atomic::{AtomicPtr, AtomicUsize};
 
 use super::Debt;
+use crate::sync::{self, AtomicPtr, AtomicUsize};
 use crate::RefCnt;
 
 pub const REPLACEMENT_TAG: usize = 0b01;
@@ -126,6 +126,28 @@ pub(super) struct Local {
     generation: Cell<usize>,
 }
 
+impl Local {
+    pub(super) const fn new() -> Self {
+        Local {
+            generation: Cell::new(0),
+        }
+    }
+
+    /// Will the next generation wrap around?
+    ///
+    /// In that case, the node needs to be sent to cooldown before it is used (see the ABA
+    /// protection above).
+    pub(super) fn wraps(&self) -> bool {
+        self.generation.get().wrapping_add(4) == 0
+    }
+
+    /// Jumps right before the wrap around, to test it.
+    #[cfg(all(test, loom))]
+    pub(super) fn before_wrap(&self) {
+        self.generation.set(0usize.wrapping_sub(4));
+    }
+}
+
 // Make sure the pointers have 2 empty bits. Always.
 #[derive(Default)]
 #[repr(align(4))]
@@ -188,13 +210,11 @@ impl Slots {
         &self.slot
     }
 
-    pub(super) fn get_debt(&self, ptr: usize, local: &Local) -> (usize, bool) {
+    pub(super) fn get_debt(&self, ptr: usize, local: &Local) -> usize {
         // Incrementing by 4 ensures we always have enough space for 2 bit of tags.
         let gen = local.generation.get().wrapping_add(4);
         debug_assert_eq!(gen & GEN_TAG, 0);
         local.generation.set(gen);
-        // Signal the caller that the node should be sent to a cooldown.
-        let discard = gen == 0;
         let gen = gen | GEN_TAG;
         // We will sync by the write to the control. But we also sync the value of the previous
         // generation/released slot. That way we may re-confirm in the writer that the reader is
@@ -209,7 +229,28 @@ impl Slots {
         let prev = self.control.swap(gen, SeqCst);
         debug_assert_eq!(IDLE, prev, "Left control in wrong state");
 
-        (gen, discard)
+        gen
+    }
+
+    /// The address of the storage a reader is loading from right now, if any.
+    ///
+    /// This lets a writer with many storages find the one to [`help`][Slots::help] with, instead
+    /// of trying all of them. The address is confirmed by the control staying the same around
+    /// it, the same way `help` checks it.
+    pub(super) fn active_addr(&self) -> Option<usize> {
+        let mut control = self.control.load(SeqCst);
+        loop {
+            if control & TAG_MASK != GEN_TAG {
+                // Either idle or already helped, nothing to do.
+                return None;
+            }
+            let active_addr = self.active_addr.load(SeqCst);
+            let new_control = self.control.load(SeqCst);
+            if new_control == control {
+                return Some(active_addr);
+            }
+            control = new_control;
+        }
     }
 
     pub(super) fn help<R, T>(&self, who: &Self, storage_addr: usize, replacement: &R)
@@ -302,7 +343,8 @@ impl Slots {
     }
 
     pub(super) fn init(&mut self) {
-        *self.space_offer.get_mut() = &mut self.handover;
+        let handover: *mut Handover = &mut self.handover;
+        sync::ptr_set_mut(&mut self.space_offer, handover);
     }
 
     pub(super) fn confirm(&self, gen: usize, ptr: usize) -> Result<(), usize> {
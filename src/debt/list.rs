@@ -25,12 +25,33 @@
 //! We also do release-acquire "send" from the start-cooldown to check-cooldown to make sure we see
 //! at least as up to date value of the writers as when the cooldown started. That we if we see 0,
 //! we know it must have happened since then.
+//!
+//! # Debt filter
+//!
+//! Each node has a tiny bloom filter (one bit per hash of an address) of what it may owe: the
+//! pointers in the fast slots and the storages it is doing a helping load from. The writers skip
+//! the nodes that can't have a debt relevant to them, so a thread that holds nothing of theirs
+//! costs them one load. The storage address is not enough for the fast slots ‒ the storage can be
+//! moved while the guards live.
+//!
+//! The reader sets the bit with a SeqCst read-write operation before it puts the debt in. That's
+//! ordered before its confirmation load of the pointer. The writer loads the filter with SeqCst
+//! after it changed the pointer. If it doesn't see the bit, the reader set it only after the change
+//! and it'll see the new pointer in the confirmation. If the bit is already set from before (by
+//! this thread or by a previous owner of the node, which happens-before us through the claim of
+//! the node), we don't set it again ‒ but then it was also set before the change. With the
+//! asymmetric fences, the heavy fence of the writer orders it the same way as the debt itself.
+//!
+//! Only the owner changes the filter. Setting a bit is a read-write operation, so the bits only
+//! accumulate ‒ a thread going through many pointers soon has all of them set and stops paying for
+//! it. Once in a [`FILTER_RESET_PERIOD`] marks, if the node has no debts at all, it replaces the
+//! whole filter instead of adding to it. Nobody can owe anything through the old bits then, so the
+//! filter doesn't stay full forever.
 
 use core::cell::Cell;
 use core::ptr;
 use core::slice::Iter;
-use core::sync::atomic::Ordering::*;
-use core::sync::atomic::{AtomicPtr, AtomicUsize};
+use core::sync::atomic::Ordering::{self, *};
 
 #[cfg(feature = "experimental-thread-local")]
 use core::cell::OnceCell;
@@ -40,14 +61,86 @@ use crate::imports::Box;
 use super::fast::{Local as FastLocal, Slots as FastSlots};
 use super::helping::{Local as HelpingLocal, Slots as HelpingSlots};
 use super::Debt;
+use crate::sync::{AtomicPtr, AtomicUsize};
 use crate::RefCnt;
 
 const NODE_UNUSED: usize = 0;
 const NODE_USED: usize = 1;
 const NODE_COOLDOWN: usize = 2;
 
-/// The head of the debt linked list.
-static LIST_HEAD: AtomicPtr<Node> = AtomicPtr::new(ptr::null_mut());
+/// The thread id of a [`LocalNode`] not assigned yet.
+#[cfg(not(feature = "custom-thread-local"))]
+const NO_THREAD_ID: usize = 0;
+/// The thread id of the temporary [`LocalNode`]s, which don't belong to any thread.
+#[cfg(not(feature = "custom-thread-local"))]
+const TMP_THREAD_ID: usize = !0;
+
+/// The next thread id to hand out.
+#[cfg(all(not(feature = "custom-thread-local"), not(loom)))]
+static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(NO_THREAD_ID + 1);
+
+#[cfg(all(not(feature = "custom-thread-local"), loom))]
+loom::lazy_static! {
+    /// The next thread id to hand out.
+    static ref NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(NO_THREAD_ID + 1);
+}
+
+/// Number of the linked lists the nodes are grouped into (see the numa module).
+#[cfg(not(all(feature = "numa", not(loom))))]
+const GROUPS: usize = 1;
+#[cfg(all(feature = "numa", not(loom)))]
+use crate::numa::GROUPS;
+
+// Only as the initial value of the list heads, never used directly.
+#[cfg(not(loom))]
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_NODE: AtomicPtr<Node> = AtomicPtr::new(ptr::null_mut());
+
+/// The heads of the debt linked lists, one per group.
+#[cfg(not(loom))]
+static LIST_HEADS: [AtomicPtr<Node>; GROUPS] = [NO_NODE; GROUPS];
+
+#[cfg(loom)]
+loom::lazy_static! {
+    /// The heads of the debt linked lists.
+    ///
+    /// Loom needs a fresh one for each explored execution.
+    static ref LIST_HEADS: [AtomicPtr<Node>; GROUPS] = [AtomicPtr::new(ptr::null_mut())];
+}
+
+/// The group the current thread claims its nodes from.
+#[cfg(not(all(feature = "numa", not(loom))))]
+fn local_group() -> usize {
+    0
+}
+
+#[cfg(all(feature = "numa", not(loom)))]
+use crate::numa::local_group;
+
+/// The order in which to walk the groups from the current thread.
+#[cfg(not(all(feature = "numa", not(loom))))]
+fn group_order() -> &'static [usize] {
+    &[0]
+}
+
+#[cfg(all(feature = "numa", not(loom)))]
+use crate::numa::group_order;
+
+/// Marks the group as having nodes, before the first one is added.
+#[cfg(not(all(feature = "numa", not(loom))))]
+fn populate(_group: usize) {}
+
+#[cfg(all(feature = "numa", not(loom)))]
+use crate::numa::populate;
+
+/// The groups that may have nodes, a bit for each.
+#[cfg(not(all(feature = "numa", not(loom))))]
+fn populated() -> usize {
+    1
+}
+
+#[cfg(all(feature = "numa", not(loom)))]
+use crate::numa::populated;
 
 pub struct NodeReservation<'a>(&'a Node);
 
@@ -57,12 +150,61 @@ impl Drop for NodeReservation<'_> {
     }
 }
 
+/// Number of bits in the debt filter.
+// usize::BITS needs a newer compiler.
+const FILTER_BITS: usize = core::mem::size_of::<usize>() * 8;
+
+/// The bit of the address (a pointer or a storage) in the debt filter (see the module docs).
+///
+/// Multiple addresses can get the same bit, the filter can have false positives.
+#[cfg(not(loom))]
+pub(crate) fn filter_bit(addr: usize) -> usize {
+    // Fibonacci hashing; the top bits are the well mixed ones. The addresses are mostly aligned,
+    // so the lowest bits don't carry much.
+    let hash = (addr >> 3).wrapping_mul(0x9E37_79B9_7F4A_7C15_u64 as usize);
+    1 << ((hash >> (FILTER_BITS - 6)) % FILTER_BITS)
+}
+
+#[cfg(loom)]
+loom::lazy_static! {
+    /// The addresses seen in the current execution, in the order of their first use.
+    static ref FILTER_ADDRS: std::sync::Mutex<std::vec::Vec<usize>> = Default::default();
+}
+
+/// Loom needs every execution to do the same operations, but the addresses differ between them.
+/// So the bit goes by the index of the address in this execution instead, which depends only on
+/// the interleaving. Unrelated addresses get different bits, the writers really skip nodes.
+#[cfg(loom)]
+pub(crate) fn filter_bit(addr: usize) -> usize {
+    let mut addrs = FILTER_ADDRS.lock().unwrap();
+    let idx = match addrs.iter().position(|&a| a == addr) {
+        Some(idx) => idx,
+        None => {
+            addrs.push(addr);
+            addrs.len() - 1
+        }
+    };
+    1 << (idx % FILTER_BITS)
+}
+
+/// How many marks of a node go between the attempts to reset its filter (see the module docs).
+#[cfg(not(loom))]
+const FILTER_RESET_PERIOD: usize = 4096;
+
+/// Under loom, the reset has to happen within the few operations of a test.
+#[cfg(loom)]
+const FILTER_RESET_PERIOD: usize = 4;
+
 /// One thread-local node for debts.
 #[repr(C, align(64))]
 pub(crate) struct Node {
     fast: FastSlots,
     helping: HelpingSlots,
     in_use: AtomicUsize,
+    /// What the node may owe (see the module docs).
+    filter: AtomicUsize,
+    /// Number of marks, to know when to reset the filter.
+    marks: AtomicUsize,
     // Next node in the list.
     //
     // It is a pointer because we touch it before synchronization (we don't _dereference_ it before
@@ -78,6 +220,8 @@ impl Default for Node {
             fast: FastSlots::default(),
             helping: HelpingSlots::default(),
             in_use: AtomicUsize::new(NODE_USED),
+            filter: AtomicUsize::new(0),
+            marks: AtomicUsize::new(0),
             next: ptr::null(),
             active_writers: AtomicUsize::new(0),
         }
@@ -85,11 +229,25 @@ impl Default for Node {
 }
 
 impl Node {
-    /// Goes through the debt linked list.
+    /// Goes through the debt linked lists.
     ///
-    /// This traverses the linked list, calling the closure on each node. If the closure returns
-    /// `Some`, it terminates with that value early, otherwise it runs to the end.
+    /// This traverses the linked lists, calling the closure on each node. If the closure returns
+    /// `Some`, it terminates with that value early, otherwise it runs to the end. The groups of
+    /// nodes are visited from the nearest one to the current thread, the empty ones are skipped.
     pub(crate) fn traverse<R, F: FnMut(&'static Node) -> Option<R>>(mut f: F) -> Option<R> {
+        // If we don't see the bit of a group, we wouldn't see its nodes in the list either.
+        let populated = populated();
+        group_order()
+            .iter()
+            .filter(|&&group| populated & (1 << group) != 0)
+            .find_map(|&group| Self::traverse_group(group, &mut f))
+    }
+
+    /// Goes through the linked list of one group.
+    fn traverse_group<R, F: FnMut(&'static Node) -> Option<R>>(
+        group: usize,
+        f: &mut F,
+    ) -> Option<R> {
         // Acquire ‒ we want to make sure we read the correct version of data at the end of the
         // pointer. Any write to the DEBT_HEAD is with Release.
         //
@@ -98,7 +256,7 @@ impl Node {
         //
         // Note that the other pointers in the chain never change and are *ordinary* pointers. The
         // whole linked list is synchronized through the head.
-        let mut current = unsafe { LIST_HEAD.load(SeqCst).as_ref() };
+        let mut current = unsafe { LIST_HEADS[group].load(SeqCst).as_ref() };
         while let Some(node) = current {
             let result = f(node);
             if result.is_some() {
@@ -138,6 +296,46 @@ impl Node {
         }
     }
 
+    /// Can the node hold a debt for any of the addresses in the mask?
+    ///
+    /// The mask is made of [`filter_bit`]s.
+    pub(crate) fn may_owe(&self, mask: usize) -> bool {
+        // SeqCst to order it after the change of the pointer (see the module docs).
+        self.filter.load(SeqCst) & mask != 0
+    }
+
+    /// Records the address in the filter, before putting a debt in (see the module docs).
+    ///
+    /// Must be called only by the owner.
+    #[inline]
+    fn mark(&self, addr: usize) {
+        let bit = filter_bit(addr);
+        // Relaxed is enough, only we change these (or the previous owners).
+        let marks = self.marks.load(Relaxed).wrapping_add(1);
+        self.marks.store(marks, Relaxed);
+        // The period is a power of two.
+        let reset = marks & (FILTER_RESET_PERIOD - 1) == 0;
+        if reset || self.filter.load(Relaxed) & bit == 0 {
+            self.mark_slow(bit, reset);
+        }
+    }
+
+    #[cold]
+    fn mark_slow(&self, bit: usize, reset: bool) {
+        if reset && self.is_empty() {
+            self.filter.swap(bit, SeqCst);
+        } else if self.filter.load(Relaxed) & bit == 0 {
+            self.filter.fetch_or(bit, SeqCst);
+        }
+    }
+
+    /// Checks there are no debts in the node.
+    fn is_empty(&self) -> bool {
+        self.fast_slots()
+            .chain(core::iter::once(self.helping_slot()))
+            .all(|d| d.0.load(Relaxed) == Debt::NONE)
+    }
+
     /// Mark this node that a writer is currently playing with it.
     pub fn reserve_writer(&self) -> NodeReservation<'_> {
         self.active_writers.fetch_add(1, Acquire);
@@ -147,10 +345,12 @@ impl Node {
     /// "Allocate" a node.
     ///
     /// Either a new one is created, or previous one is reused. The node is claimed to become
-    /// in_use.
+    /// in_use. Only the group of the current thread is considered.
     fn get() -> &'static Self {
+        let group = local_group();
+        let head = &LIST_HEADS[group];
         // Try to find an unused one in the chain and reuse it.
-        Self::traverse(|node| {
+        Self::traverse_group(group, &mut |node: &'static Node| {
             node.check_cooldown();
             if node
                 .in_use
@@ -168,16 +368,17 @@ impl Node {
         .unwrap_or_else(|| {
             let node = Box::leak(Box::<Node>::default());
             node.helping.init();
+            populate(group);
             // We don't want to read any data in addition to the head, Relaxed is fine
             // here.
             //
             // We do need to release the data to others, but for that, we acquire in the
             // compare_exchange below.
-            let mut head = LIST_HEAD.load(SeqCst);
+            let mut current = head.load(SeqCst);
             loop {
-                node.next = head;
-                if let Err(old) = LIST_HEAD.compare_exchange_weak(
-                    head, node,
+                node.next = current;
+                if let Err(old) = head.compare_exchange_weak(
+                    current, node,
                     // We need to release *the whole chain* here. For that, we need to
                     // acquire it first.
                     //
@@ -185,7 +386,7 @@ impl Node {
                     // anything to the debts.
                     SeqCst, SeqCst, // Nothing changed, go next round of the loop.
                 ) {
-                    head = old;
+                    current = old;
                 } else {
                     return node;
                 }
@@ -202,6 +403,11 @@ impl Node {
     pub(crate) fn helping_slot(&self) -> &Debt {
         self.helping.slot()
     }
+
+    /// The storage the reader on this node is loading from through the helping slot, if any.
+    pub(crate) fn helping_addr(&self) -> Option<usize> {
+        self.helping.active_addr()
+    }
 }
 
 /// A wrapper around a node pointer, to un-claim the node on thread shutdown.
@@ -216,17 +422,42 @@ pub(crate) struct LocalNode {
 
     /// Thread local data for the helping strategy.
     helping: HelpingLocal,
+
+    /// See [`thread_id`][LocalNode::thread_id].
+    #[cfg(not(feature = "custom-thread-local"))]
+    thread_id: Cell<usize>,
 }
 
 impl LocalNode {
+    /// Creates a local node without any node assigned yet.
+    pub(crate) const fn new() -> Self {
+        LocalNode {
+            node: Cell::new(None),
+            fast: FastLocal::new(),
+            helping: HelpingLocal::new(),
+            #[cfg(not(feature = "custom-thread-local"))]
+            thread_id: Cell::new(NO_THREAD_ID),
+        }
+    }
+
+    /// A local node for a single use, when there's no proper thread-local storage for it.
+    ///
+    /// Dropping it sends the node into cooldown, so it can be reused by someone else.
     #[cfg(not(feature = "experimental-thread-local"))]
+    fn tmp() -> Self {
+        let tmp_node = LocalNode::new();
+        tmp_node.node.set(Some(Node::get()));
+        #[cfg(not(feature = "custom-thread-local"))]
+        tmp_node.thread_id.set(TMP_THREAD_ID);
+        tmp_node
+    }
+
+    #[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
     pub(crate) fn with<R, F: FnOnce(&LocalNode) -> R>(f: F) -> R {
         let f = Cell::new(Some(f));
         THREAD_HEAD
             .try_with(|head| {
-                if head.node.get().is_none() {
-                    head.node.set(Some(Node::get()));
-                }
+                head.ensure_node();
                 let f = f.take().unwrap();
                 f(head)
             })
@@ -237,11 +468,7 @@ impl LocalNode {
             // Note that the situation should be very very rare and not happen often, so the slower
             // performance doesn't matter that much.
             .unwrap_or_else(|_| {
-                let tmp_node = LocalNode {
-                    node: Cell::new(Some(Node::get())),
-                    fast: FastLocal::default(),
-                    helping: HelpingLocal::default(),
-                };
+                let tmp_node = LocalNode::tmp();
                 let f = f.take().unwrap();
                 f(&tmp_node)
                 // Drop of tmp_node -> sends the node we just used into cooldown.
@@ -250,42 +477,103 @@ impl LocalNode {
 
     #[cfg(feature = "experimental-thread-local")]
     pub(crate) fn with<R, F: FnOnce(&LocalNode) -> R>(f: F) -> R {
-        let thread_head = THREAD_HEAD.get_or_init(|| LocalNode {
-            node: Cell::new(None),
-            fast: FastLocal::default(),
-            helping: HelpingLocal::default(),
-        });
-        if thread_head.node.get().is_none() {
-            thread_head.node.set(Some(Node::get()));
-        }
+        let thread_head = THREAD_HEAD.get_or_init(LocalNode::new);
+        thread_head.ensure_node();
         f(&thread_head)
     }
 
+    #[cfg(feature = "custom-thread-local")]
+    pub(crate) fn with<R, F: FnOnce(&LocalNode) -> R>(f: F) -> R {
+        let f = Cell::new(Some(f));
+        let result = Cell::new(None);
+        if let Some(provider) = crate::local_storage::provider() {
+            provider(&mut |storage| {
+                // Make sure we don't call it twice if the provider is misbehaving.
+                if let Some(f) = f.take() {
+                    let head = storage.local_node();
+                    head.ensure_node();
+                    result.set(Some(f(head)));
+                }
+            });
+        }
+        // Either there's no provider registered, or it didn't have a storage for us right now.
+        // Just like with the thread local shutdown, use a node just once.
+        result.into_inner().unwrap_or_else(|| {
+            let tmp_node = LocalNode::tmp();
+            let f = f.take().unwrap();
+            f(&tmp_node)
+        })
+    }
+
+    /// A unique identity of the thread owning this, for keeping data per thread.
+    ///
+    /// Unlike the address of the thread local storage, it is never reused by another thread. It is
+    /// `None` for the temporary local nodes, as these don't belong to any thread.
+    ///
+    /// Not available with the `custom-thread-local`, where the local node may be shared by several
+    /// threads taking turns.
+    #[cfg(not(feature = "custom-thread-local"))]
+    pub(crate) fn thread_id(&self) -> Option<usize> {
+        match self.thread_id.get() {
+            TMP_THREAD_ID => None,
+            NO_THREAD_ID => {
+                let id = NEXT_THREAD_ID.fetch_add(1, Relaxed);
+                self.thread_id.set(id);
+                Some(id)
+            }
+            id => Some(id),
+        }
+    }
+
+    /// Makes sure there's a node assigned.
+    ///
+    /// The node can get lost between uses (see [`new_helping`][LocalNode::new_helping]), so anyone
+    /// keeping the `LocalNode` for multiple operations needs to call this before each of them.
+    #[inline]
+    pub(crate) fn ensure_node(&self) {
+        if self.node.get().is_none() {
+            self.node.set(Some(Node::get()));
+        }
+    }
+
     /// Creates a new debt.
     ///
     /// This stores the debt of the given pointer (untyped, casted into an usize) and returns a
     /// reference to that slot, or gives up with `None` if all the slots are currently full.
     #[inline]
     pub(crate) fn new_fast(&self, ptr: usize) -> Option<&'static Debt> {
+        self.new_fast_ordered(ptr, SeqCst)
+    }
+
+    /// Creates a new debt, with a custom ordering of storing it.
+    ///
+    /// Anything weaker than `SeqCst` is correct only if the caller ensures the ordering by other
+    /// means (like the asymmetric fences).
+    #[inline]
+    pub(crate) fn new_fast_ordered(&self, ptr: usize, order: Ordering) -> Option<&'static Debt> {
         let node = &self.node.get().expect("LocalNode::with ensures it is set");
         debug_assert_eq!(node.in_use.load(Relaxed), NODE_USED);
-        node.fast.get_debt(ptr, &self.fast)
+        node.mark(ptr);
+        node.fast.get_debt(ptr, &self.fast, order)
     }
 
     /// Initializes a helping slot transaction.
     ///
     /// Returns the generation (with tag).
     pub(crate) fn new_helping(&self, ptr: usize) -> usize {
-        let node = &self.node.get().expect("LocalNode::with ensures it is set");
-        debug_assert_eq!(node.in_use.load(Relaxed), NODE_USED);
-        let (gen, discard) = node.helping.get_debt(ptr, &self.helping);
-        if discard {
+        if self.helping.wraps() {
             // Too many generations happened, make sure the writers give the poor node a break for
-            // a while so they don't observe the generation wrapping around.
+            // a while so they don't observe the generation wrapping around. This needs to happen
+            // before the transaction, the confirm still needs a node.
+            let node = self.node.take().expect("LocalNode::with ensures it is set");
             node.start_cooldown();
-            self.node.take();
+            self.ensure_node();
         }
-        gen
+        let node = &self.node.get().expect("LocalNode::with ensures it is set");
+        debug_assert_eq!(node.in_use.load(Relaxed), NODE_USED);
+        // The debt in the helping slot doesn't outlive the load, so the storage can't move.
+        node.mark(ptr);
+        node.helping.get_debt(ptr, &self.helping)
     }
 
     /// Confirm the helping transaction.
@@ -332,14 +620,22 @@ impl Drop for LocalNode {
     }
 }
 
-#[cfg(not(feature = "experimental-thread-local"))]
+#[cfg(not(any(
+    feature = "experimental-thread-local",
+    feature = "custom-thread-local",
+    loom
+)))]
 thread_local! {
     /// A debt node assigned to this thread.
-    static THREAD_HEAD: LocalNode = LocalNode {
-        node: Cell::new(None),
-        fast: FastLocal::default(),
-        helping: HelpingLocal::default(),
-    };
+    // The const {} syntax is not available on old compilers.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static THREAD_HEAD: LocalNode = LocalNode::new();
+}
+
+#[cfg(loom)]
+loom::thread_local! {
+    /// A debt node assigned to this thread.
+    static THREAD_HEAD: LocalNode = LocalNode::new();
 }
 
 #[cfg(feature = "experimental-thread-local")]
@@ -347,17 +643,24 @@ thread_local! {
 /// A debt node assigned to this thread.
 static THREAD_HEAD: OnceCell<LocalNode> = OnceCell::new();
 
+#[cfg(all(test, loom))]
+impl LocalNode {
+    /// Makes the next helping load wrap the generation around.
+    pub(super) fn before_wrap(&self) {
+        self.helping.before_wrap();
+    }
+
+    /// The node the thread currently owns.
+    pub(super) fn node(&self) -> Option<&'static Node> {
+        self.node.get()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     impl Node {
-        fn is_empty(&self) -> bool {
-            self.fast_slots()
-                .chain(core::iter::once(self.helping_slot()))
-                .all(|d| d.0.load(Relaxed) == Debt::NONE)
-        }
-
         fn get_thread() -> &'static Self {
             LocalNode::with(|h| h.node.get().unwrap())
         }
@@ -368,4 +671,37 @@ mod tests {
     fn new_empty() {
         assert!(Node::get_thread().is_empty());
     }
+
+    /// The filter is marked by the owner and reset once in a while, when it has no debts.
+    #[test]
+    fn filter() {
+        let (a, b) = (0x1000, 0x2000);
+        let (bit_a, bit_b) = (filter_bit(a), filter_bit(b));
+        assert_eq!(1, bit_a.count_ones());
+        assert_eq!(bit_a, filter_bit(a));
+
+        let node = Node::default();
+        assert!(!node.may_owe(bit_a));
+        node.mark(a);
+        assert!(node.may_owe(bit_a));
+        node.mark(b);
+        assert_eq!(bit_a | bit_b, node.filter.load(Relaxed));
+
+        // Empty, so it starts over at the end of the period (the last of these marks).
+        for _ in 2..FILTER_RESET_PERIOD - 1 {
+            node.mark(b);
+        }
+        assert_eq!(bit_a | bit_b, node.filter.load(Relaxed));
+        node.mark(b);
+        assert_eq!(bit_b, node.filter.load(Relaxed));
+
+        // Holding a debt, so it keeps adding.
+        let local = FastLocal::default();
+        let debt = node.fast.get_debt(b, &local, SeqCst).unwrap();
+        for _ in 0..FILTER_RESET_PERIOD {
+            node.mark(a);
+        }
+        assert_eq!(bit_a | bit_b, node.filter.load(Relaxed));
+        assert!(debt.pay::<crate::imports::Arc<u8>>(b as *const u8));
+    }
 }
@@ -0,0 +1,249 @@
+//! Model checking of the collision paths of the debts.
+//!
+//! Unlike `tests/loom.rs`, these drive the debt slots directly, so they can check that the
+//! collision in question really happens in some of the explored executions (the model checker
+//! would happily pass a test that never gets there). Run them by:
+//!
+//! ```sh
+//! RUSTFLAGS="--cfg loom" cargo test --release --lib loom_tests
+//! ```
+
+extern crate std;
+
+use core::cell::Cell;
+use core::sync::atomic::Ordering::*;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc as StdArc;
+
+use loom::model::Builder;
+use loom::sync::Arc;
+use loom::thread;
+
+use super::LocalNode;
+use crate::ref_cnt::RefCnt;
+use crate::strategy::hybrid::{Config, HybridStrategy};
+use crate::ArcSwapAny;
+
+type Value = Arc<usize>;
+
+loom::thread_local! {
+    /// How many nodes the writers of the current thread skipped thanks to the debt filter.
+    pub(super) static SKIPPED: Cell<usize> = Cell::new(0);
+}
+
+/// See `tests/loom.rs`, the threads need bigger stacks.
+fn spawn<R, F>(f: F) -> thread::JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    thread::Builder::new().stack_size(1 << 20).spawn(f).unwrap()
+}
+
+fn model<F: Fn() + Sync + Send + 'static>(f: F) {
+    let mut builder = Builder::new();
+    if builder.preemption_bound.is_none() {
+        builder.preemption_bound = Some(3);
+    }
+    let f = StdArc::new(f);
+    builder.check(move || {
+        let f = StdArc::clone(&f);
+        spawn(move || f()).join().unwrap();
+    });
+}
+
+/// Goes straight to the helping slot, without trying the fast ones.
+#[derive(Clone, Default)]
+struct HelpingOnly;
+
+impl Config for HelpingOnly {
+    const USE_FAST: bool = false;
+}
+
+/// The writer helps a reader in the middle of a helping load, so the reader's confirm fails and
+/// it takes the replacement instead of its own candidate.
+#[test]
+fn confirm_loses() {
+    static CONFIRMED: AtomicBool = AtomicBool::new(false);
+    static HELPED: AtomicBool = AtomicBool::new(false);
+
+    model(|| {
+        let shared = Arc::new(ArcSwapAny::<Value>::new(Arc::new(0)));
+
+        let reader = {
+            let shared = Arc::clone(&shared);
+            spawn(move || {
+                let storage = &shared.ptr;
+                LocalNode::with(|node| {
+                    let gen = node.new_helping(storage as *const _ as usize);
+                    let candidate = storage.load(SeqCst);
+                    match node.confirm_helping(gen, candidate as usize) {
+                        Ok(debt) => {
+                            CONFIRMED.store(true, Relaxed);
+                            // Protected by the debt.
+                            let value = unsafe { *candidate };
+                            if !debt.pay::<Value>(candidate) {
+                                unsafe { Value::dec(candidate) };
+                            }
+                            value
+                        }
+                        Err((unused, replacement)) => {
+                            HELPED.store(true, Relaxed);
+                            if !unused.pay::<Value>(candidate) {
+                                unsafe { Value::dec(candidate) };
+                            }
+                            // The writer loaded it after the store.
+                            let replacement = unsafe { Value::from_ptr(replacement as *const _) };
+                            assert_eq!(1, *replacement);
+                            *replacement
+                        }
+                    }
+                })
+            })
+        };
+
+        shared.store(Arc::new(1));
+        let value = reader.join().unwrap();
+        assert!(value == 0 || value == 1);
+        assert_eq!(1, **shared.load());
+    });
+
+    assert!(CONFIRMED.load(Relaxed));
+    assert!(HELPED.load(Relaxed));
+}
+
+/// The generation of the helping slot wraps around in the middle of the writer helping.
+///
+/// The node goes to cooldown before the wrapped generation is used. The reader gets it back right
+/// away if no writer is looking at it, otherwise it has to take another one.
+#[test]
+fn generation_wraps() {
+    static REUSED: AtomicBool = AtomicBool::new(false);
+    static FRESH: AtomicBool = AtomicBool::new(false);
+
+    model(|| {
+        let shared = Arc::new(ArcSwapAny::<Value, HybridStrategy<HelpingOnly>>::new(
+            Arc::new(0),
+        ));
+
+        let reader = {
+            let shared = Arc::clone(&shared);
+            spawn(move || {
+                let before = LocalNode::with(|node| {
+                    node.before_wrap();
+                    node.node().map(|node| node as *const _)
+                });
+                let first = **shared.load();
+                let second = **shared.load();
+                assert!(first <= second);
+                let after = LocalNode::with(|node| node.node().map(|node| node as *const _));
+                if before == after {
+                    REUSED.store(true, Relaxed);
+                } else {
+                    FRESH.store(true, Relaxed);
+                }
+            })
+        };
+
+        shared.store(Arc::new(1));
+        reader.join().unwrap();
+        assert_eq!(1, **shared.load());
+    });
+
+    assert!(REUSED.load(Relaxed));
+    assert!(FRESH.load(Relaxed));
+}
+
+/// The reader returns its fast debt at the same time the writer pays it.
+///
+/// Exactly one of them succeeds and the reader knows if it needs to drop the reference the writer
+/// gave it.
+#[test]
+fn pay_races_pay_all() {
+    static READER_PAID: AtomicBool = AtomicBool::new(false);
+    static WRITER_PAID: AtomicBool = AtomicBool::new(false);
+
+    model(|| {
+        let shared = Arc::new(ArcSwapAny::<Value>::new(Arc::new(0)));
+
+        let reader = {
+            let shared = Arc::clone(&shared);
+            spawn(move || {
+                let storage = &shared.ptr;
+                LocalNode::with(|node| {
+                    let ptr = storage.load(SeqCst);
+                    let debt = node.new_fast(ptr as usize).expect("The slots are empty");
+                    if storage.load(SeqCst) != ptr && debt.pay::<Value>(ptr) {
+                        // Replaced before we got the debt in, the pointer may be gone already.
+                        return None;
+                    }
+                    // Either confirmed, or the writer paid the debt for us already. Both keep it
+                    // alive.
+                    let value = unsafe { *ptr };
+                    if debt.pay::<Value>(ptr) {
+                        READER_PAID.store(true, Relaxed);
+                    } else {
+                        WRITER_PAID.store(true, Relaxed);
+                        unsafe { Value::dec(ptr) };
+                    }
+                    Some(value)
+                })
+            })
+        };
+
+        shared.store(Arc::new(1));
+        let value = reader.join().unwrap();
+        if let Some(value) = value {
+            assert!(value <= 1);
+        }
+    });
+
+    assert!(READER_PAID.load(Relaxed));
+    assert!(WRITER_PAID.load(Relaxed));
+}
+
+/// The writer skips the node of a reader working with a different storage.
+///
+/// The reader has marked the writer's value before, but it resets its filter in the meantime, so
+/// the writer either goes through the node (before the reset) or skips it (after).
+#[test]
+fn filter_skips() {
+    static VISITED: AtomicBool = AtomicBool::new(false);
+    static SKIPPED_READER: AtomicBool = AtomicBool::new(false);
+
+    model(|| {
+        let a = Arc::new(ArcSwapAny::<Value>::new(Arc::new(0)));
+        let b = Arc::new(ArcSwapAny::<Value>::new(Arc::new(1)));
+
+        // Claims our node, with the current value of b in the filter.
+        drop(b.load());
+
+        let writer = {
+            let b = Arc::clone(&b);
+            spawn(move || {
+                // Our own node owes b something, so the only one to skip is the reader's.
+                drop(b.load());
+                b.store(Arc::new(2));
+                SKIPPED.with(Cell::get)
+            })
+        };
+
+        // Enough marks to reset the filter, forgetting about b.
+        for _ in 0..3 {
+            drop(a.load());
+        }
+        let guard = a.load();
+        assert_eq!(0, **guard);
+
+        match writer.join().unwrap() {
+            0 => VISITED.store(true, Relaxed),
+            1 => SKIPPED_READER.store(true, Relaxed),
+            n => panic!("Skipped {} nodes", n),
+        }
+        assert_eq!(2, **b.load());
+        assert_eq!(0, **guard);
+    });
+
+    assert!(VISITED.load(Relaxed));
+    assert!(SKIPPED_READER.load(Relaxed));
+}
@@ -9,20 +9,27 @@
 //! gives it up, another (new) thread can claim it.
 //!
 //! The writers walk the whole chain and pay the debts (by bumping the ref counts) of the just
-//! removed pointer.
+//! removed pointer. They skip the nodes that can't owe them anything (see the debt filter in the
+//! list submodule).
 //!
 //! Each node has some fast (but fallible) nodes and a fallback node, with different algorithms to
 //! claim them (see the relevant submodules).
 
-use core::sync::atomic::AtomicUsize;
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
 use core::sync::atomic::Ordering::*;
 
-pub(crate) use self::list::{LocalNode, Node};
+pub(crate) use self::list::{filter_bit, LocalNode, Node};
 use super::RefCnt;
+use crate::imports::Vec;
+use crate::sync::AtomicPtr;
+use crate::sync::AtomicUsize;
 
 mod fast;
 mod helping;
 mod list;
+#[cfg(all(test, loom))]
+mod loom_tests;
 
 /// One debt slot.
 ///
@@ -83,37 +90,285 @@ impl Debt {
         T: RefCnt,
         R: Fn() -> T,
     {
-        LocalNode::with(|local| {
-            let val = unsafe { T::from_ptr(ptr) };
-            // Pre-pay one ref count that can be safely put into a debt slot to pay it.
-            T::inc(&val);
+        Self::pay_retired(&Retiring::new(ptr, storage_addr, replacement));
+    }
 
+    /// Pays all the debts of the retired pointer(s), in a single pass through the nodes.
+    pub(crate) fn pay_retired<R: Retired + ?Sized>(retired: &R) {
+        let mask = retired.filter_mask();
+        LocalNode::with(|local| {
             Node::traverse::<(), _>(|node| {
+                // Nobody there owes us anything.
+                if !node.may_owe(mask) {
+                    #[cfg(all(test, loom))]
+                    loom_tests::SKIPPED.with(|skipped| skipped.set(skipped.get() + 1));
+                    return None;
+                }
+
                 // Make the cooldown trick know we are poking into this node.
                 let _reservation = node.reserve_writer();
 
-                local.help(node, storage_addr, &replacement);
+                retired.help(local, node);
 
                 let all_slots = node
                     .fast_slots()
                     .chain(core::iter::once(node.helping_slot()));
                 for slot in all_slots {
-                    // Note: Release is enough even here. That makes sure the increment is
-                    // visible to whoever might acquire on this slot and can't leak below this.
-                    // And we are the ones doing decrements anyway.
-                    if slot.pay::<T>(ptr) {
-                        // Pre-pay one more, for another future slot
-                        T::inc(&val);
-                    }
+                    retired.pay(slot);
                 }
 
                 None
             });
-            // Implicit dec by dropping val in here, pair for the above
         })
     }
 }
 
+/// A pointer removed from a storage, with debts that still need to be paid.
+///
+/// This is implemented by a single such pointer and by a bunch of them, so many of them can be
+/// paid in one pass through the nodes.
+pub(crate) trait Retired {
+    /// The [`filter_bit`]s of the pointer(s) and the storages they were removed from.
+    fn filter_mask(&self) -> usize;
+    /// Helps the readers of the storage that are stuck in the fallback on the given node.
+    fn help(&self, local: &LocalNode, node: &Node);
+    /// Pays the debt in the slot if it is for this pointer.
+    ///
+    /// Returns if it did.
+    fn pay(&self, slot: &Debt) -> bool;
+}
+
+/// The [`Retired`] of a single pointer.
+struct Retiring<T: RefCnt, R> {
+    ptr: *const T::Base,
+    storage_addr: usize,
+    replacement: R,
+    /// One ref count that can be put into a debt slot to pay it.
+    prepaid: T,
+}
+
+impl<T: RefCnt, R: Fn() -> T> Retiring<T, R> {
+    /// Prepares the retirement of the pointer.
+    ///
+    /// The caller must keep the value alive for as long as this exists.
+    fn new(ptr: *const T::Base, storage_addr: usize, replacement: R) -> Self {
+        // We don't own the ref count in there, so don't drop it.
+        let val = ManuallyDrop::new(unsafe { T::from_ptr(ptr) });
+        // Pre-pay one ref count that can be safely put into a debt slot to pay it.
+        let prepaid = unsafe { T::from_ptr(T::inc(&val)) };
+        Retiring {
+            ptr,
+            storage_addr,
+            replacement,
+            prepaid,
+        }
+    }
+}
+
+impl<T: RefCnt, R: Fn() -> T> Retired for Retiring<T, R> {
+    fn filter_mask(&self) -> usize {
+        filter_bit(self.ptr as usize) | filter_bit(self.storage_addr)
+    }
+
+    fn help(&self, local: &LocalNode, node: &Node) {
+        local.help(node, self.storage_addr, &self.replacement);
+    }
+
+    fn pay(&self, slot: &Debt) -> bool {
+        // Note: Release is enough even here. That makes sure the increment is visible to whoever
+        // might acquire on this slot and can't leak below this. And we are the ones doing
+        // decrements anyway.
+        if slot.pay::<T>(self.ptr) {
+            // The prepaid one went into the slot. Pre-pay one more, for another future slot.
+            T::inc(&self.prepaid);
+            true
+        } else {
+            false
+        }
+    }
+    // Implicit dec by dropping prepaid, pair for the first inc.
+}
+
+/// A strategy that can load a fresh value for the readers helped by a writer.
+///
+/// Needed to pay the debts of the [`Retirements`] later on, when the strategy itself is known
+/// only by its address.
+pub(crate) trait Replacement<T: RefCnt> {
+    /// Loads a value from the storage.
+    ///
+    /// # Safety
+    ///
+    /// The storage must be one managed by this strategy.
+    unsafe fn replacement(&self, storage: &AtomicPtr<T::Base>) -> T;
+}
+
+/// One pointer in the [`Retirements`].
+///
+/// The types are erased (into plain addresses and functions working on them), so a batch doesn't
+/// need an allocation for each of them.
+struct Retiree {
+    ptr: usize,
+    storage_addr: usize,
+    strategy_addr: usize,
+    /// Adds one ref count to the pointer.
+    inc: unsafe fn(usize),
+    /// Drops one ref count of the pointer.
+    dec: unsafe fn(usize),
+    /// Helps the readers on the node (see [`Retired::help`]).
+    help: unsafe fn(&Retiree, &LocalNode, &Node),
+}
+
+impl Retiree {
+    unsafe fn inc<T: RefCnt>(ptr: usize) {
+        let val = ManuallyDrop::new(T::from_ptr(ptr as *const T::Base));
+        T::inc(&val);
+    }
+
+    unsafe fn dec<T: RefCnt>(ptr: usize) {
+        T::dec(ptr as *const T::Base);
+    }
+
+    unsafe fn help<T: RefCnt, S: Replacement<T>>(&self, local: &LocalNode, node: &Node) {
+        let strategy = &*(self.strategy_addr as *const S);
+        let storage = &*(self.storage_addr as *const AtomicPtr<T::Base>);
+        local.help(node, self.storage_addr, &|| strategy.replacement(storage));
+    }
+}
+
+impl Drop for Retiree {
+    fn drop(&mut self) {
+        // The removed value itself and the prepaid ref count (see Retiring).
+        unsafe {
+            (self.dec)(self.ptr);
+            (self.dec)(self.ptr);
+        }
+    }
+}
+
+/// Bunch of retired pointers, to be paid together.
+// pub because it shows in the sealed strategy traits, not reachable from outside.
+#[derive(Default)]
+pub struct Retirements<'a> {
+    retirees: Vec<Retiree>,
+    /// The retirees borrow the strategies and the storages.
+    _borrow: PhantomData<&'a ()>,
+}
+
+impl<'a> Retirements<'a> {
+    /// Adds a value removed from the storage.
+    ///
+    /// The value is kept alive until the debts are paid by [`pay`][Retirements::pay].
+    ///
+    /// # Safety
+    ///
+    /// The storage must be managed by the strategy.
+    pub(crate) unsafe fn push<T, S>(
+        &mut self,
+        old: T,
+        storage: &'a AtomicPtr<T::Base>,
+        strategy: &'a S,
+    ) where
+        T: RefCnt + 'a,
+        S: Replacement<T>,
+    {
+        let ptr = T::into_ptr(old);
+        // Pre-pay one ref count that can be safely put into a debt slot to pay it.
+        Retiree::inc::<T>(ptr as usize);
+        self.retirees.push(Retiree {
+            ptr: ptr as usize,
+            storage_addr: storage as *const _ as usize,
+            strategy_addr: strategy as *const S as usize,
+            inc: Retiree::inc::<T>,
+            dec: Retiree::dec::<T>,
+            help: Retiree::help::<T, S>,
+        });
+    }
+
+    /// Pays all the debts of the pointers (in a single pass through the nodes) and releases them.
+    pub(crate) fn pay(&mut self) {
+        if self.retirees.is_empty() {
+            return;
+        }
+        // Sorted, so each slot can find its pointer without going through all of them.
+        self.retirees.sort_unstable_by_key(|retiree| retiree.ptr);
+        // And the same for the storages of the readers to help.
+        let mut by_storage = self
+            .retirees
+            .iter()
+            .enumerate()
+            .map(|(idx, retiree)| (retiree.storage_addr, idx))
+            .collect::<Vec<_>>();
+        by_storage.sort_unstable();
+        Debt::pay_retired(&SortedRetirees {
+            by_ptr: &self.retirees,
+            by_storage,
+        });
+        self.retirees.clear();
+    }
+}
+
+/// The [`Retired`] of the [`Retirements`].
+struct SortedRetirees<'r> {
+    /// The retirees, sorted by the pointer.
+    by_ptr: &'r [Retiree],
+    /// The storage addresses with the index of their retiree in `by_ptr`, sorted.
+    by_storage: Vec<(usize, usize)>,
+}
+
+impl Retired for SortedRetirees<'_> {
+    fn filter_mask(&self) -> usize {
+        self.by_ptr.iter().fold(0, |mask, retiree| {
+            mask | filter_bit(retiree.ptr) | filter_bit(retiree.storage_addr)
+        })
+    }
+
+    fn help(&self, local: &LocalNode, node: &Node) {
+        // The reader can be loading from only one storage, so only one retiree can help it. If
+        // the same storage is there more than once, any of them provides the current value.
+        let addr = match node.helping_addr() {
+            Some(addr) => addr,
+            None => return,
+        };
+        if let Ok(idx) = self
+            .by_storage
+            .binary_search_by_key(&addr, |&(storage_addr, _)| storage_addr)
+        {
+            let retiree = &self.by_ptr[self.by_storage[idx].1];
+            unsafe { (retiree.help)(retiree, local, node) };
+        }
+    }
+
+    fn pay(&self, slot: &Debt) -> bool {
+        // The same as a failed compare_exchange in Debt::pay would see.
+        let owed = slot.0.load(SeqCst);
+        if owed == Debt::NONE {
+            return false;
+        }
+        // A slot can hold only one debt. If the same pointer got retired more than once, any of
+        // them can pay it.
+        match self
+            .by_ptr
+            .binary_search_by_key(&owed, |retiree| retiree.ptr)
+        {
+            Ok(idx) => {
+                let retiree = &self.by_ptr[idx];
+                // Same as Retiring::pay, but the pointer type doesn't matter for the slot.
+                if slot
+                    .0
+                    .compare_exchange(owed, Debt::NONE, SeqCst, SeqCst)
+                    .is_ok()
+                {
+                    unsafe { (retiree.inc)(retiree.ptr) };
+                    true
+                } else {
+                    false
+                }
+            }
+            Err(_) => false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use alloc::sync::Arc;
@@ -0,0 +1,404 @@
+//! Tracking of the outstanding guards, for finding where they are held.
+//!
+//! Holding many [`Guard`][crate::Guard]s at once (or holding them for a long time) exhausts the
+//! fast slots of the thread and makes further loads slower (see the
+//! [limitations][crate::docs::limitations]). It is not always obvious which code holds them,
+//! though.
+//!
+//! With the `debug-guards` feature, each guard remembers where it was created (through
+//! `#[track_caller]`, so this is the place in the user code calling
+//! [`load`][crate::ArcSwapAny::load] and similar methods) and, optionally, the whole backtrace.
+//! The guards of the current thread can be listed with [`dump`]. It is also possible to set
+//! limits on how many guards a thread may hold and for how long; exceeding them is reported on
+//! stderr or by a panic, together with the list of the guards.
+//!
+//! This makes each guard considerably more expensive and is meant for debugging only. The feature
+//! needs the standard library and Rust 1.65.
+//!
+//! Note that a guard belongs to the thread that created it, even if it is sent to another one.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use arc_swap::ArcSwap;
+//! use arc_swap::debug_guards::{self, Action};
+//!
+//! debug_guards::set_max_guards(Some(100));
+//! debug_guards::set_max_hold(Some(Duration::from_secs(1)));
+//! debug_guards::set_action(Action::Warn);
+//!
+//! let shared = ArcSwap::from_pointee(42);
+//! let guard = shared.load();
+//! for info in debug_guards::dump() {
+//!     println!("{}", info);
+//! }
+//! # drop(guard);
+//! ```
+
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What happens when a thread exceeds one of the limits.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Action {
+    /// Print a warning with the list of the guards to stderr.
+    ///
+    /// This is the default.
+    #[default]
+    Warn,
+    /// Panic, with the list of the guards in the message.
+    ///
+    /// Exceeding the hold duration is found out when dropping the guard at the latest. If the
+    /// thread is already panicking at that time, only a warning is printed.
+    Panic,
+}
+
+const UNLIMITED: u64 = u64::MAX;
+
+static MAX_GUARDS: AtomicU64 = AtomicU64::new(UNLIMITED);
+static MAX_HOLD_NANOS: AtomicU64 = AtomicU64::new(UNLIMITED);
+static PANIC: AtomicBool = AtomicBool::new(false);
+static BACKTRACES: AtomicBool = AtomicBool::new(false);
+/// Number of the currently set limits, so we don't have to look at them if there are none.
+static LIMITS: AtomicUsize = AtomicUsize::new(0);
+
+fn set_limit(limit: &AtomicU64, value: u64) {
+    let old = limit.swap(value, Ordering::Relaxed);
+    match (old == UNLIMITED, value == UNLIMITED) {
+        (true, false) => {
+            LIMITS.fetch_add(1, Ordering::Relaxed);
+        }
+        (false, true) => {
+            LIMITS.fetch_sub(1, Ordering::Relaxed);
+        }
+        _ => (),
+    }
+}
+
+fn limit(limit: &AtomicU64) -> Option<u64> {
+    match limit.load(Ordering::Relaxed) {
+        UNLIMITED => None,
+        value => Some(value),
+    }
+}
+
+/// Sets how many guards a single thread may hold at once.
+///
+/// `None` (the default) means no limit. The limit applies to all threads.
+pub fn set_max_guards(max: Option<usize>) {
+    set_limit(&MAX_GUARDS, max.map_or(UNLIMITED, |max| max as u64));
+}
+
+/// Sets for how long a guard may be held.
+///
+/// `None` (the default) means no limit. It is checked whenever the thread creates or drops a
+/// guard and in [`check`].
+pub fn set_max_hold(max: Option<Duration>) {
+    let nanos = max.map_or(UNLIMITED, |max| {
+        u64::try_from(max.as_nanos()).unwrap_or(UNLIMITED - 1)
+    });
+    set_limit(&MAX_HOLD_NANOS, nanos);
+}
+
+/// Sets what happens when a limit is exceeded.
+pub fn set_action(action: Action) {
+    PANIC.store(action == Action::Panic, Ordering::Relaxed);
+}
+
+/// Turns capturing of the whole backtrace for each new guard on or off.
+///
+/// It is off by default, because it is very slow. Only the location of the call is remembered
+/// then.
+pub fn set_backtraces(enabled: bool) {
+    BACKTRACES.store(enabled, Ordering::Relaxed);
+}
+
+/// Information about one outstanding guard.
+#[derive(Clone, Debug)]
+pub struct GuardInfo {
+    location: &'static Location<'static>,
+    created: Instant,
+    backtrace: Option<Arc<Backtrace>>,
+}
+
+impl GuardInfo {
+    /// The place where the guard was created.
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// When the guard was created.
+    pub fn created(&self) -> Instant {
+        self.created
+    }
+
+    /// For how long the guard has been held.
+    pub fn held_for(&self) -> Duration {
+        self.created.elapsed()
+    }
+
+    /// The backtrace of the creation, if capturing them is turned on.
+    ///
+    /// See [`set_backtraces`].
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_deref()
+    }
+}
+
+impl Display for GuardInfo {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        write!(
+            formatter,
+            "guard created at {}, held for {:?}",
+            self.location,
+            self.held_for()
+        )?;
+        if let Some(backtrace) = &self.backtrace {
+            write!(formatter, "\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+struct Entry {
+    info: GuardInfo,
+    /// Already reported as held for too long, don't repeat it.
+    reported: bool,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    entries: BTreeMap<u64, Entry>,
+}
+
+impl Registry {
+    fn dump(&self) -> Vec<GuardInfo> {
+        self.entries
+            .values()
+            .map(|entry| entry.info.clone())
+            .collect()
+    }
+
+    /// Finds the guards held for too long that were not reported yet.
+    ///
+    /// The `dropped` one is already out of the registry, but still needs checking.
+    fn overdue(&mut self, dropped: Option<&Entry>) -> Option<String> {
+        let max = Duration::from_nanos(limit(&MAX_HOLD_NANOS)?);
+        let mut report = String::new();
+        for entry in self.entries.values_mut() {
+            if !entry.reported && entry.info.held_for() > max {
+                entry.reported = true;
+                report.push_str(&format!("\n  {}", entry.info));
+            }
+        }
+        if let Some(entry) = dropped {
+            if !entry.reported && entry.info.held_for() > max {
+                report.push_str(&format!("\n  {}", entry.info));
+            }
+        }
+        if report.is_empty() {
+            None
+        } else {
+            Some(format!("guards held for more than {:?}:{}", max, report))
+        }
+    }
+
+    fn too_many(&self) -> Option<String> {
+        let max = limit(&MAX_GUARDS)?;
+        // Report only when crossing the limit, not for each guard above it.
+        if self.entries.len() as u64 != max + 1 {
+            return None;
+        }
+        let mut report = format!("more than {} guards held:", max);
+        for entry in self.entries.values() {
+            report.push_str(&format!("\n  {}", entry.info));
+        }
+        Some(report)
+    }
+}
+
+thread_local! {
+    static REGISTRY: Arc<Mutex<Registry>> = Arc::default();
+}
+
+fn lock(registry: &Mutex<Registry>) -> MutexGuard<'_, Registry> {
+    // Nothing is left half-done during a panic.
+    registry.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn report(problem: Option<String>) {
+    if let Some(problem) = problem {
+        let message = format!("arc-swap ({:?}): {}", thread::current().id(), problem);
+        if PANIC.load(Ordering::Relaxed) && !thread::panicking() {
+            panic!("{}", message);
+        } else {
+            eprintln!("{}", message);
+        }
+    }
+}
+
+/// Lists the guards created by the current thread that are still alive.
+///
+/// They are sorted by the time of creation.
+pub fn dump() -> Vec<GuardInfo> {
+    REGISTRY
+        .try_with(|registry| lock(registry).dump())
+        .unwrap_or_default()
+}
+
+/// Checks the guards of the current thread against the limit on the hold duration.
+///
+/// This happens automatically whenever the thread creates or drops a guard, but a thread that
+/// holds a guard and does nothing else can be checked this way. Each guard is reported only once.
+pub fn check() {
+    let problem = REGISTRY
+        .try_with(|registry| lock(registry).overdue(None))
+        .ok()
+        .and_then(|problem| problem);
+    report(problem);
+}
+
+/// Registration of one guard, removed on drop.
+pub(crate) struct Tracked {
+    // None if created during the thread's shutdown, when we don't have the registry any more.
+    registry: Option<Arc<Mutex<Registry>>>,
+    id: u64,
+}
+
+impl Tracked {
+    #[track_caller]
+    pub(crate) fn new() -> Self {
+        let info = GuardInfo {
+            location: Location::caller(),
+            created: Instant::now(),
+            backtrace: if BACKTRACES.load(Ordering::Relaxed) {
+                Some(Arc::new(Backtrace::force_capture()))
+            } else {
+                None
+            },
+        };
+        let registered = REGISTRY.try_with(|registry| {
+            let mut locked = lock(registry);
+            let id = locked.next_id;
+            locked.next_id += 1;
+            let entry = Entry {
+                info,
+                reported: false,
+            };
+            locked.entries.insert(id, entry);
+            let problem = if LIMITS.load(Ordering::Relaxed) == 0 {
+                None
+            } else {
+                locked.too_many().or_else(|| locked.overdue(None))
+            };
+            (Arc::clone(registry), id, problem)
+        });
+        match registered {
+            Ok((registry, id, problem)) => {
+                let tracked = Tracked {
+                    registry: Some(registry),
+                    id,
+                };
+                // Panicking here unregisters it again.
+                report(problem);
+                tracked
+            }
+            Err(_) => Tracked {
+                registry: None,
+                id: 0,
+            },
+        }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        if let Some(registry) = &self.registry {
+            let problem = {
+                let mut locked = lock(registry);
+                let entry = locked.entries.remove(&self.id);
+                if LIMITS.load(Ordering::Relaxed) == 0 {
+                    None
+                } else {
+                    locked.overdue(entry.as_ref())
+                }
+            };
+            report(problem);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ArcSwap;
+
+    // The tests of the limits are in tests/debug-guards.rs, they would interfere with other tests
+    // running in parallel here.
+
+    #[test]
+    fn dump_locations() {
+        let shared = ArcSwap::from_pointee(42);
+        let before = dump().len();
+        let first = shared.load();
+        let line = line!() - 1;
+        let second = crate::Guard::<_>::from_inner(shared.load_full());
+        let infos = dump();
+        assert_eq!(before + 2, infos.len());
+        assert_eq!(file!(), infos[before].location().file());
+        assert_eq!(line, infos[before].location().line());
+        assert_eq!(line + 2, infos[before + 1].location().line());
+        drop(first);
+        drop(second);
+        assert_eq!(before, dump().len());
+    }
+
+    /// The location is the caller's even through the access adaptors.
+    #[test]
+    fn through_access() {
+        use crate::access::{Access, DynAccess, Map};
+        use crate::lazy::LazyArcSwap;
+
+        let shared = ArcSwap::from_pointee((42, 43));
+        let map = Map::new(&shared, |v: &(usize, usize)| &v.0);
+        let lazy = LazyArcSwap::new(|| 42);
+        let before = dump().len();
+        let (mapped, mapped_line) = (Access::load(&map), line!());
+        let (dynamic, dynamic_line) = (DynAccess::load(&map), line!());
+        let (lazy, lazy_line) = (lazy.load(), line!());
+        let infos = dump();
+        assert_eq!(before + 3, infos.len());
+        let lines = [mapped_line, dynamic_line, lazy_line];
+        for (info, line) in infos[before..].iter().zip(&lines) {
+            assert_eq!(file!(), info.location().file());
+            assert_eq!(*line, info.location().line());
+        }
+        assert_eq!(42, *mapped);
+        assert_eq!(42, *dynamic);
+        assert_eq!(42, *lazy);
+    }
+
+    #[test]
+    fn dropped_elsewhere() {
+        let shared = ArcSwap::from_pointee(42);
+        let guard = shared.load();
+        assert_eq!(1, dump().len());
+        thread::spawn(move || {
+            assert!(dump().is_empty());
+            drop(guard);
+        })
+        .join()
+        .unwrap();
+        assert!(dump().is_empty());
+    }
+}
@@ -31,6 +31,9 @@
 //! If too many [`Guard`]s are kept around, the performance might be poor. These are not intended
 //! to be stored in data structures or used across async yield points.
 //!
+//! To find out which code holds them, the `debug-guards` feature records where each guard was
+//! created and can report threads holding too many of them (see the `debug_guards` module).
+//!
 //! [`ArcSwap`]: crate::ArcSwap
 //! [`Guard`]: crate::Guard
 //! [`AtomicPtr`]: std::sync::atomic::AtomicPtr
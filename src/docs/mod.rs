@@ -34,6 +34,23 @@
 //! example](https://opensource.apple.com/source/clang/clang-800.0.38/src/projects/compiler-rt/lib/builtins/emutls.c.auto.html)
 //! from Clang.
 //!
+//! The `custom-thread-local` feature also builds arc-swap for `no_std` targets (it still needs
+//! `alloc`), but works on stable Rust. Instead of using thread locals, the user registers a
+//! function that provides the per-thread (or per-task, or per-CPU) storage. See the
+//! `local_storage` module (available only with the feature) for details. It can't be combined
+//! with `experimental-thread-local`.
+//!
+//! Targets without native atomic compare and swap (like `thumbv6m` or some RISC-V
+//! microcontrollers) are supported with the `portable-atomic` feature, usually together with
+//! `custom-thread-local`. The atomic operations then come from the
+//! [`portable-atomic`](https://docs.rs/portable-atomic) crate. On such targets `alloc` has no
+//! `Arc`, so the one from [`portable-atomic-util`](https://docs.rs/portable-atomic-util) is used
+//! instead (the [`ArcSwap`][crate::ArcSwap] holds that one there). The `portable-atomic` crate
+//! needs to be told how to emulate the operations, either by enabling its `critical-section`
+//! feature (and providing a [`critical-section`](https://docs.rs/critical-section) implementation)
+//! or, on single-core systems, with the `--cfg portable_atomic_unsafe_assume_single_core` flag. See
+//! its documentation for details.
+//!
 //! # Minimal compiler version
 //!
 //! The `1` versions will compile on all compilers supporting the 2018 edition. Note that this
@@ -78,6 +78,9 @@
 //! }
 //! ```
 //!
+//! For the common case of reloading the configuration from a file, the `reload` module (behind
+//! the `reload` feature) provides a ready-made version of the configuration thread.
+//!
 //! # Consistent snapshots
 //!
 //! While one probably wants to get a fresh instance every time a work chunk is available,
@@ -0,0 +1,350 @@
+//! A storage remembering the last few values, for rollbacks.
+//!
+//! When a bad configuration gets pushed, it is handy to be able to go back to the previous one
+//! right away. The [`HistoryArcSwap`] keeps a bounded history of the values stored in it (together
+//! with the time they were stored and an optional label) and allows to
+//! [`rollback`][HistoryArcSwap::rollback] to the previous one.
+//!
+//! The readers use the usual [`load`][HistoryArcSwap::load] from the underlying [`ArcSwap`] and
+//! are not slowed down in any way. The writers are serialized through a lock. The values in the
+//! history are kept alive, so they are not freed until they fall out of the history.
+//!
+//! This is available only with the standard library (it is not available with the
+//! `experimental-thread-local` and `custom-thread-local` features).
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::sync::Arc;
+//!
+//! use arc_swap::history::HistoryArcSwap;
+//!
+//! let config = HistoryArcSwap::new(Arc::new("good".to_owned()), 10);
+//! config.store_labeled(Arc::new("bad".to_owned()), "deploy #42");
+//! assert_eq!("bad", **config.load());
+//! assert_eq!(Some("deploy #42"), config.history()[0].label());
+//!
+//! // Oops
+//! config.rollback();
+//! assert_eq!("good", **config.load());
+//! ```
+
+use core::fmt::{Debug, Formatter, Result as FmtResult};
+use std::collections::VecDeque;
+use std::string::String;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+use std::vec::Vec;
+
+use crate::cache::{Access as CacheAccess, Cache};
+use crate::imports::Arc;
+use crate::{ArcSwap, Guard};
+
+/// One remembered value.
+#[derive(Debug)]
+pub struct Entry<T> {
+    value: Arc<T>,
+    stored_at: SystemTime,
+    label: Option<String>,
+}
+
+// Derive would require T: Clone
+impl<T> Clone for Entry<T> {
+    fn clone(&self) -> Self {
+        Entry {
+            value: Arc::clone(&self.value),
+            stored_at: self.stored_at,
+            label: self.label.clone(),
+        }
+    }
+}
+
+impl<T> Entry<T> {
+    /// The value itself.
+    pub fn value(&self) -> &Arc<T> {
+        &self.value
+    }
+
+    /// When the value was stored.
+    pub fn stored_at(&self) -> SystemTime {
+        self.stored_at
+    }
+
+    /// The label it was stored with, if any.
+    #[allow(clippy::option_as_ref_deref)] // as_deref needs a newer compiler
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_ref().map(String::as_str)
+    }
+}
+
+/// An [`ArcSwap`] that remembers the last few values stored in it.
+///
+/// See the [module documentation](index.html) for details.
+#[derive(Debug)]
+pub struct HistoryArcSwap<T> {
+    current: ArcSwap<T>,
+    /// The newest one is at the front and it is the one in current.
+    history: Mutex<VecDeque<Entry<T>>>,
+    capacity: usize,
+}
+
+impl<T> HistoryArcSwap<T> {
+    /// Creates a new storage, remembering up to `capacity` values.
+    ///
+    /// The current value counts into the capacity, so capacity of 1 means no rollback is
+    /// possible.
+    ///
+    /// # Panics
+    ///
+    /// If the `capacity` is 0.
+    pub fn new(val: Arc<T>, capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "The history needs to hold at least the current value"
+        );
+        let mut history = VecDeque::with_capacity(capacity);
+        history.push_front(Entry {
+            value: Arc::clone(&val),
+            stored_at: SystemTime::now(),
+            label: None,
+        });
+        HistoryArcSwap {
+            current: ArcSwap::new(val),
+            history: Mutex::new(history),
+            capacity,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<Entry<T>>> {
+        // Nothing can panic while the lock is held in a way that would leave the history broken.
+        self.history.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Loads the current value.
+    ///
+    /// See [`ArcSwapAny::load`][crate::ArcSwapAny::load].
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    #[inline]
+    pub fn load(&self) -> Guard<Arc<T>> {
+        self.current.load()
+    }
+
+    /// Loads the current value as a full `Arc`.
+    pub fn load_full(&self) -> Arc<T> {
+        self.current.load_full()
+    }
+
+    /// The underlying storage.
+    ///
+    /// It can only be read from, storing into it directly would bypass the history.
+    pub fn storage(&self) -> StorageView<'_, T> {
+        StorageView(&self.current)
+    }
+
+    /// Creates a [`Cache`] over the underlying storage.
+    pub fn cache(&self) -> HistoryCache<'_, T> {
+        HistoryCache(Cache::new(&self.current))
+    }
+
+    /// Stores the entry as is, including its time.
+    fn store_entry(&self, entry: Entry<T>) {
+        let mut history = self.lock();
+        self.current.swap(Arc::clone(&entry.value));
+        history.push_front(entry);
+        history.truncate(self.capacity);
+    }
+
+    /// Stores a new value, remembering the old one.
+    pub fn store(&self, val: Arc<T>) {
+        self.store_entry(Entry {
+            value: val,
+            stored_at: SystemTime::now(),
+            label: None,
+        });
+    }
+
+    /// Stores a new value with a label.
+    ///
+    /// The label can be used later on to find the value in the [`history`][Self::history].
+    pub fn store_labeled<L: Into<String>>(&self, val: Arc<T>, label: L) {
+        self.store_entry(Entry {
+            value: val,
+            stored_at: SystemTime::now(),
+            label: Some(label.into()),
+        });
+    }
+
+    /// Goes back to the previous value.
+    ///
+    /// The current value is forgotten. Returns the restored value, or `None` if there's nothing
+    /// to go back to (in which case nothing happens).
+    pub fn rollback(&self) -> Option<Arc<T>> {
+        let mut history = self.lock();
+        if history.len() < 2 {
+            return None;
+        }
+        history.pop_front();
+        let previous = Arc::clone(&history[0].value);
+        self.current.swap(Arc::clone(&previous));
+        Some(previous)
+    }
+
+    /// A snapshot of the remembered values.
+    ///
+    /// The newest (current) one is first.
+    pub fn history(&self) -> Vec<Entry<T>> {
+        self.lock().iter().cloned().collect()
+    }
+}
+
+/// A read-only view of the storage inside a [`HistoryArcSwap`].
+///
+/// See [`HistoryArcSwap::storage`].
+pub struct StorageView<'a, T>(&'a ArcSwap<T>);
+
+impl<T> StorageView<'_, T> {
+    /// Loads the current value.
+    ///
+    /// See [`ArcSwapAny::load`][crate::ArcSwapAny::load].
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    #[inline]
+    pub fn load(&self) -> Guard<Arc<T>> {
+        self.0.load()
+    }
+
+    /// Loads the current value as a full `Arc`.
+    pub fn load_full(&self) -> Arc<T> {
+        self.0.load_full()
+    }
+}
+
+impl<T> Clone for StorageView<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for StorageView<'_, T> {}
+
+impl<T: Debug> Debug for StorageView<'_, T> {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        formatter
+            .debug_tuple("StorageView")
+            .field(&self.load())
+            .finish()
+    }
+}
+
+/// A [`Cache`] over the storage inside a [`HistoryArcSwap`].
+///
+/// Unlike the plain [`Cache`], it doesn't give access to the storage itself. See
+/// [`HistoryArcSwap::cache`].
+#[derive(Clone)]
+pub struct HistoryCache<'a, T>(Cache<&'a ArcSwap<T>, Arc<T>>);
+
+impl<T> HistoryCache<'_, T> {
+    /// Loads the currently held value.
+    ///
+    /// See [`Cache::load`].
+    #[inline]
+    pub fn load(&mut self) -> &Arc<T> {
+        self.0.load()
+    }
+}
+
+impl<T> CacheAccess<T> for HistoryCache<'_, T> {
+    fn load(&mut self) -> &T {
+        self.0.load()
+    }
+}
+
+impl<T: Debug> Debug for HistoryCache<'_, T> {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        formatter
+            .debug_tuple("HistoryCache")
+            .field(&self.0)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn bounded() {
+        let shared = HistoryArcSwap::new(Arc::new(0), 3);
+        for i in 1..10 {
+            shared.store(Arc::new(i));
+        }
+        let values = shared
+            .history()
+            .iter()
+            .map(|e| **e.value())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![9, 8, 7], values);
+    }
+
+    #[test]
+    fn rollback() {
+        let shared = HistoryArcSwap::new(Arc::new(0), 3);
+        assert!(shared.rollback().is_none());
+        shared.store_labeled(Arc::new(1), "one");
+        shared.store(Arc::new(2));
+        assert_eq!(2, **shared.load());
+        assert_eq!(1, *shared.rollback().unwrap());
+        assert_eq!(1, **shared.load());
+        let history = shared.history();
+        assert_eq!(Some("one"), history[0].label());
+        assert_eq!(0, *shared.rollback().unwrap());
+        assert!(shared.rollback().is_none());
+        assert_eq!(0, **shared.load());
+    }
+
+    /// The entries keep the time they were stored at, even through a rollback.
+    ///
+    /// The system clock can go backwards, so the times are given explicitly.
+    #[test]
+    fn stored_at() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let shared = HistoryArcSwap::new(Arc::new(0), 3);
+        for i in 1..4 {
+            shared.store_entry(Entry {
+                value: Arc::new(i),
+                stored_at: at(i),
+                label: None,
+            });
+        }
+        shared.rollback().unwrap();
+        let times = shared
+            .history()
+            .iter()
+            .map(Entry::stored_at)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![at(2), at(1)], times);
+    }
+
+    /// The values fallen out of the history are released.
+    #[test]
+    fn releases() {
+        let first = Arc::new(0);
+        let shared = HistoryArcSwap::new(Arc::clone(&first), 2);
+        shared.store(Arc::new(1));
+        assert_eq!(2, Arc::strong_count(&first));
+        shared.store(Arc::new(2));
+        assert_eq!(1, Arc::strong_count(&first));
+    }
+
+    #[test]
+    fn storage_view() {
+        let shared = HistoryArcSwap::new(Arc::new(0), 2);
+        let view = shared.storage();
+        let mut cache = shared.cache();
+        shared.store(Arc::new(1));
+        assert_eq!(1, **view.load());
+        assert_eq!(1, **cache.load());
+        assert_eq!(1, *CacheAccess::load(&mut cache));
+    }
+}
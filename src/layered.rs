@@ -0,0 +1,334 @@
+//! A value composed of a base and layers of overrides.
+//!
+//! Configuration often comes from several places ‒ there are some defaults, then a configuration
+//! file, environment variables, and maybe some overrides set at runtime by an administrator. Each
+//! of them is refreshed independently and the effective configuration is the result of merging
+//! all of them together.
+//!
+//! The [`Layered`] holds the base value and an ordered list of the override layers. Each of them
+//! is its own storage and can be updated independently. The merged result is provided through
+//! [`load`][Layered::load] (or the [`Access`] trait). It is computed lazily ‒ the merge happens
+//! only when some of the parts changed since the last time, similar to how the [`Cache`] checks
+//! for changes.
+//!
+//! There are two kinds of layers:
+//!
+//! * The permanent ones, created by [`add_layer`][Layered::add_layer]. These stay for the whole
+//!   life of the [`Layered`] (but can be empty).
+//! * The temporary ones, created by [`push`][Layered::push]. These are removed once the returned
+//!   [`LayerGuard`] is dropped.
+//!
+//! The layers are applied in the order they were added (both kinds together), with the later
+//! ones taking precedence.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::sync::Arc;
+//!
+//! use arc_swap::layered::Layered;
+//!
+//! #[derive(Clone, Debug, Default)]
+//! struct Config {
+//!     port: u16,
+//!     debug: bool,
+//! }
+//!
+//! #[derive(Default)]
+//! struct Overrides {
+//!     port: Option<u16>,
+//!     debug: Option<bool>,
+//! }
+//!
+//! let config = Layered::new(Config { port: 80, debug: false }, |cfg: &Config, o: &Overrides| {
+//!     Config {
+//!         port: o.port.unwrap_or(cfg.port),
+//!         debug: o.debug.unwrap_or(cfg.debug),
+//!     }
+//! });
+//!
+//! let file = config.add_layer(None);
+//! let admin = config.add_layer(None);
+//! assert_eq!(80, config.load().port);
+//!
+//! file.store(Some(Arc::new(Overrides { port: Some(8080), ..Default::default() })));
+//! assert_eq!(8080, config.load().port);
+//!
+//! {
+//!     let _debugging = config.push(Overrides { debug: Some(true), ..Default::default() });
+//!     assert!(config.load().debug);
+//! }
+//! assert!(!config.load().debug);
+//!
+//! admin.store(Some(Arc::new(Overrides { port: Some(1234), ..Default::default() })));
+//! assert_eq!(1234, config.load().port);
+//! ```
+//!
+//! [`Cache`]: crate::cache::Cache
+
+use core::ops::Deref;
+
+use crate::access::Access;
+use crate::imports::{Arc, Vec};
+use crate::ref_cnt::RefCnt;
+use crate::{ArcSwap, ArcSwapOption, Guard};
+
+/// One layer of overrides.
+type Layer<L> = Arc<ArcSwapOption<L>>;
+
+/// The merged value, together with all the parts it was computed from.
+///
+/// The parts are held, not only their addresses, so they can't be freed and the address reused
+/// by a different value.
+struct Merged<T, L> {
+    value: Arc<T>,
+    base: Arc<T>,
+    layers: Arc<Vec<Layer<L>>>,
+    sources: Vec<Option<Arc<L>>>,
+}
+
+impl<T, L> Merged<T, L> {
+    /// Checks if this is still the up to date merge of the current parts.
+    fn is_current(&self, base: &Arc<T>, layers: &Arc<Vec<Layer<L>>>) -> bool {
+        Arc::ptr_eq(&self.base, base)
+            && Arc::ptr_eq(&self.layers, layers)
+            && layers
+                .iter()
+                .zip(&self.sources)
+                .all(|(layer, source)| RefCnt::as_ptr(&*layer.load()) == RefCnt::as_ptr(source))
+    }
+}
+
+/// The guard returned by [`Layered::load`].
+///
+/// It dereferences to the merged value.
+pub struct LayeredGuard<T, L>(Guard<Arc<Merged<T, L>>>);
+
+impl<T, L> Deref for LayeredGuard<T, L> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+/// A base value merged with layers of overrides.
+///
+/// See the [module documentation](index.html) for details.
+pub struct Layered<T, L, F> {
+    base: ArcSwap<T>,
+    layers: ArcSwap<Vec<Layer<L>>>,
+    merged: ArcSwap<Merged<T, L>>,
+    merge: F,
+}
+
+impl<T, L, F> Layered<T, L, F>
+where
+    F: Fn(&T, &L) -> T,
+{
+    /// Creates a new instance with the given base value and no layers.
+    ///
+    /// The `merge` function produces a value with a layer of overrides applied on top of it.
+    pub fn new(base: T, merge: F) -> Self {
+        let base = Arc::new(base);
+        let layers = Arc::new(Vec::new());
+        let merged = Merged {
+            value: Arc::clone(&base),
+            base: Arc::clone(&base),
+            layers: Arc::clone(&layers),
+            sources: Vec::new(),
+        };
+        Layered {
+            base: ArcSwap::new(base),
+            layers: ArcSwap::new(layers),
+            merged: ArcSwap::from_pointee(merged),
+            merge,
+        }
+    }
+
+    /// The storage of the base value.
+    ///
+    /// It can be used to update the base value.
+    pub fn base(&self) -> &ArcSwap<T> {
+        &self.base
+    }
+
+    /// Adds a new permanent layer on top of the existing ones.
+    ///
+    /// The returned storage can be used to set or update the overrides in the layer. An empty
+    /// layer is simply skipped during the merge.
+    pub fn add_layer(&self, initial: Option<L>) -> Arc<ArcSwapOption<L>> {
+        let layer = Arc::new(ArcSwapOption::new(initial.map(Arc::new)));
+        self.layers.rcu(|layers| {
+            let mut layers = Vec::clone(layers);
+            layers.push(Arc::clone(&layer));
+            layers
+        });
+        layer
+    }
+
+    /// Pushes a temporary layer on top of the existing ones.
+    ///
+    /// The layer is removed once the returned guard is dropped. It is possible to drop the guards
+    /// in a different order than they were created.
+    pub fn push(&self, layer: L) -> LayerGuard<'_, T, L, F> {
+        let layer = self.add_layer(Some(layer));
+        LayerGuard {
+            layered: self,
+            layer,
+        }
+    }
+
+    /// Loads the merged value.
+    ///
+    /// If any of the parts changed since the last time, the value is merged anew.
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    pub fn load(&self) -> LayeredGuard<T, L> {
+        let cached = self.merged.load();
+        let base = self.base.load();
+        let layers = self.layers.load();
+        if cached.is_current(&base, &layers) {
+            return LayeredGuard(cached);
+        }
+
+        let base = Guard::into_inner(base);
+        let layers = Guard::into_inner(layers);
+        let sources = layers
+            .iter()
+            .map(|layer| layer.load_full())
+            .collect::<Vec<_>>();
+        let value = sources
+            .iter()
+            .filter_map(Option::as_ref)
+            .fold(Arc::clone(&base), |value, layer| {
+                Arc::new((self.merge)(&value, layer))
+            });
+        let merged = Arc::new(Merged {
+            value,
+            base,
+            layers,
+            sources,
+        });
+        // If someone else already put something else in, don't overwrite it. It is likely more
+        // up to date and if not, whoever comes next will notice.
+        self.merged.compare_and_swap(&cached, Arc::clone(&merged));
+        LayeredGuard(Guard::from_inner(merged))
+    }
+
+    fn remove_layer(&self, layer: &Layer<L>) {
+        self.layers.rcu(|layers| {
+            layers
+                .iter()
+                .filter(|l| !Arc::ptr_eq(l, layer))
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+    }
+}
+
+impl<T, L, F> Access<T> for Layered<T, L, F>
+where
+    F: Fn(&T, &L) -> T,
+{
+    type Guard = LayeredGuard<T, L>;
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    fn load(&self) -> Self::Guard {
+        self.load()
+    }
+}
+
+/// A temporary layer of a [`Layered`].
+///
+/// Created by [`Layered::push`]. The layer is removed once this is dropped.
+pub struct LayerGuard<'a, T, L, F>
+where
+    F: Fn(&T, &L) -> T,
+{
+    layered: &'a Layered<T, L, F>,
+    layer: Layer<L>,
+}
+
+impl<T, L, F> LayerGuard<'_, T, L, F>
+where
+    F: Fn(&T, &L) -> T,
+{
+    /// The storage of the layer, to update the overrides in it.
+    pub fn layer(&self) -> &ArcSwapOption<L> {
+        &self.layer
+    }
+}
+
+impl<T, L, F> Drop for LayerGuard<'_, T, L, F>
+where
+    F: Fn(&T, &L) -> T,
+{
+    fn drop(&mut self) {
+        self.layered.remove_layer(&self.layer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Merge = fn(&Vec<u8>, &u8) -> Vec<u8>;
+
+    // Needs to fit the Fn(&T, &L) -> T signature
+    #[allow(clippy::ptr_arg)]
+    fn append(base: &Vec<u8>, layer: &u8) -> Vec<u8> {
+        let mut result = base.clone();
+        result.push(*layer);
+        result
+    }
+
+    fn layered() -> Layered<Vec<u8>, u8, Merge> {
+        Layered::new(vec![0], append)
+    }
+
+    #[test]
+    fn layers_in_order() {
+        let layered = layered();
+        let first = layered.add_layer(Some(1));
+        let second = layered.add_layer(None);
+        assert_eq!(vec![0, 1], *layered.load());
+        second.store(Some(Arc::new(2)));
+        assert_eq!(vec![0, 1, 2], *layered.load());
+        first.store(None);
+        assert_eq!(vec![0, 2], *layered.load());
+        layered.base().store(Arc::new(vec![42]));
+        assert_eq!(vec![42, 2], *layered.load());
+    }
+
+    /// Nothing is merged again if nothing changed.
+    #[test]
+    fn lazy() {
+        let layered = layered();
+        let _layer = layered.add_layer(Some(1));
+        let first = layered.load();
+        let second = layered.load();
+        assert!(core::ptr::eq(&*first, &*second));
+    }
+
+    #[test]
+    fn push_pop() {
+        let layered = layered();
+        let a = layered.push(1);
+        let b = layered.push(2);
+        assert_eq!(vec![0, 1, 2], *layered.load());
+        b.layer().store(Some(Arc::new(3)));
+        assert_eq!(vec![0, 1, 3], *layered.load());
+        drop(a);
+        assert_eq!(vec![0, 3], *layered.load());
+        drop(b);
+        assert_eq!(vec![0], *layered.load());
+    }
+
+    /// The guard keeps the old merged value even after it changes.
+    #[test]
+    fn guard_stays() {
+        let layered = layered();
+        let guard = layered.load();
+        let _layer = layered.push(1);
+        assert_eq!(vec![0], *guard);
+        assert_eq!(vec![0, 1], *Access::load(&layered));
+    }
+}
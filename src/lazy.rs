@@ -0,0 +1,451 @@
+//! Storages initialized on the first load.
+//!
+//! A common pattern is to start with an empty [`ArcSwapOption`] and to fill it in on the first
+//! use. Written by hand, the „if it's `None`, store a new value“ is easy to get wrong ‒ two threads
+//! may both see the `None` and the second store overwrites the value the first thread already
+//! handed out.
+//!
+//! The [`get_or_init`][ArcSwapAny::get_or_init] method does this correctly, using
+//! [`compare_and_swap`][ArcSwapAny::compare_and_swap] from `None`. The [`LazyArcSwap`] wraps the
+//! storage together with the factory, so it can be used similar to a plain [`ArcSwap`].
+//!
+//! # Racing initialization
+//!
+//! Once a value is stored, it is never replaced by the initialization. All threads see the same
+//! value, the first one successfully stored.
+//!
+//! Only one thread at a time runs the factory of a storage. If several threads do the very first
+//! load at the same time, the others wait for it to finish and use its value, so the factory runs
+//! exactly once. It runs again only if it panics or if `None` is stored into the storage later.
+//! The loads of an already initialized storage don't wait for anything.
+//!
+//! The storages being initialized are kept in a global list (with the standard library, protected
+//! by a mutex; without it, by a spin lock and the others spin while waiting). The factory may
+//! initialize other storages, but not the same one ‒ that panics with the standard library and
+//! hangs without it.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::sync::Arc;
+//!
+//! use arc_swap::lazy::LazyArcSwap;
+//!
+//! let config = LazyArcSwap::new(|| "default".to_owned());
+//! assert!(!config.is_initialized());
+//! assert_eq!("default", *config.load());
+//! assert!(config.is_initialized());
+//!
+//! config.store(Arc::new("updated".to_owned()));
+//! assert_eq!("updated", *config.load());
+//! ```
+//!
+//! [`ArcSwap`]: crate::ArcSwap
+
+use core::fmt::{Debug, Formatter, Result as FmtResult};
+use core::ops::Deref;
+use core::ptr;
+#[cfg(any(feature = "experimental-thread-local", feature = "custom-thread-local"))]
+use core::sync::atomic;
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::Ordering;
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+use std::thread::{self, ThreadId};
+
+#[cfg(any(feature = "experimental-thread-local", feature = "custom-thread-local"))]
+use core::cell::UnsafeCell;
+
+use crate::imports::{Arc, Box, Vec};
+use crate::strategy::{CaS, DefaultStrategy, Strategy};
+#[cfg(any(feature = "experimental-thread-local", feature = "custom-thread-local"))]
+use crate::sync::{AtomicPtr, AtomicUsize};
+use crate::{ArcSwapAny, ArcSwapOption, Guard};
+
+/// The storages being initialized and who does it.
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+struct InProgress {
+    storages: Mutex<Vec<(usize, ThreadId)>>,
+    done: Condvar,
+}
+
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+impl InProgress {
+    fn new() -> Self {
+        InProgress {
+            storages: Mutex::new(Vec::new()),
+            done: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<(usize, ThreadId)>> {
+        // No user code runs under the lock, nothing can be left broken by a panic.
+        self.storages.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn start(&self, storage: usize) {
+        let me = thread::current().id();
+        let mut storages = self.lock();
+        while let Some(&(_, owner)) = storages.iter().find(|&&(s, _)| s == storage) {
+            assert!(owner != me, "Recursive initialization of a lazy storage");
+            storages = self
+                .done
+                .wait(storages)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        storages.push((storage, me));
+    }
+
+    fn finish(&self, storage: usize) {
+        self.lock().retain(|&(s, _)| s != storage);
+        self.done.notify_all();
+    }
+}
+
+/// The storages being initialized.
+#[cfg(any(feature = "experimental-thread-local", feature = "custom-thread-local"))]
+struct InProgress {
+    locked: AtomicUsize,
+    storages: UnsafeCell<Vec<usize>>,
+}
+
+// Accessed only with the spin lock held.
+#[cfg(any(feature = "experimental-thread-local", feature = "custom-thread-local"))]
+unsafe impl Sync for InProgress {}
+
+#[cfg(any(feature = "experimental-thread-local", feature = "custom-thread-local"))]
+impl InProgress {
+    fn new() -> Self {
+        InProgress {
+            locked: AtomicUsize::new(0),
+            storages: UnsafeCell::new(Vec::new()),
+        }
+    }
+
+    fn with<R, F: FnOnce(&mut Vec<usize>) -> R>(&self, f: F) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            atomic::spin_loop_hint();
+        }
+        // We hold the lock. The closures don't panic.
+        let result = f(unsafe { &mut *self.storages.get() });
+        self.locked.store(0, Ordering::Release);
+        result
+    }
+
+    fn start(&self, storage: usize) {
+        while !self.with(|storages| {
+            let free = !storages.contains(&storage);
+            if free {
+                storages.push(storage);
+            }
+            free
+        }) {
+            atomic::spin_loop_hint();
+        }
+    }
+
+    fn finish(&self, storage: usize) {
+        self.with(|storages| storages.retain(|&s| s != storage));
+    }
+}
+
+/// Lazily allocated on the first use and never freed.
+///
+/// Not a `static` directly, because creating the mutex in a static needs a newer compiler.
+static IN_PROGRESS: AtomicPtr<InProgress> = AtomicPtr::new(ptr::null_mut());
+
+fn in_progress() -> &'static InProgress {
+    let mut current = IN_PROGRESS.load(Ordering::Acquire);
+    if current.is_null() {
+        let new = Box::into_raw(Box::new(InProgress::new()));
+        current = match IN_PROGRESS.compare_exchange(
+            ptr::null_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new,
+            Err(other) => {
+                // Someone was faster, use theirs.
+                drop(unsafe { Box::from_raw(new) });
+                other
+            }
+        };
+    }
+    // Once set, it is never changed nor freed.
+    unsafe { &*current }
+}
+
+/// The current thread initializes the storage on the address, until dropped.
+struct Initializing(usize);
+
+impl Initializing {
+    /// Waits until nobody else initializes the storage and takes it over.
+    fn start(storage: usize) -> Self {
+        in_progress().start(storage);
+        Initializing(storage)
+    }
+}
+
+impl Drop for Initializing {
+    fn drop(&mut self) {
+        in_progress().finish(self.0);
+    }
+}
+
+/// A guard returned from the lazy loads.
+///
+/// This is like a [`Guard`] of the `Option<Arc<T>>`, but the value is known to be there, so it
+/// dereferences directly to the `T`.
+pub struct LazyGuard<T, S: Strategy<Option<Arc<T>>> = DefaultStrategy>(Guard<Option<Arc<T>>, S>);
+
+impl<T, S: Strategy<Option<Arc<T>>>> LazyGuard<T, S> {
+    /// Converts the guard into a full `Arc`.
+    ///
+    /// See [`Guard::into_inner`].
+    pub fn into_inner(lease: Self) -> Arc<T> {
+        Guard::into_inner(lease.0).expect("Lazy guard holds a value")
+    }
+}
+
+impl<T, S: Strategy<Option<Arc<T>>>> Deref for LazyGuard<T, S> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &T {
+        self.0.as_ref().expect("Lazy guard holds a value")
+    }
+}
+
+impl<T: Debug, S: Strategy<Option<Arc<T>>>> Debug for LazyGuard<T, S> {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        self.deref().fmt(formatter)
+    }
+}
+
+impl<T, S> ArcSwapAny<Option<Arc<T>>, S>
+where
+    S: CaS<Option<Arc<T>>>,
+{
+    /// Loads the value, initializing it first if it is `None`.
+    ///
+    /// The `init` is called only if the storage is empty. Other threads initializing the same
+    /// storage at the same time wait for it. If some other thread stores a value while `init`
+    /// runs, that value is kept and the result of `init` is thrown away. See the
+    /// [`lazy`][crate::lazy] module for details.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use arc_swap::ArcSwapOption;
+    ///
+    /// let shared = ArcSwapOption::<usize>::empty();
+    /// assert_eq!(42, *shared.get_or_init(|| 42));
+    /// // Already initialized, the closure is not called again.
+    /// assert_eq!(42, *shared.get_or_init(|| unreachable!()));
+    /// ```
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    pub fn get_or_init<F>(&self, init: F) -> LazyGuard<T, S>
+    where
+        F: FnOnce() -> T,
+    {
+        let current = self.load();
+        if current.is_some() {
+            return LazyGuard(current);
+        }
+        let _initializing = Initializing::start(self as *const Self as usize);
+        // Someone else might have finished the initialization before we started ours.
+        let current = self.load();
+        if current.is_some() {
+            return LazyGuard(current);
+        }
+        let new = Arc::new(init());
+        let previous = self.compare_and_swap(&*current, Some(Arc::clone(&new)));
+        if previous.is_some() {
+            // Someone was faster, use their value instead.
+            LazyGuard(previous)
+        } else {
+            LazyGuard(Guard::from_inner(Some(new)))
+        }
+    }
+}
+
+/// A storage initialized on the first load.
+///
+/// See the [module documentation](index.html) for details.
+pub struct LazyArcSwap<T, F, S: Strategy<Option<Arc<T>>> = DefaultStrategy> {
+    storage: ArcSwapAny<Option<Arc<T>>, S>,
+    init: F,
+}
+
+impl<T, F> LazyArcSwap<T, F>
+where
+    F: Fn() -> T,
+{
+    /// Creates a new, not yet initialized, storage.
+    pub fn new(init: F) -> Self {
+        LazyArcSwap {
+            storage: ArcSwapOption::empty(),
+            init,
+        }
+    }
+}
+
+impl<T, F, S> LazyArcSwap<T, F, S>
+where
+    F: Fn() -> T,
+    S: CaS<Option<Arc<T>>>,
+{
+    /// Loads the value, initializing it if this is the first load.
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    #[inline]
+    pub fn load(&self) -> LazyGuard<T, S> {
+        self.storage.get_or_init(&self.init)
+    }
+
+    /// Loads the value as a full `Arc`, initializing it if needed.
+    pub fn load_full(&self) -> Arc<T> {
+        LazyGuard::into_inner(self.load())
+    }
+
+    /// Replaces the value.
+    ///
+    /// This works with both initialized and not yet initialized storage. In the latter case, the
+    /// factory is never called.
+    pub fn store(&self, val: Arc<T>) {
+        self.storage.store(Some(val));
+    }
+
+    /// Checks if the value was already initialized (or stored).
+    pub fn is_initialized(&self) -> bool {
+        self.storage.load().is_some()
+    }
+
+    /// The underlying storage.
+    ///
+    /// It is `None` until the value is initialized. Storing `None` into it makes the next load
+    /// initialize the value again.
+    pub fn storage(&self) -> &ArcSwapAny<Option<Arc<T>>, S> {
+        &self.storage
+    }
+}
+
+impl<T, F, S> Debug for LazyArcSwap<T, F, S>
+where
+    T: Debug,
+    S: Strategy<Option<Arc<T>>>,
+{
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        formatter
+            .debug_tuple("LazyArcSwap")
+            .field(&self.storage.load())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::sync::atomic::AtomicUsize;
+    use core::time::Duration;
+    use std::panic::{self, AssertUnwindSafe};
+
+    use crossbeam_utils::thread;
+
+    use super::*;
+    use crate::access::Access;
+    use crate::imports::Vec;
+
+    #[test]
+    fn get_or_init() {
+        let shared = ArcSwapOption::empty();
+        assert_eq!(1, *shared.get_or_init(|| 1));
+        assert_eq!(1, *shared.get_or_init(|| 2));
+        shared.store(Some(Arc::new(3)));
+        assert_eq!(3, *shared.get_or_init(|| 4));
+    }
+
+    #[test]
+    fn lazy_once() {
+        let calls = AtomicUsize::new(0);
+        let lazy = LazyArcSwap::new(|| calls.fetch_add(1, Ordering::Relaxed));
+        assert_eq!(0, calls.load(Ordering::Relaxed));
+        assert_eq!(0, *lazy.load());
+        assert_eq!(0, *Access::load(&lazy));
+        assert_eq!(1, calls.load(Ordering::Relaxed));
+
+        lazy.store(Arc::new(42));
+        assert_eq!(42, *lazy.load_full());
+        assert_eq!(1, calls.load(Ordering::Relaxed));
+    }
+
+    /// Storing before the first load skips the factory altogether.
+    #[test]
+    fn store_first() {
+        let lazy = LazyArcSwap::new(|| -> usize { unreachable!() });
+        lazy.store(Arc::new(1));
+        assert!(lazy.is_initialized());
+        assert_eq!(1, *lazy.load());
+    }
+
+    /// All the threads see the same value, even if they race on the initialization. The factory
+    /// runs just once.
+    #[test]
+    fn race() {
+        let calls = AtomicUsize::new(0);
+        let lazy = LazyArcSwap::new(|| {
+            // Give the others the time to pile up.
+            std::thread::sleep(Duration::from_millis(10));
+            calls.fetch_add(1, Ordering::Relaxed)
+        });
+        let seen = thread::scope(|s| {
+            let handles = (0..8)
+                .map(|_| s.spawn(|_| lazy.load_full()))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        })
+        .unwrap();
+        let stored = lazy.load_full();
+        assert!(seen.iter().all(|v| Arc::ptr_eq(v, &stored)));
+        assert_eq!(1, calls.load(Ordering::Relaxed));
+    }
+
+    /// The factory can initialize other storages.
+    #[test]
+    fn nested() {
+        let inner = LazyArcSwap::new(|| 1);
+        let outer = LazyArcSwap::new(|| *inner.load() + 1);
+        assert_eq!(2, *outer.load());
+        assert_eq!(1, *inner.load());
+    }
+
+    /// Initialization of the same storage from its own factory is a bug, caught by a panic
+    /// instead of a deadlock.
+    #[test]
+    #[should_panic(expected = "Recursive")]
+    #[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+    fn recursive() {
+        let shared = ArcSwapOption::<usize>::empty();
+        shared.get_or_init(|| *shared.get_or_init(|| 1));
+    }
+
+    /// A panicking factory lets the next one try again.
+    #[test]
+    fn panicked() {
+        let shared = ArcSwapOption::<usize>::empty();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            shared.get_or_init(|| panic!("Failed"));
+        }));
+        assert!(result.is_err());
+        assert_eq!(1, *shared.get_or_init(|| 1));
+    }
+}
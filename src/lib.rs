@@ -2,7 +2,10 @@
 #![warn(missing_docs)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![allow(deprecated)]
-#![cfg_attr(feature = "experimental-thread-local", no_std)]
+#![cfg_attr(
+    any(feature = "experimental-thread-local", feature = "custom-thread-local"),
+    no_std
+)]
 #![cfg_attr(feature = "experimental-thread-local", feature(thread_local))]
 
 //! Making [`Arc`] itself atomic
@@ -84,6 +87,24 @@
 //! of application from each other (eg. giving a component access to only its own part of
 //! configuration while still having it reloaded as a whole).
 //!
+//! The [`scope`] module allows doing many loads with a single lookup of the thread-local data.
+//! Similarly, the [`batch`][mod@batch] module allows doing many stores while paying the cost of
+//! releasing the old values only once.
+//!
+//! The [`replicated`] module has a variant of the storage for read-mostly data on machines with
+//! many cores.
+//!
+//! The [`lazy`] module has storages initialized on the first load.
+//!
+//! The [`layered`] module merges a base value with layers of overrides, each updated separately.
+//! The `history` module keeps the last few values around, to allow rollbacks.
+//! With the `reload` feature, the `reload` module reloads a value from a file when it changes.
+//! The `reclaim` module moves the destruction of replaced values away from the readers.
+//! The `rcu` module has an [`rcu`][ArcSwapAny::rcu] with a backoff and serialized writers.
+//! The [`cell`] module has a single-threaded counterpart for `Rc`, without any atomics.
+//! With the `debug-guards` feature, the `debug_guards` module finds where the guards are held.
+//! With the `numa` feature, the per-thread data are grouped by NUMA nodes (see the `numa` module).
+//!
 //! # Before using
 //!
 //! The data structure is a bit niche. Before using, please check the
@@ -134,30 +155,73 @@
 
 #[rustversion::since(1.36.0)]
 #[allow(unused_imports)]
-#[cfg_attr(feature = "experimental-thread-local", macro_use)]
+#[cfg_attr(
+    any(feature = "experimental-thread-local", feature = "custom-thread-local"),
+    macro_use
+)]
 extern crate alloc;
 
 pub mod access;
 mod as_raw;
+pub mod batch;
 pub mod cache;
+pub mod cell;
 mod compile_fail_tests;
 mod debt;
+#[cfg(feature = "debug-guards")]
+pub mod debug_guards;
 pub mod docs;
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+pub mod history;
+pub mod layered;
+pub mod lazy;
+#[cfg(feature = "custom-thread-local")]
+pub mod local_storage;
+#[cfg(feature = "numa")]
+// Loom checks the debt lists without the grouping.
+#[cfg_attr(loom, allow(dead_code))]
+pub mod numa;
+#[cfg(not(feature = "custom-thread-local"))]
+mod per_thread;
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+pub mod rcu;
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+pub mod reclaim;
 mod ref_cnt;
+#[cfg(feature = "reload")]
+pub mod reload;
+pub mod replicated;
+pub mod scope;
 #[cfg(feature = "serde")]
 mod serde;
 pub mod strategy;
+mod sync;
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+mod wait;
 #[cfg(feature = "weak")]
 mod weak;
 
 // Hack to not rely on std on newer compilers (where alloc is stabilized) but still fall back to
 // std on old compilers.
+//
+// Targets without native atomic compare and swap don't have the alloc Arc at all, the one from
+// portable-atomic-util takes its place there.
 mod imports {
     #[rustversion::since(1.36.0)]
-    pub use alloc::{boxed::Box, rc::Rc, sync::Arc};
+    pub use alloc::{boxed::Box, rc::Rc, vec::Vec};
 
     #[rustversion::before(1.36.0)]
-    pub use std::{boxed::Box, rc::Rc, sync::Arc};
+    pub use std::{boxed::Box, rc::Rc, sync::Arc, sync::Weak, vec::Vec};
+
+    // Weak is used only with the weak feature.
+    #[rustversion::since(1.36.0)]
+    #[cfg(not(all(feature = "portable-atomic", not(target_has_atomic = "ptr"))))]
+    #[allow(unused_imports)]
+    pub use alloc::sync::{Arc, Weak};
+
+    #[cfg(all(feature = "portable-atomic", not(target_has_atomic = "ptr")))]
+    #[allow(unused_imports)]
+    pub use portable_atomic_util::{Arc, Weak};
 }
 
 use core::borrow::Borrow;
@@ -166,18 +230,24 @@ use core::marker::PhantomData;
 use core::mem;
 use core::ops::Deref;
 use core::ptr;
-use core::sync::atomic::{AtomicPtr, Ordering};
+use core::sync::atomic::Ordering;
 
 use crate::imports::Arc;
 
 use crate::access::{Access, Map};
 pub use crate::as_raw::AsRaw;
+pub use crate::batch::batch;
 pub use crate::cache::Cache;
 pub use crate::ref_cnt::RefCnt;
+pub use crate::scope::with_local;
+#[cfg(not(loom))]
 use crate::strategy::hybrid::{DefaultConfig, HybridStrategy};
 use crate::strategy::sealed::Protected;
 use crate::strategy::{CaS, Strategy};
 pub use crate::strategy::{DefaultStrategy, IndependentStrategy};
+use crate::sync::AtomicPtr;
+#[cfg(feature = "weak")]
+pub use crate::weak::StrongOrWeak;
 
 /// A temporary storage of the pointer.
 ///
@@ -186,9 +256,21 @@ pub use crate::strategy::{DefaultStrategy, IndependentStrategy};
 /// loaded, so most operations are to be done using that.
 pub struct Guard<T: RefCnt, S: Strategy<T> = DefaultStrategy> {
     inner: S::Protected,
+    #[cfg(feature = "debug-guards")]
+    _tracked: debug_guards::Tracked,
 }
 
 impl<T: RefCnt, S: Strategy<T>> Guard<T, S> {
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    #[inline]
+    fn new(inner: S::Protected) -> Self {
+        Guard {
+            inner,
+            #[cfg(feature = "debug-guards")]
+            _tracked: debug_guards::Tracked::new(),
+        }
+    }
+
     /// Converts it into the held value.
     ///
     /// This, on occasion, may be a tiny bit faster than cloning the Arc or whatever is being held
@@ -216,10 +298,9 @@ impl<T: RefCnt, S: Strategy<T>> Guard<T, S> {
     /// let g2 = Guard::<_, DefaultStrategy>::from_inner(Arc::clone(&*g1));
     /// # drop(g2);
     /// ```
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     pub fn from_inner(inner: T) -> Self {
-        Guard {
-            inner: S::Protected::from_inner(inner),
-        }
+        Self::new(S::Protected::from_inner(inner))
     }
 }
 
@@ -232,12 +313,14 @@ impl<T: RefCnt, S: Strategy<T>> Deref for Guard<T, S> {
 }
 
 impl<T: RefCnt, S: Strategy<T>> From<T> for Guard<T, S> {
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn from(inner: T) -> Self {
         Self::from_inner(inner)
     }
 }
 
 impl<T: Default + RefCnt, S: Strategy<T>> Default for Guard<T, S> {
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     fn default() -> Self {
         Self::from(T::default())
     }
@@ -269,6 +352,17 @@ where
     ptr::eq(a, b)
 }
 
+/// Wakes up threads waiting for a change of the storage, if there are any.
+#[inline]
+#[cfg_attr(
+    any(feature = "experimental-thread-local", feature = "custom-thread-local"),
+    allow(unused_variables)
+)]
+pub(crate) fn notify_waiters<P>(storage: &P) {
+    #[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+    wait::notify(storage as *const _ as usize);
+}
+
 /// An atomic storage for a reference counted smart pointer like [`Arc`] or `Option<Arc>`.
 ///
 /// This is a storage where a smart pointer may live. It can be read and written atomically from
@@ -344,8 +438,9 @@ impl<T: RefCnt, S: Default + Strategy<T>> From<T> for ArcSwapAny<T, S> {
 
 impl<T: RefCnt, S: Strategy<T>> Drop for ArcSwapAny<T, S> {
     fn drop(&mut self) {
-        let ptr = *self.ptr.get_mut();
+        let raw = sync::ptr_get_mut(&mut self.ptr);
         unsafe {
+            let ptr = self.strategy.leave_storage(raw);
             // To pay any possible debts
             self.strategy.wait_for_readers(ptr, &self.ptr);
             // We are getting rid of the one stored ref count
@@ -395,7 +490,7 @@ impl<T: RefCnt, S: Strategy<T>> ArcSwapAny<T, S> {
         // The AtomicPtr requires *mut in its interface. We are more like *const, so we cast it.
         // However, we always go back to *const right away when we get the pointer on the other
         // side, so it should be fine.
-        let ptr = T::into_ptr(val);
+        let ptr = unsafe { strategy.enter_storage(T::into_ptr(val)) };
         Self {
             ptr: AtomicPtr::new(ptr),
             _phantom_arc: PhantomData,
@@ -405,7 +500,8 @@ impl<T: RefCnt, S: Strategy<T>> ArcSwapAny<T, S> {
 
     /// Extracts the value inside.
     pub fn into_inner(mut self) -> T {
-        let ptr = *self.ptr.get_mut();
+        let raw = sync::ptr_get_mut(&mut self.ptr);
+        let ptr = unsafe { self.strategy.leave_storage(raw) };
         // To pay all the debts
         unsafe { self.strategy.wait_for_readers(ptr, &self.ptr) };
         mem::forget(self);
@@ -468,10 +564,11 @@ impl<T: RefCnt, S: Strategy<T>> ArcSwapAny<T, S> {
     /// # print_correct(&p);
     /// # print_broken(&p);
     /// ```
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     #[inline]
     pub fn load(&self) -> Guard<T, S> {
         let protected = unsafe { self.strategy.load(&self.ptr) };
-        Guard { inner: protected }
+        Guard::new(protected)
     }
 
     /// Replaces the value inside this instance.
@@ -483,13 +580,15 @@ impl<T: RefCnt, S: Strategy<T>> ArcSwapAny<T, S> {
 
     /// Exchanges the value inside this instance.
     pub fn swap(&self, new: T) -> T {
-        let new = T::into_ptr(new);
+        let new = unsafe { self.strategy.enter_storage(T::into_ptr(new)) };
         // AcqRel needed to publish the target of the new pointer and get the target of the old
         // one.
         //
         // SeqCst to synchronize the time lines with the group counters.
         let old = self.ptr.swap(new, Ordering::SeqCst);
+        notify_waiters(&self.ptr);
         unsafe {
+            let old = self.strategy.leave_storage(old);
             self.strategy.wait_for_readers(old, &self.ptr);
             T::from_ptr(old)
         }
@@ -511,13 +610,19 @@ impl<T: RefCnt, S: Strategy<T>> ArcSwapAny<T, S> {
     /// The `current` can be specified as `&Arc`, [`Guard`](struct.Guard.html),
     /// [`&Guards`](struct.Guards.html) or as a raw pointer (but _not_ owned `Arc`). See the
     /// [`AsRaw`] trait.
+    #[cfg_attr(feature = "debug-guards", track_caller)]
     pub fn compare_and_swap<C>(&self, current: C, new: T) -> Guard<T, S>
     where
         C: AsRaw<T::Base>,
         S: CaS<T>,
     {
+        let current = current.as_raw();
         let protected = unsafe { self.strategy.compare_and_swap(&self.ptr, current, new) };
-        Guard { inner: protected }
+        let previous: &T = protected.borrow();
+        if ptr_eq(previous, current) {
+            notify_waiters(&self.ptr);
+        }
+        Guard::new(protected)
     }
 
     /// Read-Copy-Update of the pointer inside.
@@ -619,6 +724,12 @@ impl<T: RefCnt, S: Strategy<T>> ArcSwapAny<T, S> {
     /// inside to share the unchanged values). Something like
     /// [`rpds`](https://crates.io/crates/rpds) or [`im`](https://crates.io/crates/im) might do
     /// what you need.
+    ///
+    /// # Contention
+    ///
+    /// With many writers, the closure can be called many times. The `rcu_with` (see the `rcu`
+    /// module) can back off between the attempts, serialize the writers, limit the number of
+    /// attempts and report how many there were.
     pub fn rcu<R, F>(&self, mut f: F) -> T
     where
         F: FnMut(&T) -> R,
@@ -790,6 +901,8 @@ impl<T> ArcSwapOption<T> {
     /// GLOBAL_DATA.store(Some(Arc::new(42)));
     /// assert_eq!(42, **GLOBAL_DATA.load().as_ref().unwrap());
     /// ```
+    // Loom atomics can't be created in const context.
+    #[cfg(not(loom))]
     pub const fn const_empty() -> Self {
         Self {
             ptr: AtomicPtr::new(ptr::null_mut()),
@@ -823,7 +936,17 @@ pub type IndependentArcSwap<T> = ArcSwapAny<Arc<T>, IndependentStrategy>;
 ///
 /// [Weak]: std::sync::Weak
 #[cfg(feature = "weak")]
-pub type ArcSwapWeak<T> = ArcSwapAny<alloc::sync::Weak<T>>;
+pub type ArcSwapWeak<T> = ArcSwapAny<crate::imports::Weak<T>>;
+
+/// Arc swap holding either a strong or a weak reference.
+///
+/// This is useful for caches ‒ the value is kept alive while there's enough memory. Under memory
+/// pressure, it can be [demoted][ArcSwapAny::demote] to a weak reference atomically, so it is
+/// freed unless someone else still uses it. See [`StrongOrWeak`].
+///
+/// Needs the `weak` feature turned on.
+#[cfg(feature = "weak")]
+pub type ArcSwapWeakOrStrong<T> = ArcSwapAny<StrongOrWeak<T>>;
 
 macro_rules! t {
     ($name: ident, $strategy: ty) => {
@@ -1147,7 +1270,10 @@ macro_rules! t {
 
             /// A panic from within the rcu callback should not change anything.
             #[test]
-            #[cfg(not(feature = "experimental-thread-local"))]
+            #[cfg(not(any(
+                feature = "experimental-thread-local",
+                feature = "custom-thread-local"
+            )))]
             fn rcu_panic() {
                 use std::panic;
                 let shared = ArcSwap::from(Arc::new(0));
@@ -1253,6 +1379,16 @@ macro_rules! t {
 }
 
 t!(tests_default, DefaultStrategy);
+t!(tests_dyn, crate::strategy::DynStrategy);
+#[cfg(all(
+    not(any(feature = "experimental-thread-local", feature = "custom-thread-local")),
+    test
+))]
+t!(tests_rw_lock, crate::strategy::RwLockStrategy);
+#[cfg(all(feature = "parking_lot", test))]
+t!(tests_parking_lot, crate::strategy::ParkingLotStrategy);
+#[cfg(all(feature = "experimental-strategies", not(loom), test))]
+t!(tests_membarrier, crate::strategy::MembarrierStrategy);
 #[cfg(all(feature = "internal-test-strategies", test))]
 #[allow(deprecated)]
 mod internal_strategies {
@@ -1268,8 +1404,25 @@ mod internal_strategies {
 mod tests {
     use super::*;
 
+    use alloc::boxed::Box;
     use alloc::vec::Vec;
 
+    /// The storage can move while guards are held, the debts are still paid.
+    #[test]
+    fn moved_storage() {
+        let a = Arc::new(0);
+        let shared = ArcSwap::from(Arc::clone(&a));
+        let guard = shared.load();
+        let moved = Box::new(shared);
+        moved.store(Arc::new(1));
+        // The debt got paid, the guard has its own ref count now.
+        assert_eq!(2, Arc::strong_count(&a));
+        drop(moved);
+        assert_eq!(0, **guard);
+        drop(guard);
+        assert_eq!(1, Arc::strong_count(&a));
+    }
+
     /// Accessing the value inside ArcSwap with Guards (and checks for the reference
     /// counts).
     #[test]
@@ -0,0 +1,109 @@
+//! Custom storage of the thread-local data.
+//!
+//! Each thread using the [`ArcSwapAny`][crate::ArcSwapAny] needs a bit of private bookkeeping.
+//! Usually, this lives in a [`std::thread_local!`] variable. That is not available in `no_std`
+//! environments (the `experimental-thread-local` feature solves it, but only on nightly).
+//!
+//! With the `custom-thread-local` feature, the crate is `no_std` (but still needs `alloc`) and it
+//! is up to the user to provide the storage, through a function registered with [`register`].
+//! This can be a per-thread, per-task or per-CPU storage, depending on what is available in the
+//! environment. Until something is registered (or if the registered function doesn't provide any
+//! storage), every operation claims the bookkeeping data only for its own duration, which is
+//! correct, but slow.
+//!
+//! # Examples
+//!
+//! This uses the standard thread locals, but in a real `no_std` environment it would be something
+//! else.
+//!
+//! ```rust
+//! use arc_swap::ArcSwap;
+//! use arc_swap::local_storage::{self, LocalStorage};
+//!
+//! std::thread_local! {
+//!     static STORAGE: LocalStorage = LocalStorage::new();
+//! }
+//!
+//! fn provide(f: &mut dyn FnMut(&LocalStorage)) {
+//!     // During the thread shutdown, the storage may be gone already. Not calling f is fine.
+//!     let _ = STORAGE.try_with(|s| f(s));
+//! }
+//!
+//! // Safe, because each thread has its own instance.
+//! unsafe { local_storage::register(provide) };
+//!
+//! let shared = ArcSwap::from_pointee(42);
+//! assert_eq!(42, **shared.load());
+//! ```
+
+use core::ptr;
+use core::sync::atomic::Ordering;
+
+use crate::debt::LocalNode;
+use crate::imports::Box;
+use crate::sync::AtomicPtr;
+
+/// The thread-local data.
+///
+/// It is opaque, the only thing one can do with it is to create it and hand it out to the
+/// library through the function passed to [`register`].
+///
+/// When it is dropped, any resources it held are given up for use by other threads.
+pub struct LocalStorage(LocalNode);
+
+impl LocalStorage {
+    /// Creates a new (empty) storage.
+    pub const fn new() -> Self {
+        LocalStorage(LocalNode::new())
+    }
+
+    pub(crate) fn local_node(&self) -> &LocalNode {
+        &self.0
+    }
+}
+
+impl Default for LocalStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The function providing the storage for the current thread (or task or CPU).
+///
+/// It is expected to call the passed closure exactly once with the storage of the current context.
+/// It may also skip calling it if the storage is not available at the moment.
+pub type Provider = fn(&mut dyn FnMut(&LocalStorage));
+
+/// The registered provider, if any.
+///
+/// Boxed, because a function pointer can't be stored in an atomic directly. The boxes are never
+/// freed, someone might still be using the previous one (registering is expected to be rare).
+static PROVIDER: AtomicPtr<Provider> = AtomicPtr::new(ptr::null_mut());
+
+/// Registers the function providing the [`LocalStorage`].
+///
+/// Registering another one replaces the previous one. However, this is expected to be done once
+/// at startup, before the first use of the library.
+///
+/// # Safety
+///
+/// The provider must never hand out the same instance to two contexts that might use it at the
+/// same time. In particular:
+///
+/// * Two threads must not get the same storage at the same time (a storage may be passed on to
+///   another thread once the previous one is no longer using it).
+/// * If the storage is per CPU, the thread must not be migrated to a different CPU or preempted
+///   by another user of the same storage during the call.
+/// * Interrupt handlers using the library must not share the storage with the code they
+///   interrupt.
+///
+/// Note that nested calls on the same thread (the library calling the provider again while the
+/// closure is running) are fine and expected; these must get the same instance.
+pub unsafe fn register(provider: Provider) {
+    PROVIDER.store(Box::into_raw(Box::new(provider)), Ordering::Release);
+}
+
+pub(crate) fn provider() -> Option<Provider> {
+    // Either null or a box leaked in register.
+    unsafe { PROVIDER.load(Ordering::Acquire).as_ref() }.copied()
+}
@@ -0,0 +1,402 @@
+//! NUMA-aware placement of the per-thread data.
+//!
+//! Each thread using the library gets a node with its debt slots (see the
+//! [internal details][crate::docs::internal]). Normally, all of them hang on one global list and
+//! the writers walk the whole list on every change. On a machine with multiple NUMA nodes
+//! (sockets), that list interleaves nodes living in memory of different sockets.
+//!
+//! With the `numa` feature, the nodes are grouped by the NUMA node of the thread that claims them.
+//! A thread claims (or allocates) a node only from its own group. The allocation is done on that
+//! thread, so it's likely placed into the memory local to it (the usual first-touch policy). The
+//! writers go through the groups from the nearest one to the furthest, so they finish the local
+//! part before moving to another socket.
+//!
+//! The topology is detected on the first use. On Linux, the NUMA nodes and distances are read from
+//! sysfs and the current CPU is found by `sched_getcpu`; elsewhere (or if it can't be read) all
+//! threads are considered to be on a single node. A different [`Topology`] can be
+//! [`install`]ed ‒ for example a simulated one to test the code on a machine with a single socket.
+//!
+//! A thread is located when it claims a node and the group is remembered until it claims another
+//! one, so the (possibly expensive) `locate` of the topology isn't called on every operation.
+//! Threads migrating between NUMA nodes are fine (they just may access remote memory until they
+//! claim a new node), as is replacing the topology while the library is in use. Up to 16 groups
+//! are kept; NUMA nodes beyond that share them. The writers skip the groups that never had any
+//! nodes.
+//!
+//! This needs the standard library.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::cell::Cell;
+//!
+//! use arc_swap::numa::{self, Topology};
+//! use arc_swap::ArcSwap;
+//!
+//! thread_local! {
+//!     static SIMULATED_NODE: Cell<usize> = Cell::new(0);
+//! }
+//!
+//! // Two sockets, with the usual distances reported by Linux.
+//! let topology = Topology::new(vec![vec![10, 20], vec![20, 10]], || {
+//!     SIMULATED_NODE.with(Cell::get)
+//! });
+//! numa::install(topology);
+//!
+//! let shared = ArcSwap::from_pointee(42);
+//! std::thread::scope(|s| {
+//!     s.spawn(|| {
+//!         SIMULATED_NODE.with(|node| node.set(1));
+//!         assert_eq!(42, **shared.load());
+//!     });
+//! });
+//! ```
+
+use std::cell::Cell;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// Number of the node groups.
+pub(crate) const GROUPS: usize = 16;
+
+/// The NUMA layout of the machine.
+pub struct Topology {
+    distances: Vec<Vec<u32>>,
+    locate: Box<dyn Fn() -> usize + Send + Sync>,
+}
+
+impl Topology {
+    /// Creates a topology from a distance table and a way to locate the current thread.
+    ///
+    /// The `distances[a][b]` is the distance from the NUMA node `a` to `b` (in the units used by
+    /// Linux, where 10 is local). The `locate` returns the NUMA node the current thread runs on.
+    ///
+    /// # Panics
+    ///
+    /// If the table is not square or is empty.
+    pub fn new<L>(distances: Vec<Vec<u32>>, locate: L) -> Self
+    where
+        L: Fn() -> usize + Send + Sync + 'static,
+    {
+        assert!(!distances.is_empty(), "Topology needs at least one node");
+        assert!(
+            distances.iter().all(|row| row.len() == distances.len()),
+            "The distance table must be square"
+        );
+        Topology {
+            distances,
+            locate: Box::new(locate),
+        }
+    }
+
+    /// A topology with just one NUMA node.
+    pub fn single() -> Self {
+        Self::new(vec![vec![10]], || 0)
+    }
+
+    /// Detects the topology of the current machine.
+    ///
+    /// Falls back to [`single`][Topology::single] if it can't be found out.
+    pub fn detect() -> Self {
+        sys::detect().unwrap_or_else(Self::single)
+    }
+
+    /// Number of the NUMA nodes.
+    pub fn nodes(&self) -> usize {
+        self.distances.len()
+    }
+
+    /// The NUMA node the current thread runs on.
+    pub fn current(&self) -> usize {
+        (self.locate)()
+    }
+
+    /// The order in which to walk the groups, from the given one.
+    ///
+    /// This is always a permutation of all the groups ‒ even the ones this topology doesn't know
+    /// about may hold nodes from a previous one.
+    fn group_order(&self, from: usize) -> Vec<usize> {
+        let distance = |group: usize| {
+            (group..self.nodes())
+                .step_by(GROUPS)
+                .map(|to| self.distances[from % self.nodes()][to])
+                .min()
+                .unwrap_or(u32::MAX)
+        };
+        let mut order = (0..GROUPS).collect::<Vec<_>>();
+        // The stable sort keeps the groups with the same distance in the index order.
+        order.sort_by_key(|&group| (group != from % GROUPS, distance(group)));
+        order
+    }
+}
+
+impl Debug for Topology {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        formatter
+            .debug_struct("Topology")
+            .field("distances", &self.distances)
+            .finish()
+    }
+}
+
+/// The installed topology with the precomputed orders.
+struct Placement {
+    topology: Topology,
+    /// For each group, the order of the groups to walk.
+    orders: Vec<Vec<usize>>,
+}
+
+impl Placement {
+    fn new(topology: Topology) -> Self {
+        let orders = (0..GROUPS)
+            .map(|group| topology.group_order(group))
+            .collect();
+        Placement { topology, orders }
+    }
+}
+
+/// The current placement.
+///
+/// The replaced ones are leaked, someone may still be using them.
+static PLACEMENT: AtomicPtr<Placement> = AtomicPtr::new(ptr::null_mut());
+
+fn placement() -> &'static Placement {
+    let current = PLACEMENT.load(Ordering::Acquire);
+    if current.is_null() {
+        let detected = Box::into_raw(Box::new(Placement::new(Topology::detect())));
+        match PLACEMENT.compare_exchange(
+            ptr::null_mut(),
+            detected,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => unsafe { &*detected },
+            Err(other) => {
+                // Someone was faster (either detecting or installing), use theirs.
+                drop(unsafe { Box::from_raw(detected) });
+                unsafe { &*other }
+            }
+        }
+    } else {
+        // Once set, never freed.
+        unsafe { &*current }
+    }
+}
+
+/// Installs a topology, replacing the detected (or previously installed) one.
+///
+/// This is expected to be done once, at the start of the program (or in tests). The previous
+/// topology is leaked, because other threads may still be using it.
+pub fn install(topology: Topology) {
+    let new = Box::into_raw(Box::new(Placement::new(topology)));
+    PLACEMENT.swap(new, Ordering::AcqRel);
+}
+
+/// The groups that ever had any nodes, a bit for each.
+///
+/// Nodes are never freed, so once set, the bits stay.
+static POPULATED: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The placement the current thread was last located in and its group there.
+    // The const {} syntax is not available on old compilers.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static LOCATION: Cell<(*const Placement, usize)> = Cell::new((ptr::null(), 0));
+}
+
+/// Locates the current thread and remembers its group.
+///
+/// This is done when the thread claims a node.
+pub(crate) fn local_group() -> usize {
+    let placement = placement();
+    let group = placement.topology.current() % GROUPS;
+    // During the thread shutdown, the thread local may be gone already. We just don't remember.
+    let _ = LOCATION.try_with(|location| location.set((placement, group)));
+    group
+}
+
+/// The groups to walk, from the current thread.
+///
+/// Uses the remembered group, unless the thread wasn't located yet (or the topology changed).
+pub(crate) fn group_order() -> &'static [usize] {
+    let placement = placement();
+    let remembered = LOCATION
+        .try_with(Cell::get)
+        .ok()
+        .filter(|&(located_in, _)| ptr::eq(located_in, placement));
+    let group = match remembered {
+        Some((_, group)) => group,
+        None => local_group(),
+    };
+    &placement.orders[group]
+}
+
+/// Marks the group as having nodes.
+///
+/// Must be called before a node is added to it.
+pub(crate) fn populate(group: usize) {
+    // Only when allocating a new node, so it doesn't matter it's a read-write operation. SeqCst,
+    // so anyone seeing the node in the list sees the bit too.
+    POPULATED.fetch_or(1 << group, Ordering::SeqCst);
+}
+
+/// The groups that may have nodes, a bit for each.
+pub(crate) fn populated() -> usize {
+    POPULATED.load(Ordering::SeqCst)
+}
+
+#[cfg(all(target_os = "linux", not(miri)))]
+mod sys {
+    use std::convert::TryFrom;
+    use std::fs;
+    use std::path::Path;
+
+    use super::Topology;
+
+    const SYSFS: &str = "/sys/devices/system/node";
+
+    /// Parses the kernel's list format (`0-3,8,10-11`).
+    fn parse_list(list: &str) -> Option<Vec<usize>> {
+        let mut result = Vec::new();
+        for part in list.trim().split(',').filter(|part| !part.is_empty()) {
+            let mut bounds = part.splitn(2, '-');
+            let start: usize = bounds.next()?.parse().ok()?;
+            let end = match bounds.next() {
+                Some(end) => end.parse().ok()?,
+                None => start,
+            };
+            result.extend(start..=end);
+        }
+        Some(result)
+    }
+
+    fn read(path: &Path) -> Option<String> {
+        fs::read_to_string(path).ok()
+    }
+
+    pub(super) fn detect() -> Option<Topology> {
+        let nodes = parse_list(&read(&Path::new(SYSFS).join("online"))?)?;
+        // The node IDs may have holes, we number them densely.
+        let mut distances = Vec::with_capacity(nodes.len());
+        let mut cpu_nodes = Vec::new();
+        for (index, node) in nodes.iter().enumerate() {
+            let dir = Path::new(SYSFS).join(format!("node{}", node));
+            let row = read(&dir.join("distance"))?
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<Vec<u32>, _>>()
+                .ok()?;
+            // The row is indexed by the node IDs, pick the online ones.
+            let row = nodes
+                .iter()
+                .map(|&to| row.get(to).cloned())
+                .collect::<Option<Vec<_>>>()?;
+            distances.push(row);
+            for cpu in parse_list(&read(&dir.join("cpulist"))?)? {
+                if cpu_nodes.len() <= cpu {
+                    cpu_nodes.resize(cpu + 1, 0);
+                }
+                cpu_nodes[cpu] = index;
+            }
+        }
+        if nodes.is_empty() {
+            return None;
+        }
+        Some(Topology::new(distances, move || {
+            let cpu = unsafe { libc::sched_getcpu() };
+            // Negative on error; unknown CPUs (hotplugged later) go to the first node.
+            usize::try_from(cpu)
+                .ok()
+                .and_then(|cpu| cpu_nodes.get(cpu).cloned())
+                .unwrap_or(0)
+        }))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn lists() {
+            assert_eq!(
+                Some(vec![0, 1, 2, 3, 8, 10, 11]),
+                parse_list("0-3,8,10-11\n")
+            );
+            assert_eq!(Some(vec![0]), parse_list("0"));
+            assert_eq!(Some(vec![]), parse_list("\n"));
+            assert_eq!(None, parse_list("x"));
+        }
+
+        /// Whatever the machine, detection provides a sane topology.
+        #[test]
+        fn detect_sane() {
+            let topology = Topology::detect();
+            assert!(topology.nodes() >= 1);
+            assert!(topology.current() < topology.nodes());
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", not(miri))))]
+mod sys {
+    use super::Topology;
+
+    pub(super) fn detect() -> Option<Topology> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_permutation(order: &[usize]) {
+        let mut sorted = order.to_vec();
+        sorted.sort_unstable();
+        assert_eq!((0..GROUPS).collect::<Vec<_>>(), sorted);
+    }
+
+    #[test]
+    fn nearest_first() {
+        let topology = Topology::new(
+            vec![
+                vec![10, 21, 31, 21],
+                vec![21, 10, 21, 31],
+                vec![31, 21, 10, 21],
+                vec![21, 31, 21, 10],
+            ],
+            || 0,
+        );
+        let order = topology.group_order(2);
+        assert_eq!(&[2, 1, 3, 0], &order[..4]);
+        assert_permutation(&order);
+        for group in 0..GROUPS {
+            assert_permutation(&topology.group_order(group));
+        }
+    }
+
+    /// More NUMA nodes than groups share them.
+    #[test]
+    fn many_nodes() {
+        let nodes = GROUPS + 2;
+        let distances = (0..nodes)
+            .map(|from| {
+                (0..nodes)
+                    .map(|to| if from == to { 10 } else { 40 - to as u32 })
+                    .collect()
+            })
+            .collect();
+        let topology = Topology::new(distances, || 0);
+        let order = topology.group_order(1);
+        // Group 0 has the node GROUPS, the closest one (node GROUPS + 1 is in our group).
+        assert_eq!(&[1, 0, GROUPS - 1, GROUPS - 2], &order[..4]);
+        assert_permutation(&order);
+    }
+
+    #[test]
+    #[should_panic(expected = "square")]
+    fn not_square() {
+        Topology::new(vec![vec![10, 20], vec![20]], || 0);
+    }
+}
@@ -0,0 +1,118 @@
+//! A value kept separately for each thread.
+//!
+//! The values are in a linked list, only prepended to, each tagged by the
+//! [`thread_id`][LocalNode::thread_id] of its thread. Nobody but that thread touches the value
+//! (until the whole thing is dropped), so it doesn't need to be thread safe itself. The ids are
+//! never reused, so the values of terminated threads are kept around until then.
+//!
+//! This relies on the [`LocalNode`] being really per thread. That is not the case with the
+//! `custom-thread-local` feature (the provided storage may be per CPU, for example), so this is
+//! not available there.
+
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::Ordering;
+
+use crate::debt::LocalNode;
+use crate::imports::Box;
+use crate::sync::{ptr_get_mut, AtomicPtr};
+
+struct Slot<T> {
+    thread: usize,
+    value: RefCell<T>,
+    next: *mut Slot<T>,
+}
+
+pub(crate) struct PerThread<T> {
+    slots: AtomicPtr<Slot<T>>,
+    /// Owns the values, but they are not shared between threads.
+    _values: PhantomData<RefCell<T>>,
+}
+
+impl<T> PerThread<T> {
+    pub(crate) fn new() -> Self {
+        PerThread {
+            slots: AtomicPtr::new(ptr::null_mut()),
+            _values: PhantomData,
+        }
+    }
+
+    /// Provides the value of the current thread, creating it with `init` on the first use.
+    ///
+    /// Returns `None` if the thread can't be told apart from the others at the moment (like
+    /// during its shutdown, when the thread local storage is already gone).
+    pub(crate) fn get_or<F: FnOnce() -> T>(&self, init: F) -> Option<&RefCell<T>> {
+        let thread = LocalNode::with(LocalNode::thread_id)?;
+        let mut current = self.slots.load(Ordering::Acquire);
+        // The slots live as long as self.
+        while let Some(slot) = unsafe { current.as_ref() } {
+            if slot.thread == thread {
+                return Some(&slot.value);
+            }
+            current = slot.next;
+        }
+        // Only this thread could have added its slot, so it's not there.
+        let slot = Box::into_raw(Box::new(Slot {
+            thread,
+            value: RefCell::new(init()),
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.slots.load(Ordering::Acquire);
+        loop {
+            // Not published yet, so it's still ours.
+            unsafe { (*slot).next = head };
+            match self
+                .slots
+                .compare_exchange_weak(head, slot, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Some(unsafe { &(*slot).value }),
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+impl<T> Drop for PerThread<T> {
+    fn drop(&mut self) {
+        let mut current = ptr_get_mut(&mut self.slots);
+        while !current.is_null() {
+            // We have exclusive access, nobody can hold a reference to the slots.
+            let slot = unsafe { Box::from_raw(current) };
+            current = slot.next;
+        }
+    }
+}
+
+// The value is used only by its thread, but it may be created on one thread and dropped on
+// another one.
+unsafe impl<T: Send> Sync for PerThread<T> {}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::sync::Mutex;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn separate() {
+        let per_thread = PerThread::new();
+        *per_thread.get_or(|| 0).unwrap().borrow_mut() += 1;
+        *per_thread.get_or(|| 0).unwrap().borrow_mut() += 1;
+        let others = Mutex::new(0);
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let value = per_thread.get_or(|| 10).unwrap();
+                    *value.borrow_mut() += 1;
+                    *others.lock().unwrap() += *value.borrow();
+                });
+            }
+        });
+        assert_eq!(2, *per_thread.get_or(|| 0).unwrap().borrow());
+        assert_eq!(44, *others.lock().unwrap());
+    }
+}
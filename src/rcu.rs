@@ -0,0 +1,341 @@
+//! Read-Copy-Update with a control over the contention.
+//!
+//! The [`rcu`][ArcSwapAny::rcu] retries whenever another writer changes the value in the
+//! meantime, calling the closure again each time. If the closure is expensive and there are many
+//! writers, most of that work is wasted. The [`rcu_with`][ArcSwapAny::rcu_with] can be configured
+//! to:
+//!
+//! * Back off exponentially after a failed attempt (spinning first, then yielding the CPU), to
+//!   let the other writers finish.
+//! * Serialize the writers on the same storage through a lock, so the closure usually runs just
+//!   once. The readers still don't take any locks.
+//! * Give up after some number of attempts.
+//!
+//! It also reports how many attempts it took, which helps to find out if the contention is a
+//! problem in the first place.
+//!
+//! The locks are shared between storages (there's a fixed number of them, chosen by the address
+//! of the storage), so unrelated writers sometimes wait for each other. A serialized
+//! [`rcu_with`][ArcSwapAny::rcu_with] inside the closure of another one (on any storage) is not
+//! serialized, to avoid deadlocks. Only the serialized writers wait for each other ‒ other
+//! writes (like [`store`][ArcSwapAny::store] or plain [`rcu`][ArcSwapAny::rcu]) can still make
+//! an attempt fail.
+//!
+//! Available only with the standard library.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::thread;
+//!
+//! use arc_swap::rcu::Config;
+//! use arc_swap::ArcSwap;
+//!
+//! let cnt = ArcSwap::from_pointee(0);
+//! let config = Config::new().backoff(true).serialize(true);
+//! thread::scope(|scope| {
+//!     for _ in 0..10 {
+//!         scope.spawn(|| {
+//!             let (_, stats) = cnt.rcu_with(&config, |inner| **inner + 1).unwrap();
+//!             // Nobody else writes there without the lock.
+//!             assert_eq!(1, stats.attempts());
+//!         });
+//!     }
+//! });
+//! assert_eq!(10, **cnt.load());
+//! ```
+
+use core::cell::Cell;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{self, AtomicPtr, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use crate::ref_cnt::RefCnt;
+use crate::strategy::{CaS, Strategy};
+use crate::{ptr_eq, ArcSwapAny, Guard};
+
+/// Number of the writer locks.
+const LOCKS: usize = 64;
+
+/// After this many rounds of backoff, we yield instead of spinning.
+const SPIN_LIMIT: u32 = 6;
+
+/// Lazily allocated on the first use and never freed.
+///
+/// Not a `static` array of mutexes directly, because creating these in a static needs a newer
+/// compiler.
+static WRITER_LOCKS: AtomicPtr<Vec<Mutex<()>>> = AtomicPtr::new(ptr::null_mut());
+
+thread_local! {
+    /// Set while the thread holds one of the writer locks.
+    // The const {} syntax is not available on old compilers.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static SERIALIZED: Cell<bool> = Cell::new(false);
+}
+
+fn writer_locks() -> &'static [Mutex<()>] {
+    let mut current = WRITER_LOCKS.load(Ordering::Acquire);
+    if current.is_null() {
+        let locks = (0..LOCKS).map(|_| Mutex::new(())).collect();
+        let new = Box::into_raw(Box::new(locks));
+        current = match WRITER_LOCKS.compare_exchange(
+            ptr::null_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new,
+            Err(other) => {
+                // Someone was faster, use theirs.
+                drop(unsafe { Box::from_raw(new) });
+                other
+            }
+        };
+    }
+    // Once set, it is never changed nor freed.
+    unsafe { &*current }
+}
+
+/// Holds the writer lock of one storage (see the module docs).
+struct Serialized {
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Serialized {
+    /// Locks the writer lock for the storage on the given address.
+    ///
+    /// Returns `None` if the thread already holds one of the locks.
+    fn lock(addr: usize) -> Option<Self> {
+        if SERIALIZED.with(Cell::get) {
+            return None;
+        }
+        // The storages are at least pointer-aligned, the lowest bits don't carry anything.
+        let index = (addr / mem::align_of::<usize>()) % LOCKS;
+        // Nothing is protected by the lock itself, a panic in the closure leaves nothing broken.
+        let lock = writer_locks()[index]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        SERIALIZED.with(|serialized| serialized.set(true));
+        Some(Serialized { _lock: lock })
+    }
+}
+
+impl Drop for Serialized {
+    fn drop(&mut self) {
+        SERIALIZED.with(|serialized| serialized.set(false));
+    }
+}
+
+/// Configuration of the [`rcu_with`][ArcSwapAny::rcu_with].
+///
+/// The default behaves like the [`rcu`][ArcSwapAny::rcu] ‒ retries right away, without any lock
+/// and as many times as needed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Config {
+    backoff: bool,
+    serialize: bool,
+    max_attempts: Option<usize>,
+}
+
+impl Config {
+    /// Creates the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Turns the exponential backoff after a failed attempt on or off.
+    pub fn backoff(self, backoff: bool) -> Self {
+        Config { backoff, ..self }
+    }
+
+    /// Turns the serialization of the writers on or off.
+    ///
+    /// See the [module documentation](index.html) for the details.
+    pub fn serialize(self, serialize: bool) -> Self {
+        Config { serialize, ..self }
+    }
+
+    /// Limits how many times the closure is called.
+    ///
+    /// `None` (the default) means no limit.
+    ///
+    /// # Panics
+    ///
+    /// If the limit is 0.
+    pub fn max_attempts(self, max_attempts: Option<usize>) -> Self {
+        assert_ne!(Some(0), max_attempts, "At least one attempt is needed");
+        Config {
+            max_attempts,
+            ..self
+        }
+    }
+}
+
+/// Statistics of one [`rcu_with`][ArcSwapAny::rcu_with].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Stats {
+    attempts: usize,
+}
+
+impl Stats {
+    /// How many times the closure was called.
+    ///
+    /// The last one succeeded, unless the limit on the attempts was reached.
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+}
+
+/// The exponential backoff between the attempts.
+struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    fn wait(&mut self) {
+        if self.step <= SPIN_LIMIT {
+            for _ in 0..1 << self.step {
+                atomic::spin_loop_hint();
+            }
+            self.step += 1;
+        } else {
+            thread::yield_now();
+        }
+    }
+}
+
+impl<T, S> ArcSwapAny<T, S>
+where
+    T: RefCnt,
+    S: Strategy<T> + CaS<T>,
+{
+    /// Read-Copy-Update of the value, with a control over the contention.
+    ///
+    /// This is like the [`rcu`][ArcSwapAny::rcu], but it can back off, serialize the writers and
+    /// give up, as set in the `config`. See the [`rcu`][crate::rcu] module.
+    ///
+    /// Returns the previous value and the statistics. If the limit on the attempts is reached,
+    /// the value is left unchanged and only the statistics are returned as the error.
+    ///
+    /// Available only with the standard library.
+    pub fn rcu_with<R, F>(&self, config: &Config, mut f: F) -> Result<(T, Stats), Stats>
+    where
+        F: FnMut(&T) -> R,
+        R: Into<T>,
+    {
+        let _serialized = if config.serialize {
+            Serialized::lock(&self.ptr as *const _ as usize)
+        } else {
+            None
+        };
+        let mut stats = Stats { attempts: 0 };
+        let mut backoff = Backoff { step: 0 };
+        let mut cur = self.load();
+        loop {
+            let new = f(&cur).into();
+            stats.attempts += 1;
+            let prev = self.compare_and_swap(&*cur, new);
+            if ptr_eq(&*cur, &*prev) {
+                return Ok((Guard::into_inner(prev), stats));
+            }
+            if config.max_attempts == Some(stats.attempts) {
+                return Err(stats);
+            }
+            cur = prev;
+            if config.backoff {
+                backoff.wait();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::ArcSwap;
+
+    const THREADS: usize = 8;
+    const ITERATIONS: usize = 100;
+
+    fn increments(config: &Config) {
+        let cnt = ArcSwap::from_pointee(0);
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    for _ in 0..ITERATIONS {
+                        let (_, stats) = cnt.rcu_with(config, |inner| **inner + 1).unwrap();
+                        if config.serialize {
+                            assert_eq!(1, stats.attempts());
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(THREADS * ITERATIONS, **cnt.load());
+    }
+
+    #[test]
+    fn plain() {
+        increments(&Config::new());
+    }
+
+    #[test]
+    fn backoff() {
+        increments(&Config::new().backoff(true));
+    }
+
+    #[test]
+    fn serialized() {
+        increments(&Config::new().serialize(true));
+        increments(&Config::new().serialize(true).backoff(true));
+    }
+
+    /// Gives up if someone else always changes the value first.
+    #[test]
+    fn exhausted() {
+        let shared = ArcSwap::from_pointee(0);
+        let config = Config::new().max_attempts(Some(3)).backoff(true);
+        let stats = shared
+            .rcu_with(&config, |inner| {
+                shared.store(Arc::new(**inner + 10));
+                **inner + 1
+            })
+            .unwrap_err();
+        assert_eq!(3, stats.attempts());
+        assert_eq!(30, **shared.load());
+    }
+
+    /// A serialized rcu nested in another one doesn't deadlock, even on the same storage.
+    #[test]
+    fn nested() {
+        let outer = ArcSwap::from_pointee(0);
+        let inner = ArcSwap::from_pointee(0);
+        let config = Config::new().serialize(true);
+        let first = Cell::new(true);
+        let (previous, stats) = outer
+            .rcu_with(&config, |value| {
+                if first.replace(false) {
+                    // This makes the first attempt fail.
+                    outer.rcu_with(&config, |value| **value + 10).unwrap();
+                }
+                inner.rcu_with(&config, |value| **value + 1).unwrap();
+                **value + 1
+            })
+            .unwrap();
+        assert_eq!(10, *previous);
+        assert_eq!(2, stats.attempts());
+        assert_eq!(11, **outer.load());
+        assert_eq!(2, **inner.load());
+        assert!(!SERIALIZED.with(Cell::get));
+    }
+
+    #[test]
+    #[should_panic(expected = "At least one")]
+    fn no_attempts() {
+        Config::new().max_attempts(Some(0));
+    }
+}
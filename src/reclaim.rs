@@ -0,0 +1,303 @@
+//! Deferred destruction of the replaced values.
+//!
+//! When a value is replaced, it is destroyed by whoever releases the last reference to it. That
+//! is often a reader dropping its [`Guard`] ‒ and if the value is something big (like a
+//! multi-gigabyte index), the reader has to free all of it before it can continue. That's bad
+//! news for latency-sensitive readers.
+//!
+//! The [`ReclaimingArcSwap`] doesn't let the replaced values go. It hands them to a [`Reclaimer`]
+//! instead, which keeps them until they are no longer used by anyone else. They are destroyed in
+//! [`Reclaimer::collect`], on the thread calling it. That can be a dedicated background thread
+//! (see [`Reclaimer::spawn`]), a task in some executor, or simply a place in the application
+//! where it doesn't hurt.
+//!
+//! Only the values replaced through the [`ReclaimingArcSwap`] are deferred. If the storage is
+//! updated directly (through [`ReclaimingArcSwap::storage`]) or someone keeps a clone of a value
+//! and drops it later, the value may still be destroyed elsewhere. Also note that the values are
+//! kept alive until the next collection, so the memory consumption is higher.
+//!
+//! This is available only with the standard library (it is not available with the
+//! `experimental-thread-local` and `custom-thread-local` features).
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::sync::Arc;
+//!
+//! use arc_swap::reclaim::{Reclaimer, ReclaimingArcSwap};
+//!
+//! let reclaimer = Arc::new(Reclaimer::new());
+//! let index = ReclaimingArcSwap::new(Arc::new(vec![0u8; 1024]), Arc::clone(&reclaimer));
+//!
+//! let guard = index.load();
+//! index.store(Arc::new(vec![1u8; 1024]));
+//! // Not destroyed here, even though this was the last reader of the old value.
+//! drop(guard);
+//! assert_eq!(1, reclaimer.pending());
+//!
+//! // But here.
+//! assert_eq!(1, reclaimer.collect());
+//! assert_eq!(0, reclaimer.pending());
+//! ```
+
+use std::io::Result as IoResult;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::vec::Vec;
+
+use crate::strategy::{DefaultStrategy, Strategy};
+use crate::{ArcSwapAny, Guard};
+
+/// A holder of replaced values, destroying them once they are not used.
+///
+/// See the [module documentation](index.html) for details.
+#[derive(Debug)]
+pub struct Reclaimer<T> {
+    retired: Mutex<Vec<Arc<T>>>,
+}
+
+impl<T> Default for Reclaimer<T> {
+    fn default() -> Self {
+        Reclaimer {
+            retired: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<T> Reclaimer<T> {
+    /// Creates an empty reclaimer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Arc<T>>> {
+        // Nothing can panic while the lock is held in a way that would leave the list broken (the
+        // values are destroyed outside of it).
+        self.retired.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Hands a value over to the reclaimer.
+    ///
+    /// It'll be destroyed during some future [`collect`][Reclaimer::collect], once no one else
+    /// uses it.
+    pub fn retire(&self, val: Arc<T>) {
+        self.lock().push(val);
+    }
+
+    /// Number of values waiting to be destroyed.
+    pub fn pending(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Destroys the values that are no longer used elsewhere.
+    ///
+    /// The destruction happens in the current thread, after releasing the internal lock. The
+    /// values that are still used are kept for the next time.
+    ///
+    /// Returns the number of destroyed values.
+    pub fn collect(&self) -> usize {
+        let unused = {
+            let mut retired = self.lock();
+            let mut unused = Vec::new();
+            let mut used = Vec::with_capacity(retired.len());
+            for val in retired.drain(..) {
+                // Unlike checking the reference count, this can't race with someone upgrading
+                // a weak reference.
+                match Arc::try_unwrap(val) {
+                    Ok(val) => unused.push(val),
+                    Err(val) => used.push(val),
+                }
+            }
+            *retired = used;
+            unused
+        };
+        unused.len()
+    }
+}
+
+impl<T: Send + Sync + 'static> Reclaimer<T> {
+    /// Starts a background thread periodically calling [`collect`][Reclaimer::collect].
+    ///
+    /// The thread stops when the returned handle is dropped (and it does one last collection
+    /// before it does so).
+    pub fn spawn(reclaimer: &Arc<Self>, interval: Duration) -> IoResult<Collector> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("arc-swap-reclaim".to_owned())
+            .spawn({
+                let reclaimer = Arc::clone(reclaimer);
+                move || {
+                    // Nothing is ever sent, we wait for the other end to be dropped.
+                    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                        reclaimer.collect();
+                    }
+                    reclaimer.collect();
+                }
+            })?;
+        Ok(Collector {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+}
+
+/// The handle of a background collection thread.
+///
+/// Created by [`Reclaimer::spawn`]. Dropping it stops the thread.
+#[derive(Debug)]
+pub struct Collector {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        // Wakes up the thread and makes it terminate.
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            // A panic in a destructor of the value. Don't double-panic in here.
+            let _ = thread.join();
+        }
+    }
+}
+
+/// An [`ArcSwap`][crate::ArcSwap] handing the replaced values to a [`Reclaimer`].
+///
+/// See the [module documentation](index.html) for details.
+#[derive(Debug)]
+pub struct ReclaimingArcSwap<T, S: Strategy<Arc<T>> = DefaultStrategy> {
+    storage: ArcSwapAny<Arc<T>, S>,
+    reclaimer: Arc<Reclaimer<T>>,
+}
+
+impl<T> ReclaimingArcSwap<T> {
+    /// Creates a new storage, handing the replaced values to the given reclaimer.
+    ///
+    /// A reclaimer can be shared between multiple storages.
+    pub fn new(val: Arc<T>, reclaimer: Arc<Reclaimer<T>>) -> Self {
+        Self::with_strategy(val, reclaimer, DefaultStrategy::default())
+    }
+}
+
+impl<T, S: Strategy<Arc<T>>> ReclaimingArcSwap<T, S> {
+    /// Creates a new storage while customizing the protection strategy.
+    pub fn with_strategy(val: Arc<T>, reclaimer: Arc<Reclaimer<T>>, strategy: S) -> Self {
+        ReclaimingArcSwap {
+            storage: ArcSwapAny::with_strategy(val, strategy),
+            reclaimer,
+        }
+    }
+
+    /// Loads the current value.
+    ///
+    /// See [`ArcSwapAny::load`].
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    #[inline]
+    pub fn load(&self) -> Guard<Arc<T>, S> {
+        self.storage.load()
+    }
+
+    /// Loads the current value as a full `Arc`.
+    pub fn load_full(&self) -> Arc<T> {
+        self.storage.load_full()
+    }
+
+    /// Replaces the value, handing the old one to the reclaimer.
+    pub fn store(&self, val: Arc<T>) {
+        self.reclaimer.retire(self.storage.swap(val));
+    }
+
+    /// The reclaimer the replaced values go to.
+    pub fn reclaimer(&self) -> &Arc<Reclaimer<T>> {
+        &self.reclaimer
+    }
+
+    /// The underlying storage.
+    ///
+    /// This can be used to plug it into the APIs for reading (like the [`Cache`][crate::Cache]).
+    /// Storing into it directly would bypass the reclaimer.
+    pub fn storage(&self) -> &ArcSwapAny<Arc<T>, S> {
+        &self.storage
+    }
+}
+
+impl<T, S: Strategy<Arc<T>>> Drop for ReclaimingArcSwap<T, S> {
+    fn drop(&mut self) {
+        // Don't destroy the last value here either. We can't take it out of the storage, so we
+        // keep another reference in the reclaimer.
+        self.reclaimer.retire(self.storage.load_full());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Remembers the thread it got dropped in.
+    struct DropIn(Arc<Mutex<Option<thread::ThreadId>>>);
+
+    impl Drop for DropIn {
+        fn drop(&mut self) {
+            *self.0.lock().unwrap() = Some(thread::current().id());
+        }
+    }
+
+    #[test]
+    fn kept_until_unused() {
+        let reclaimer = Arc::new(Reclaimer::new());
+        let shared = ReclaimingArcSwap::new(Arc::new(1), Arc::clone(&reclaimer));
+        let held = shared.load_full();
+        shared.store(Arc::new(2));
+        assert_eq!(1, reclaimer.pending());
+        assert_eq!(0, reclaimer.collect());
+        assert_eq!(1, reclaimer.pending());
+        drop(held);
+        assert_eq!(1, reclaimer.collect());
+        assert_eq!(0, reclaimer.pending());
+        assert_eq!(2, **shared.load());
+    }
+
+    #[test]
+    fn last_value_on_drop() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        struct Counted(Arc<AtomicUsize>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let reclaimer = Arc::new(Reclaimer::new());
+        let shared = ReclaimingArcSwap::new(
+            Arc::new(Counted(Arc::clone(&dropped))),
+            Arc::clone(&reclaimer),
+        );
+        drop(shared);
+        assert_eq!(0, dropped.load(Ordering::Relaxed));
+        assert_eq!(1, reclaimer.collect());
+        assert_eq!(1, dropped.load(Ordering::Relaxed));
+    }
+
+    /// The background thread destroys the value, not the reader.
+    #[test]
+    fn background() {
+        let dropped_in = Arc::new(Mutex::new(None));
+        let reclaimer = Arc::new(Reclaimer::new());
+        let collector = Reclaimer::spawn(&reclaimer, Duration::from_millis(1)).unwrap();
+        let shared = ReclaimingArcSwap::new(
+            Arc::new(DropIn(Arc::clone(&dropped_in))),
+            Arc::clone(&reclaimer),
+        );
+        let guard = shared.load();
+        shared.store(Arc::new(DropIn(Arc::new(Mutex::new(None)))));
+        drop(guard);
+        while dropped_in.lock().unwrap().is_none() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_ne!(Some(thread::current().id()), *dropped_in.lock().unwrap());
+        drop(collector);
+    }
+}
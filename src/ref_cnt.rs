@@ -19,7 +19,7 @@ use crate::imports::{Arc, Rc};
 ///
 /// It is also implemented for [Rc], but that is not considered very useful (because the
 /// [ArcSwapAny] is not `Send` or `Sync`, therefore there's very little advantage for it to be
-/// atomic).
+/// atomic). The [`CellSwap`][crate::cell::CellSwap] is a better fit for that.
 ///
 /// # Safety
 ///
@@ -126,6 +126,22 @@ unsafe impl<T> RefCnt for Arc<T> {
     }
 }
 
+/// The `Arc` of loom, so the model checker tracks the values (their leaks included) in the loom
+/// tests.
+#[cfg(loom)]
+unsafe impl<T> RefCnt for loom::sync::Arc<T> {
+    type Base = T;
+    fn into_ptr(me: Self) -> *mut T {
+        loom::sync::Arc::into_raw(me) as *mut T
+    }
+    fn as_ptr(me: &Self) -> *mut T {
+        loom::sync::Arc::as_ptr(me) as *mut T
+    }
+    unsafe fn from_ptr(ptr: *const T) -> Self {
+        loom::sync::Arc::from_raw(ptr)
+    }
+}
+
 unsafe impl<T> RefCnt for Rc<T> {
     type Base = T;
     fn into_ptr(me: Rc<T>) -> *mut T {
@@ -0,0 +1,455 @@
+//! Reloading a value from a file when it changes.
+//!
+//! A common use of the [`ArcSwap`] is holding a configuration that gets reloaded from a file
+//! (see the [patterns][crate::docs::patterns]). This module provides a ready-made driver for
+//! that. A background thread watches the file, parses it whenever it changes and stores the
+//! result.
+//!
+//! The file is polled ‒ both its modification time and a hash of its content are checked, so
+//! changes are noticed even on file systems with coarse timestamps. Once a change is seen, the
+//! file needs to stay unchanged for the debounce interval before it is loaded, so a file being
+//! written in several steps is not loaded half-way through.
+//!
+//! If the file can't be read, parsed or doesn't pass validation, the error is reported through a
+//! callback and the previous (good) value stays in place.
+//!
+//! This needs the `reload` feature.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use arc_swap::reload::Watcher;
+//!
+//! # let path = std::env::temp_dir().join(format!("arc-swap-doc-{}", std::process::id()));
+//! # std::fs::write(&path, "42").unwrap();
+//! let parse = |content: &[u8]| -> Result<u32, String> {
+//!     String::from_utf8_lossy(content).trim().parse().map_err(|e| format!("{}", e))
+//! };
+//! let reloader = Watcher::new(&path, parse, Duration::from_millis(100))
+//!     .validate(|value: &u32| if *value > 0 { Ok(()) } else { Err("Zero".to_owned()) })
+//!     .on_error(|error| eprintln!("Failed to reload config: {}", error))
+//!     .start()
+//!     .expect("Initial load failed");
+//!
+//! assert_eq!(42, **reloader.load());
+//! // Dropping it stops the background thread.
+//! drop(reloader);
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+
+use std::boxed::Box;
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{ArcSwap, Guard};
+
+/// An error while loading the file.
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The file couldn't be read.
+    Io(IoError),
+    /// The parse function failed.
+    Parse(E),
+    /// The parsed value didn't pass the validation.
+    Invalid(E),
+}
+
+impl<E: Display> Display for Error<E> {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            Error::Io(e) => write!(fmt, "Failed to read: {}", e),
+            Error::Parse(e) => write!(fmt, "Failed to parse: {}", e),
+            Error::Invalid(e) => write!(fmt, "Invalid: {}", e),
+        }
+    }
+}
+
+impl<E: StdError + 'static> StdError for Error<E> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Parse(e) | Error::Invalid(e) => Some(e),
+        }
+    }
+}
+
+type Validate<T, E> = Box<dyn Fn(&T) -> Result<(), E> + Send>;
+type OnError<E> = Box<dyn Fn(Error<E>) + Send>;
+
+/// Configuration of the file watching.
+///
+/// Once configured, the watching is started with [`start`][Watcher::start].
+pub struct Watcher<T, E, P> {
+    path: PathBuf,
+    parse: P,
+    debounce: Duration,
+    poll_interval: Duration,
+    validate: Option<Validate<T, E>>,
+    on_error: Option<OnError<E>>,
+}
+
+impl<T, E, P> Watcher<T, E, P>
+where
+    T: Send + Sync + 'static,
+    E: Send + 'static,
+    P: Fn(&[u8]) -> Result<T, E> + Send + 'static,
+{
+    /// Creates the configuration.
+    ///
+    /// The file on the `path` is parsed with the `parse` function. Once a change is detected, it
+    /// needs to stay the same for the `debounce` interval before it is loaded.
+    ///
+    /// The file is checked once per second by default, but at least as often as the `debounce`
+    /// interval (and at most once per millisecond).
+    pub fn new<Pa: AsRef<Path>>(path: Pa, parse: P, debounce: Duration) -> Self {
+        Watcher {
+            path: path.as_ref().to_owned(),
+            parse,
+            debounce,
+            poll_interval: debounce
+                .min(Duration::from_secs(1))
+                .max(Duration::from_millis(1)),
+            validate: None,
+            on_error: None,
+        }
+    }
+
+    /// Sets how often the file is checked for changes.
+    pub fn poll_interval(self, poll_interval: Duration) -> Self {
+        Watcher {
+            poll_interval,
+            ..self
+        }
+    }
+
+    /// Sets a validation of the parsed values.
+    ///
+    /// Values not passing it are not stored and reported as errors.
+    pub fn validate<V>(self, validate: V) -> Self
+    where
+        V: Fn(&T) -> Result<(), E> + Send + 'static,
+    {
+        Watcher {
+            validate: Some(Box::new(validate)),
+            ..self
+        }
+    }
+
+    /// Sets the callback for errors during reloading.
+    ///
+    /// By default, the errors are silently ignored (and the old value is kept).
+    pub fn on_error<C>(self, on_error: C) -> Self
+    where
+        C: Fn(Error<E>) + Send + 'static,
+    {
+        Watcher {
+            on_error: Some(Box::new(on_error)),
+            ..self
+        }
+    }
+
+    /// Loads the file for the first time and starts watching it.
+    ///
+    /// The initial load happens right away, in the current thread. If it fails, the error is
+    /// returned (the error callback is not called for it).
+    pub fn start(self) -> Result<Reloader<T>, Error<E>> {
+        let mut state = State {
+            watcher: self,
+            loaded: Snapshot::default(),
+            seen: Snapshot::default(),
+            seen_since: Instant::now(),
+        };
+        let content = fs::read(&state.watcher.path).map_err(Error::Io)?;
+        let snapshot = Snapshot::new(&state.watcher.path, &content);
+        let value = state.parse(&content)?;
+        state.loaded = snapshot.clone();
+        state.seen = snapshot;
+
+        let storage = Arc::new(ArcSwap::from_pointee(value));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("arc-swap-reload".to_owned())
+            .spawn({
+                let storage = Arc::clone(&storage);
+                move || {
+                    // Nothing is ever sent, we wait for the other end to be dropped.
+                    while let Err(RecvTimeoutError::Timeout) =
+                        stopped.recv_timeout(state.watcher.poll_interval)
+                    {
+                        state.poll(&storage);
+                    }
+                }
+            })
+            .map_err(Error::Io)?;
+
+        Ok(Reloader {
+            storage,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+}
+
+/// What the file looked like at some point.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Snapshot {
+    modified: Option<SystemTime>,
+    hash: u64,
+}
+
+impl Snapshot {
+    fn new(path: &Path, content: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        Snapshot {
+            modified: fs::metadata(path).and_then(|m| m.modified()).ok(),
+            hash: hasher.finish(),
+        }
+    }
+}
+
+/// The state of the background thread.
+struct State<T, E, P> {
+    watcher: Watcher<T, E, P>,
+    /// The file as it was the last time we tried to load it (successfully or not).
+    loaded: Snapshot,
+    /// The file as it was the last time we looked.
+    seen: Snapshot,
+    /// Since when the file looks like `seen`.
+    seen_since: Instant,
+}
+
+impl<T, E, P> State<T, E, P>
+where
+    P: Fn(&[u8]) -> Result<T, E>,
+{
+    fn parse(&self, content: &[u8]) -> Result<T, Error<E>> {
+        let value = (self.watcher.parse)(content).map_err(Error::Parse)?;
+        if let Some(validate) = &self.watcher.validate {
+            validate(&value).map_err(Error::Invalid)?;
+        }
+        Ok(value)
+    }
+
+    fn report(&self, error: Error<E>) {
+        if let Some(on_error) = &self.watcher.on_error {
+            on_error(error);
+        }
+    }
+
+    fn poll(&mut self, storage: &ArcSwap<T>) {
+        let content = match fs::read(&self.watcher.path) {
+            Ok(content) => content,
+            Err(e) => {
+                // Report it only once, not on every poll.
+                let missing = Snapshot::default();
+                if self.loaded != missing {
+                    self.loaded = missing.clone();
+                    self.seen = missing;
+                    self.report(Error::Io(e));
+                }
+                return;
+            }
+        };
+        let snapshot = Snapshot::new(&self.watcher.path, &content);
+        if snapshot != self.seen {
+            // It's changing, start the debounce anew.
+            self.seen = snapshot;
+            self.seen_since = Instant::now();
+        }
+        if self.seen != self.loaded && self.seen_since.elapsed() >= self.watcher.debounce {
+            self.loaded = self.seen.clone();
+            match self.parse(&content) {
+                Ok(value) => storage.store(Arc::new(value)),
+                Err(e) => self.report(e),
+            }
+        }
+    }
+}
+
+/// The handle of a running file watcher.
+///
+/// Created by [`Watcher::start`]. Dropping it stops the watching.
+pub struct Reloader<T> {
+    storage: Arc<ArcSwap<T>>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T> Reloader<T> {
+    /// Loads the current value.
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    #[inline]
+    pub fn load(&self) -> Guard<Arc<T>> {
+        self.storage.load()
+    }
+
+    /// The storage the values are loaded into.
+    ///
+    /// It can be cloned and passed to other places that need the value (this is what would be
+    /// usually passed to the worker threads). It stays valid even after the [`Reloader`] is
+    /// dropped, it just no longer gets updated.
+    pub fn storage(&self) -> &Arc<ArcSwap<T>> {
+        &self.storage
+    }
+}
+
+impl<T> Drop for Reloader<T> {
+    fn drop(&mut self) {
+        // Wakes up the thread and makes it terminate.
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            // A panic in the parse function or the callback. Don't double-panic in here.
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::format;
+    use std::string::{String, ToString};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::vec::Vec;
+
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    /// A file in the temp directory, removed at the end.
+    struct TmpFile(PathBuf);
+
+    impl TmpFile {
+        fn new(name: &str, content: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "arc-swap-reload-{}-{}",
+                std::process::id(),
+                name
+            ));
+            fs::write(&path, content).unwrap();
+            TmpFile(path)
+        }
+
+        fn write(&self, content: &str) {
+            fs::write(&self.0, content).unwrap();
+        }
+    }
+
+    impl Drop for TmpFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn parse(content: &[u8]) -> Result<usize, String> {
+        std::str::from_utf8(content)
+            .map_err(|e| e.to_string())?
+            .trim()
+            .parse()
+            .map_err(|e: std::num::ParseIntError| e.to_string())
+    }
+
+    /// Waits (with a timeout) for the condition to become true.
+    fn wait_for<F: Fn() -> bool>(cond: F) {
+        let start = Instant::now();
+        while !cond() {
+            assert!(start.elapsed() < Duration::from_secs(10), "Timed out");
+            thread::sleep(TICK);
+        }
+    }
+
+    #[test]
+    fn reloads() {
+        let file = TmpFile::new("reloads", "1");
+        let reloader = Watcher::new(&file.0, parse, TICK).start().unwrap();
+        assert_eq!(1, **reloader.load());
+        file.write("2");
+        wait_for(|| **reloader.load() == 2);
+    }
+
+    #[test]
+    fn initial_error() {
+        let file = TmpFile::new("initial_error", "hello");
+        match Watcher::new(&file.0, parse, TICK).start() {
+            Err(Error::Parse(_)) => (),
+            _ => panic!("Should have failed to parse"),
+        }
+        let missing = file.0.with_extension("missing");
+        match Watcher::new(&missing, parse, TICK).start() {
+            Err(Error::Io(_)) => (),
+            _ => panic!("Should have failed to read"),
+        }
+    }
+
+    /// Errors are reported, the good value stays and the next good one gets loaded.
+    #[test]
+    fn keeps_good_value() {
+        let file = TmpFile::new("keeps_good_value", "1");
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let reloader = Watcher::new(&file.0, parse, TICK)
+            .validate(|v: &usize| {
+                if *v < 10 {
+                    Ok(())
+                } else {
+                    Err("Too large".to_owned())
+                }
+            })
+            .on_error({
+                let errors = Arc::clone(&errors);
+                move |e| errors.lock().unwrap().push(e.to_string())
+            })
+            .start()
+            .unwrap();
+
+        file.write("garbage");
+        wait_for(|| errors.lock().unwrap().len() == 1);
+        assert!(errors.lock().unwrap()[0].starts_with("Failed to parse"));
+        file.write("42");
+        wait_for(|| errors.lock().unwrap().len() == 2);
+        assert_eq!("Invalid: Too large", errors.lock().unwrap()[1]);
+        assert_eq!(1, **reloader.load());
+
+        file.write("3");
+        wait_for(|| **reloader.load() == 3);
+        // Reported only once each.
+        thread::sleep(TICK * 5);
+        assert_eq!(2, errors.lock().unwrap().len());
+    }
+
+    /// The file isn't loaded until it stops changing for the debounce interval.
+    #[test]
+    fn debounce() {
+        let file = TmpFile::new("debounce", "1");
+        let parsed = Arc::new(AtomicUsize::new(0));
+        let reloader = Watcher::new(
+            &file.0,
+            {
+                let parsed = Arc::clone(&parsed);
+                move |content: &[u8]| {
+                    parsed.fetch_add(1, Ordering::Relaxed);
+                    parse(content)
+                }
+            },
+            Duration::from_secs(60),
+        )
+        .poll_interval(TICK)
+        .start()
+        .unwrap();
+        file.write("2");
+        thread::sleep(TICK * 10);
+        assert_eq!(1, parsed.load(Ordering::Relaxed));
+        assert_eq!(1, **reloader.load());
+    }
+}
@@ -0,0 +1,351 @@
+//! A storage replicated into multiple shards, for read-mostly data on many cores.
+//!
+//! Even the single atomic load of the pointer inside [`ArcSwapAny::load`] makes the CPUs fight
+//! over the cache line after each write, and on machines with many cores that shows up. The
+//! [`Replicated`] keeps a separate copy of the pointer for each shard, each on its own cache line.
+//! Readers look only into the shard belonging to their thread, while writers update all of them.
+//!
+//! In other words, this trades the write performance (which is linear in the number of shards)
+//! for read scalability. It is a good fit for a „hot“ configuration that is read all the time and
+//! reloaded only rarely.
+//!
+//! Each thread is assigned a shard based on its thread-local data, so it stays the same for the
+//! life of the thread (but several threads may share a shard). A good number of shards is
+//! therefore around the number of CPUs.
+//!
+//! # Consistency
+//!
+//! Once [`store`][Replicated::store] returns, all the shards contain the new value (or a newer
+//! one, if there are concurrent writers). While a store is in progress, readers on different
+//! threads may see different values.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::sync::Arc;
+//!
+//! use arc_swap::replicated::ReplicatedArcSwap;
+//! use arc_swap::access::{Access, Map};
+//!
+//! struct Config {
+//!     name: String,
+//!     debug: bool,
+//! }
+//!
+//! let config = ReplicatedArcSwap::new(
+//!     Arc::new(Config { name: "Hello".to_owned(), debug: false }),
+//!     8,
+//! );
+//! assert!(!config.load().debug);
+//!
+//! // It can be used with the Access API...
+//! let name = Map::new(&config, |c: &Config| &c.name);
+//! assert_eq!("Hello", *name.load());
+//!
+//! // ... and with caches.
+//! let mut cache = config.cache();
+//! assert_eq!("Hello", cache.load().name);
+//!
+//! config.store(Arc::new(Config { name: "World".to_owned(), debug: true }));
+//! assert!(config.load().debug);
+//! assert_eq!("World", *name.load());
+//! assert_eq!("World", cache.load().name);
+//! ```
+
+use core::fmt::{Debug, Formatter, Result as FmtResult};
+use core::ops::Deref;
+
+use crate::cache::{Access as CacheAccess, Cache};
+use crate::debt::LocalNode;
+use crate::imports::{Arc, Box, Vec};
+use crate::ref_cnt::RefCnt;
+use crate::strategy::sealed::Local;
+use crate::strategy::{CaS, DefaultStrategy, Strategy};
+use crate::{ArcSwapAny, Guard};
+
+/// A replicated version of the [`ArcSwap`][crate::ArcSwap].
+pub type ReplicatedArcSwap<T> = Replicated<Arc<T>>;
+
+/// One shard, on its own cache line.
+///
+/// Most CPUs have 64-byte cache lines, but some prefetch them in pairs, so we go for 128.
+#[repr(align(128))]
+struct Shard<T: RefCnt, S: Strategy<T>>(ArcSwapAny<T, S>);
+
+/// A replicated version of the [`ArcSwapAny`].
+///
+/// See the [module documentation](index.html) for details.
+pub struct Replicated<T: RefCnt, S: Strategy<T> = DefaultStrategy> {
+    /// The authoritative value, used by the writers to agree on what goes into the shards.
+    ///
+    /// Readers don't touch this one.
+    master: ArcSwapAny<T, S>,
+    shards: Box<[Shard<T, S>]>,
+}
+
+impl<T: RefCnt, S: Strategy<T>> Replicated<T, S> {
+    /// Creates a new storage with the given number of shards.
+    ///
+    /// # Panics
+    ///
+    /// If `shards` is 0.
+    pub fn new(val: T, shards: usize) -> Self
+    where
+        S: Default,
+    {
+        assert!(shards > 0, "Replicated needs at least one shard");
+        let shards = (0..shards)
+            .map(|_| Shard(ArcSwapAny::new(val.clone())))
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        Replicated {
+            master: ArcSwapAny::new(val),
+            shards,
+        }
+    }
+
+    /// The number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard_for(&self, node: &LocalNode) -> &ArcSwapAny<T, S> {
+        // The thread-local data has a stable address for the life of the thread. Hash it a bit,
+        // because the addresses of different threads likely differ only in few bits.
+        let addr = node as *const LocalNode as usize;
+        let hash = (addr >> 4).wrapping_mul(0x9E37_79B9) >> 8;
+        &self.shards[hash % self.shards.len()].0
+    }
+
+    /// The shard used by the current thread.
+    ///
+    /// The shard can only be read from, writing to it directly would get the shards out of sync.
+    pub fn local_shard(&self) -> ShardView<'_, T, S> {
+        ShardView(LocalNode::with(|node| self.shard_for(node)))
+    }
+
+    /// Loads the value from the shard of the current thread.
+    ///
+    /// This is the equivalent of [`ArcSwapAny::load`].
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    #[inline]
+    pub fn load(&self) -> Guard<T, S> {
+        let protected = LocalNode::with(|node| {
+            let shard = self.shard_for(node);
+            unsafe { shard.strategy.load_local(Local(node), &shard.ptr) }
+        });
+        // Outside of the closure, so the debug-guards feature sees the right caller.
+        Guard::new(protected)
+    }
+
+    /// Loads the full value from the shard of the current thread.
+    ///
+    /// This is the equivalent of [`ArcSwapAny::load_full`].
+    pub fn load_full(&self) -> T {
+        Guard::into_inner(self.load())
+    }
+
+    /// Creates a cache over the shard of the current thread.
+    ///
+    /// The cache is bound to the shard of the thread creating it. It is still correct to use it
+    /// from a different thread (if it is [`Send`]), only slightly less efficient.
+    pub fn cache(&self) -> ShardCache<'_, T, S> {
+        ShardCache(Cache::new(self.local_shard().0))
+    }
+
+    /// Replaces the value in all the shards.
+    ///
+    /// This costs about as much as one [`ArcSwapAny::store`] per shard. Concurrent stores are
+    /// allowed and the shards end up with the value of the one that came last.
+    pub fn store(&self, val: T)
+    where
+        S: CaS<T>,
+    {
+        self.master.store(val);
+        for shard in self.shards.iter() {
+            // Bring the shard up to date with the master, but don't overwrite a newer value a
+            // concurrent writer might have already put there. If someone changes the shard while
+            // we do this, the CaS fails and we check again.
+            loop {
+                let current = shard.0.load();
+                let wanted = self.master.load_full();
+                let current_ptr = T::as_ptr(&current);
+                if current_ptr == T::as_ptr(&wanted) {
+                    break;
+                }
+                let previous = shard.0.compare_and_swap(current_ptr, wanted);
+                if T::as_ptr(&previous) == current_ptr {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// A read-only view of one shard of the [`Replicated`].
+///
+/// See [`Replicated::local_shard`].
+pub struct ShardView<'a, T: RefCnt, S: Strategy<T> = DefaultStrategy>(&'a ArcSwapAny<T, S>);
+
+impl<T: RefCnt, S: Strategy<T>> ShardView<'_, T, S> {
+    /// Loads the value from the shard.
+    ///
+    /// See [`ArcSwapAny::load`].
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    #[inline]
+    pub fn load(&self) -> Guard<T, S> {
+        self.0.load()
+    }
+
+    /// Loads the full value from the shard.
+    ///
+    /// See [`ArcSwapAny::load_full`].
+    pub fn load_full(&self) -> T {
+        self.0.load_full()
+    }
+}
+
+impl<T: RefCnt, S: Strategy<T>> Clone for ShardView<'_, T, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: RefCnt, S: Strategy<T>> Copy for ShardView<'_, T, S> {}
+
+impl<T: Debug + RefCnt, S: Strategy<T>> Debug for ShardView<'_, T, S> {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        formatter
+            .debug_tuple("ShardView")
+            .field(&self.load())
+            .finish()
+    }
+}
+
+/// A [`Cache`] over one shard of the [`Replicated`].
+///
+/// Unlike the plain [`Cache`], it doesn't give access to the shard itself. See
+/// [`Replicated::cache`].
+#[derive(Clone)]
+pub struct ShardCache<'a, T: RefCnt, S: Strategy<T> = DefaultStrategy>(
+    Cache<&'a ArcSwapAny<T, S>, T>,
+);
+
+impl<T: RefCnt, S: Strategy<T>> ShardCache<'_, T, S> {
+    /// Loads the currently held value.
+    ///
+    /// See [`Cache::load`].
+    #[inline]
+    pub fn load(&mut self) -> &T {
+        self.0.load()
+    }
+}
+
+impl<T, S> CacheAccess<T::Target> for ShardCache<'_, T, S>
+where
+    T: Deref<Target = <T as RefCnt>::Base> + RefCnt,
+    S: Strategy<T>,
+{
+    fn load(&mut self) -> &T::Target {
+        self.0.load().deref()
+    }
+}
+
+impl<T: Debug + RefCnt, S: Strategy<T>> Debug for ShardCache<'_, T, S> {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        formatter.debug_tuple("ShardCache").field(&self.0).finish()
+    }
+}
+
+impl<T, S> Debug for Replicated<T, S>
+where
+    T: Debug + RefCnt,
+    S: Strategy<T>,
+{
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        formatter
+            .debug_tuple("Replicated")
+            .field(&self.load())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+
+    use crossbeam_utils::thread;
+
+    use super::*;
+    use crate::access::{Access, Map};
+
+    #[test]
+    fn load_store() {
+        let shared = ReplicatedArcSwap::new(Arc::new(1), 4);
+        assert_eq!(4, shared.shard_count());
+        assert_eq!(1, **shared.load());
+        shared.store(Arc::new(2));
+        assert_eq!(2, **shared.load());
+        assert!(shared.shards.iter().all(|s| **s.0.load() == 2));
+    }
+
+    /// The old values are released after the store.
+    #[test]
+    fn releases() {
+        let first = Arc::new(0);
+        let shared = ReplicatedArcSwap::new(Arc::clone(&first), 3);
+        assert_eq!(5, Arc::strong_count(&first));
+        shared.store(Arc::new(1));
+        assert_eq!(1, Arc::strong_count(&first));
+    }
+
+    #[test]
+    fn access_and_cache() {
+        let shared = ReplicatedArcSwap::new(Arc::new((1, 2)), 2);
+        let second = Map::new(&shared, |v: &(usize, usize)| &v.1);
+        let mut cache = shared.cache();
+        assert_eq!(2, *second.load());
+        assert_eq!(1, cache.load().0);
+        shared.store(Arc::new((3, 4)));
+        assert_eq!(4, *second.load());
+        assert_eq!(3, cache.load().0);
+    }
+
+    /// The views of the shard see the stores to the whole storage.
+    #[test]
+    fn shard_view() {
+        let shared = ReplicatedArcSwap::new(Arc::new(1), 2);
+        let view = shared.local_shard();
+        let mut cache = shared.cache();
+        assert_eq!(1, **view.load());
+        shared.store(Arc::new(2));
+        assert_eq!(2, *view.load_full());
+        assert_eq!(2, *CacheAccess::load(&mut cache));
+    }
+
+    /// Concurrent writers leave all the shards with the same value.
+    #[test]
+    fn concurrent_stores() {
+        let shared = ReplicatedArcSwap::new(Arc::new(0), 8);
+        thread::scope(|s| {
+            for i in 0..4 {
+                let shared = &shared;
+                s.spawn(move |_| {
+                    for j in 0..100 {
+                        shared.store(Arc::new(i * 1000 + j));
+                        let _ = shared.load();
+                    }
+                });
+            }
+        })
+        .unwrap();
+        let values = shared
+            .shards
+            .iter()
+            .map(|s| **s.0.load())
+            .collect::<Vec<_>>();
+        assert!(values.iter().all(|v| *v == values[0]));
+        assert_eq!(values[0], **shared.master.load());
+    }
+}
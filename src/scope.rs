@@ -0,0 +1,186 @@
+//! Loading multiple values within a single scope.
+//!
+//! Each [`load`][ArcSwapAny::load] needs to look up the thread-local bookkeeping of the current
+//! thread. That is cheap, but not free, and it adds up when many different [`ArcSwapAny`]s are
+//! loaded in a tight loop.
+//!
+//! The [`with_local`] function looks the thread-local data up only once and provides a
+//! [`LocalScope`] through which any number of loads can be done.
+//!
+//! The guards of these loads are [`LocalGuard`]s, bound to the scope. The scope is meant for
+//! short-lived loads and the thread has only few debt slots ‒ guards piling up past it would push
+//! the later loads to the slower path. A value needed for longer can be turned into the full
+//! `T` by [`LocalGuard::into_inner`].
+//!
+//! # Reserving slots
+//!
+//! The scope doesn't reserve the debt slots for its loads up front. A slot is taken by storing the
+//! loaded pointer into it (a read-write operation each load needs anyway) and the writers give it
+//! back by resetting it to empty when they pay the debt. A reserved slot would therefore stop being
+//! reserved behind the scope's back, unless every writer checked for a reservation mark on every
+//! slot it looks at. The slots belong to the thread, so the only competition for them are the
+//! thread's own guards anyway.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arc_swap::ArcSwap;
+//!
+//! let a = ArcSwap::from_pointee(1);
+//! let b = ArcSwap::from_pointee(2);
+//!
+//! let sum = arc_swap::with_local(|scope| {
+//!     let a = scope.load(&a);
+//!     let b = scope.load(&b);
+//!     **a + **b
+//! });
+//! assert_eq!(3, sum);
+//! ```
+
+use core::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+use crate::debt::LocalNode;
+use crate::ref_cnt::RefCnt;
+use crate::strategy::sealed::Local;
+use crate::strategy::Strategy;
+use crate::{ArcSwapAny, Guard};
+
+/// A handle to the thread-local data of the current thread.
+///
+/// This is available only inside the [`with_local`] closure. See the [module
+/// documentation](index.html).
+pub struct LocalScope<'scope> {
+    node: &'scope LocalNode,
+}
+
+impl<'scope> LocalScope<'scope> {
+    /// Loads the value from the given storage.
+    ///
+    /// This is equivalent to [`ArcSwapAny::load`], but skips the thread-local lookup. The guard
+    /// can't leave the scope.
+    #[cfg_attr(feature = "debug-guards", track_caller)]
+    #[inline]
+    pub fn load<T: RefCnt, S: Strategy<T>>(
+        &self,
+        storage: &ArcSwapAny<T, S>,
+    ) -> LocalGuard<'scope, T, S> {
+        // The node may have been sent to cooldown by the previous load.
+        self.node.ensure_node();
+        let protected = unsafe { storage.strategy.load_local(Local(self.node), &storage.ptr) };
+        LocalGuard {
+            guard: Guard::new(protected),
+            _scope: PhantomData,
+        }
+    }
+}
+
+/// A guard of a load through the [`LocalScope`].
+///
+/// This is like the [`Guard`], but it can't outlive the scope.
+pub struct LocalGuard<'scope, T: RefCnt, S: Strategy<T>> {
+    guard: Guard<T, S>,
+    _scope: PhantomData<&'scope LocalNode>,
+}
+
+impl<T: RefCnt, S: Strategy<T>> LocalGuard<'_, T, S> {
+    /// Converts it into the held value, to keep it past the scope.
+    ///
+    /// See [`Guard::into_inner`].
+    #[inline]
+    pub fn into_inner(lease: Self) -> T {
+        Guard::into_inner(lease.guard)
+    }
+}
+
+impl<T: RefCnt, S: Strategy<T>> Deref for LocalGuard<'_, T, S> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: Debug + RefCnt, S: Strategy<T>> Debug for LocalGuard<'_, T, S> {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        self.guard.fmt(formatter)
+    }
+}
+
+impl<T: Display + RefCnt, S: Strategy<T>> Display for LocalGuard<'_, T, S> {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        self.guard.fmt(formatter)
+    }
+}
+
+/// Runs the closure with a [`LocalScope`] for doing multiple loads.
+///
+/// Calling [`ArcSwapAny::load`] (or [`store`][ArcSwapAny::store] or anything else) inside the
+/// closure is fine, it is just not taking advantage of the scope.
+///
+/// See the [module documentation](index.html) for an example.
+pub fn with_local<R, F: FnOnce(&LocalScope) -> R>(f: F) -> R {
+    LocalNode::with(|node| f(&LocalScope { node }))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::{ArcSwap, ArcSwapOption};
+
+    #[test]
+    fn load_multiple() {
+        let a = ArcSwap::from_pointee(1);
+        let b = ArcSwapOption::from_pointee(2);
+        with_local(|scope| {
+            let ga = scope.load(&a);
+            let gb = scope.load(&b);
+            assert_eq!(1, **ga);
+            assert_eq!(2, **gb.as_ref().unwrap());
+        });
+    }
+
+    /// Storing from within the scope pays the debts of the guards from the scope.
+    #[test]
+    fn store_inside() {
+        let shared = ArcSwap::from_pointee(0);
+        let old = shared.load_full();
+        with_local(|scope| {
+            let guard = scope.load(&shared);
+            assert_eq!(2, Arc::strong_count(&old));
+            shared.store(Arc::new(1));
+            // The guard got its own copy now.
+            assert_eq!(2, Arc::strong_count(&old));
+            assert_eq!(0, **guard);
+            assert_eq!(1, **scope.load(&shared));
+        });
+        assert_eq!(1, Arc::strong_count(&old));
+    }
+
+    /// Running out of the fast slots inside the scope still works (and survives the node going
+    /// away in the middle).
+    #[test]
+    fn many_guards() {
+        let shared = ArcSwap::from_pointee(42);
+        with_local(|scope| {
+            let guards = (0..100).map(|_| scope.load(&shared)).collect::<Vec<_>>();
+            assert!(guards.iter().all(|g| ***g == 42));
+            shared.store(Arc::new(0));
+            assert!(guards.iter().all(|g| ***g == 42));
+        });
+    }
+
+    /// The values can leave the scope as the full `T`.
+    #[test]
+    fn into_inner() {
+        let shared = ArcSwap::from_pointee(42);
+        let full = with_local(|scope| LocalGuard::into_inner(scope.load(&shared)));
+        shared.store(Arc::new(0));
+        assert_eq!(42, *full);
+        assert_eq!(1, Arc::strong_count(&full));
+    }
+}
@@ -0,0 +1,475 @@
+//! A strategy chosen at runtime.
+//!
+//! The strategy is normally a type parameter of the [`ArcSwapAny`][crate::ArcSwapAny], so
+//! switching to another one means changing the types. The [`DynStrategy`] wraps one of the other
+//! strategies, picked when it is created (for example from a configuration option), and forwards
+//! everything to it. This allows comparing the strategies in a running application.
+//!
+//! The price is a branch on each operation and a bigger guard (it can hold the guard of any of the
+//! wrapped strategies).
+
+use core::borrow::Borrow;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use core::str::FromStr;
+
+use super::hybrid::HybridProtection;
+#[cfg(all(
+    feature = "parking_lot",
+    not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+))]
+use super::lock::ParkingLotStrategy;
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+use super::lock::RwLockStrategy;
+#[cfg(all(feature = "experimental-strategies", not(loom)))]
+use super::membarrier::MembarrierStrategy;
+use super::sealed::{CaS, InnerStrategy, Local, Protected};
+#[cfg(all(
+    feature = "experimental-strategies",
+    target_pointer_width = "64",
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+))]
+use super::split::SplitCountStrategy;
+use super::DefaultStrategy;
+use crate::as_raw::AsRaw;
+use crate::debt::Retirements;
+use crate::ref_cnt::RefCnt;
+use crate::sync::AtomicPtr;
+
+/// Selection of the strategy for the [`DynStrategy`].
+///
+/// Which variants are available depends on the enabled features. The names used by the
+/// [`FromStr`] and [`Display`] implementations are in the descriptions of the variants.
+#[rustversion::attr(since(1.40), non_exhaustive)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum StrategyKind {
+    /// The [`DefaultStrategy`] (`default`).
+    Default,
+    /// The [`RwLockStrategy`][super::RwLockStrategy] (`rw-lock`).
+    ///
+    /// Only with the standard library.
+    #[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+    RwLock,
+    /// The [`ParkingLotStrategy`][super::ParkingLotStrategy] (`parking-lot`).
+    ///
+    /// Only with the `parking_lot` feature.
+    #[cfg(all(
+        feature = "parking_lot",
+        not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+    ))]
+    ParkingLot,
+    /// The [`MembarrierStrategy`][super::MembarrierStrategy] (`membarrier`).
+    ///
+    /// Only with the `experimental-strategies` feature.
+    #[cfg(all(feature = "experimental-strategies", not(loom)))]
+    Membarrier,
+    /// The [`SplitCountStrategy`][super::SplitCountStrategy] (`split-count`).
+    ///
+    /// Only with the `experimental-strategies` feature, on 64-bit x86 and ARM.
+    #[cfg(all(
+        feature = "experimental-strategies",
+        target_pointer_width = "64",
+        any(target_arch = "x86_64", target_arch = "aarch64"),
+    ))]
+    SplitCount,
+}
+
+impl StrategyKind {
+    /// All the kinds available in this build.
+    pub const ALL: &'static [StrategyKind] = &[
+        StrategyKind::Default,
+        #[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+        StrategyKind::RwLock,
+        #[cfg(all(
+            feature = "parking_lot",
+            not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+        ))]
+        StrategyKind::ParkingLot,
+        #[cfg(all(feature = "experimental-strategies", not(loom)))]
+        StrategyKind::Membarrier,
+        #[cfg(all(
+            feature = "experimental-strategies",
+            target_pointer_width = "64",
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+        ))]
+        StrategyKind::SplitCount,
+    ];
+
+    /// The name of the kind, as accepted by the [`FromStr`] implementation.
+    pub fn name(self) -> &'static str {
+        match self {
+            StrategyKind::Default => "default",
+            #[cfg(not(any(
+                feature = "experimental-thread-local",
+                feature = "custom-thread-local"
+            )))]
+            StrategyKind::RwLock => "rw-lock",
+            #[cfg(all(
+                feature = "parking_lot",
+                not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+            ))]
+            StrategyKind::ParkingLot => "parking-lot",
+            #[cfg(all(feature = "experimental-strategies", not(loom)))]
+            StrategyKind::Membarrier => "membarrier",
+            #[cfg(all(
+                feature = "experimental-strategies",
+                target_pointer_width = "64",
+                any(target_arch = "x86_64", target_arch = "aarch64"),
+            ))]
+            StrategyKind::SplitCount => "split-count",
+        }
+    }
+}
+
+// The #[default] attribute needs a newer compiler.
+#[allow(clippy::derivable_impls)]
+impl Default for StrategyKind {
+    fn default() -> Self {
+        StrategyKind::Default
+    }
+}
+
+impl Display for StrategyKind {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        formatter.write_str(self.name())
+    }
+}
+
+/// The error returned when parsing an unknown (or not available) [`StrategyKind`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct UnknownStrategy;
+
+impl Display for UnknownStrategy {
+    fn fmt(&self, formatter: &mut Formatter) -> FmtResult {
+        formatter.write_str("unknown or not available strategy")
+    }
+}
+
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+impl std::error::Error for UnknownStrategy {}
+
+impl FromStr for StrategyKind {
+    type Err = UnknownStrategy;
+    fn from_str(name: &str) -> Result<Self, UnknownStrategy> {
+        StrategyKind::ALL
+            .iter()
+            .find(|kind| kind.name() == name)
+            .cloned()
+            .ok_or(UnknownStrategy)
+    }
+}
+
+enum Inner {
+    Default(DefaultStrategy),
+    #[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+    RwLock(RwLockStrategy),
+    #[cfg(all(
+        feature = "parking_lot",
+        not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+    ))]
+    ParkingLot(ParkingLotStrategy),
+    #[cfg(all(feature = "experimental-strategies", not(loom)))]
+    Membarrier(MembarrierStrategy),
+    #[cfg(all(
+        feature = "experimental-strategies",
+        target_pointer_width = "64",
+        any(target_arch = "x86_64", target_arch = "aarch64"),
+    ))]
+    SplitCount(SplitCountStrategy),
+}
+
+/// Runs the same code for whichever strategy is inside.
+macro_rules! dispatch {
+    ($inner: expr, $s: ident => $body: expr) => {
+        match $inner {
+            Inner::Default($s) => $body,
+            #[cfg(not(any(
+                feature = "experimental-thread-local",
+                feature = "custom-thread-local"
+            )))]
+            Inner::RwLock($s) => $body,
+            #[cfg(all(
+                feature = "parking_lot",
+                not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+            ))]
+            Inner::ParkingLot($s) => $body,
+            #[cfg(all(feature = "experimental-strategies", not(loom)))]
+            Inner::Membarrier($s) => $body,
+            #[cfg(all(
+                feature = "experimental-strategies",
+                target_pointer_width = "64",
+                any(target_arch = "x86_64", target_arch = "aarch64"),
+            ))]
+            Inner::SplitCount($s) => $body,
+        }
+    };
+}
+
+/// A strategy chosen at runtime.
+///
+/// It wraps one of the other strategies, picked when it is created (for example from a
+/// configuration option), and forwards everything to it. This allows switching or comparing the
+/// strategies in a running application without changing the types. The price is a branch on each
+/// operation and a bigger guard.
+///
+/// The default is the [`DefaultStrategy`].
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use arc_swap::strategy::{DynStrategy, StrategyKind};
+/// use arc_swap::ArcSwapAny;
+///
+/// // Could come from a config file.
+/// let kind: StrategyKind = "default".parse().unwrap();
+/// let shared = ArcSwapAny::with_strategy(Arc::new(42), DynStrategy::new(kind));
+/// assert_eq!(42, **shared.load());
+/// ```
+pub struct DynStrategy(Inner);
+
+impl DynStrategy {
+    /// Creates the strategy of the given kind.
+    pub fn new(kind: StrategyKind) -> Self {
+        let inner = match kind {
+            StrategyKind::Default => Inner::Default(DefaultStrategy::default()),
+            #[cfg(not(any(
+                feature = "experimental-thread-local",
+                feature = "custom-thread-local"
+            )))]
+            StrategyKind::RwLock => Inner::RwLock(RwLockStrategy::default()),
+            #[cfg(all(
+                feature = "parking_lot",
+                not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+            ))]
+            StrategyKind::ParkingLot => Inner::ParkingLot(ParkingLotStrategy::default()),
+            #[cfg(all(feature = "experimental-strategies", not(loom)))]
+            StrategyKind::Membarrier => Inner::Membarrier(MembarrierStrategy::default()),
+            #[cfg(all(
+                feature = "experimental-strategies",
+                target_pointer_width = "64",
+                any(target_arch = "x86_64", target_arch = "aarch64"),
+            ))]
+            StrategyKind::SplitCount => Inner::SplitCount(SplitCountStrategy),
+        };
+        DynStrategy(inner)
+    }
+
+    /// Which strategy is used.
+    pub fn kind(&self) -> StrategyKind {
+        match self.0 {
+            Inner::Default(_) => StrategyKind::Default,
+            #[cfg(not(any(
+                feature = "experimental-thread-local",
+                feature = "custom-thread-local"
+            )))]
+            Inner::RwLock(_) => StrategyKind::RwLock,
+            #[cfg(all(
+                feature = "parking_lot",
+                not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+            ))]
+            Inner::ParkingLot(_) => StrategyKind::ParkingLot,
+            #[cfg(all(feature = "experimental-strategies", not(loom)))]
+            Inner::Membarrier(_) => StrategyKind::Membarrier,
+            #[cfg(all(
+                feature = "experimental-strategies",
+                target_pointer_width = "64",
+                any(target_arch = "x86_64", target_arch = "aarch64"),
+            ))]
+            Inner::SplitCount(_) => StrategyKind::SplitCount,
+        }
+    }
+}
+
+impl Default for DynStrategy {
+    fn default() -> Self {
+        Self::new(StrategyKind::default())
+    }
+}
+
+impl Clone for DynStrategy {
+    /// Creates a new strategy of the same kind.
+    ///
+    /// Note that the state of the strategy (if it has any) is not shared with the original.
+    fn clone(&self) -> Self {
+        Self::new(self.kind())
+    }
+}
+
+/// The guard content of the [`DynStrategy`].
+pub enum DynProtection<T: RefCnt> {
+    /// From the strategies using the debts.
+    Hybrid(HybridProtection<T>),
+    /// From the strategies handing out full reference counts.
+    Full(T),
+}
+
+impl<T: RefCnt> Protected<T> for DynProtection<T> {
+    #[inline]
+    fn from_inner(ptr: T) -> Self {
+        DynProtection::Full(ptr)
+    }
+
+    #[inline]
+    fn into_inner(self) -> T {
+        match self {
+            DynProtection::Hybrid(protection) => protection.into_inner(),
+            DynProtection::Full(ptr) => ptr,
+        }
+    }
+}
+
+impl<T: RefCnt> Borrow<T> for DynProtection<T> {
+    #[inline]
+    fn borrow(&self) -> &T {
+        match self {
+            DynProtection::Hybrid(protection) => protection.borrow(),
+            DynProtection::Full(ptr) => ptr,
+        }
+    }
+}
+
+impl<T: RefCnt> InnerStrategy<T> for DynStrategy {
+    type Protected = DynProtection<T>;
+    unsafe fn load(&self, storage: &AtomicPtr<T::Base>) -> Self::Protected {
+        match &self.0 {
+            Inner::Default(s) => DynProtection::Hybrid(s.load(storage)),
+            #[cfg(not(any(
+                feature = "experimental-thread-local",
+                feature = "custom-thread-local"
+            )))]
+            Inner::RwLock(s) => DynProtection::Full(s.load(storage)),
+            #[cfg(all(
+                feature = "parking_lot",
+                not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+            ))]
+            Inner::ParkingLot(s) => DynProtection::Full(s.load(storage)),
+            #[cfg(all(feature = "experimental-strategies", not(loom)))]
+            Inner::Membarrier(s) => DynProtection::Hybrid(s.load(storage)),
+            #[cfg(all(
+                feature = "experimental-strategies",
+                target_pointer_width = "64",
+                any(target_arch = "x86_64", target_arch = "aarch64"),
+            ))]
+            Inner::SplitCount(s) => DynProtection::Full(s.load(storage)),
+        }
+    }
+    #[inline]
+    unsafe fn load_local(&self, local: Local, storage: &AtomicPtr<T::Base>) -> Self::Protected {
+        match &self.0 {
+            Inner::Default(s) => DynProtection::Hybrid(s.load_local(local, storage)),
+            #[cfg(not(any(
+                feature = "experimental-thread-local",
+                feature = "custom-thread-local"
+            )))]
+            Inner::RwLock(s) => DynProtection::Full(s.load_local(local, storage)),
+            #[cfg(all(
+                feature = "parking_lot",
+                not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+            ))]
+            Inner::ParkingLot(s) => DynProtection::Full(s.load_local(local, storage)),
+            #[cfg(all(feature = "experimental-strategies", not(loom)))]
+            Inner::Membarrier(s) => DynProtection::Hybrid(s.load_local(local, storage)),
+            #[cfg(all(
+                feature = "experimental-strategies",
+                target_pointer_width = "64",
+                any(target_arch = "x86_64", target_arch = "aarch64"),
+            ))]
+            Inner::SplitCount(s) => DynProtection::Full(s.load_local(local, storage)),
+        }
+    }
+    unsafe fn wait_for_readers(&self, old: *const T::Base, storage: &AtomicPtr<T::Base>) {
+        dispatch!(&self.0, s => InnerStrategy::<T>::wait_for_readers(s, old, storage))
+    }
+    unsafe fn enter_storage(&self, ptr: *mut T::Base) -> *mut T::Base {
+        dispatch!(&self.0, s => InnerStrategy::<T>::enter_storage(s, ptr))
+    }
+    unsafe fn leave_storage(&self, raw: *mut T::Base) -> *mut T::Base {
+        dispatch!(&self.0, s => InnerStrategy::<T>::leave_storage(s, raw))
+    }
+    fn peek_storage(&self, raw: *mut T::Base) -> *mut T::Base {
+        dispatch!(&self.0, s => InnerStrategy::<T>::peek_storage(s, raw))
+    }
+    unsafe fn retire<'a>(
+        &'a self,
+        old: T,
+        storage: &'a AtomicPtr<T::Base>,
+        retirements: &mut Retirements<'a>,
+    ) where
+        T: 'a,
+    {
+        dispatch!(&self.0, s => s.retire(old, storage, retirements))
+    }
+}
+
+impl<T: RefCnt> CaS<T> for DynStrategy {
+    unsafe fn compare_and_swap<C: AsRaw<T::Base>>(
+        &self,
+        storage: &AtomicPtr<T::Base>,
+        current: C,
+        new: T,
+    ) -> Self::Protected {
+        match &self.0 {
+            Inner::Default(s) => DynProtection::Hybrid(s.compare_and_swap(storage, current, new)),
+            #[cfg(not(any(
+                feature = "experimental-thread-local",
+                feature = "custom-thread-local"
+            )))]
+            Inner::RwLock(s) => DynProtection::Full(s.compare_and_swap(storage, current, new)),
+            #[cfg(all(
+                feature = "parking_lot",
+                not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+            ))]
+            Inner::ParkingLot(s) => DynProtection::Full(s.compare_and_swap(storage, current, new)),
+            #[cfg(all(feature = "experimental-strategies", not(loom)))]
+            Inner::Membarrier(s) => {
+                DynProtection::Hybrid(s.compare_and_swap(storage, current, new))
+            }
+            #[cfg(all(
+                feature = "experimental-strategies",
+                target_pointer_width = "64",
+                any(target_arch = "x86_64", target_arch = "aarch64"),
+            ))]
+            Inner::SplitCount(s) => DynProtection::Full(s.compare_and_swap(storage, current, new)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::imports::Arc;
+    use crate::ArcSwapAny;
+
+    use super::*;
+
+    #[test]
+    fn names() {
+        for kind in StrategyKind::ALL {
+            assert_eq!(Ok(*kind), kind.name().parse());
+        }
+        assert_eq!(
+            Err(UnknownStrategy),
+            "no-such-strategy".parse::<StrategyKind>()
+        );
+    }
+
+    /// Basic operations work with each of the available kinds.
+    #[test]
+    fn all_kinds() {
+        for kind in StrategyKind::ALL {
+            let first = Arc::new(1);
+            let shared = ArcSwapAny::with_strategy(Arc::clone(&first), DynStrategy::new(*kind));
+            assert_eq!(*kind, shared.strategy.kind());
+            let guard = shared.load();
+            assert_eq!(1, **guard);
+            let previous = shared.compare_and_swap(&*guard, Arc::new(2));
+            assert!(Arc::ptr_eq(&first, &previous));
+            drop((guard, previous));
+            assert_eq!(2, *shared.swap(Arc::new(3)));
+            shared.rcu(|v| **v + 1);
+            assert_eq!(4, **shared.load());
+            drop(shared);
+            assert_eq!(1, Arc::strong_count(&first));
+        }
+    }
+}
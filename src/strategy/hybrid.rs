@@ -17,12 +17,14 @@ use core::borrow::Borrow;
 use core::mem::{self, ManuallyDrop};
 use core::ops::Deref;
 use core::ptr;
-use core::sync::atomic::AtomicPtr;
+#[cfg(all(feature = "experimental-strategies", not(loom)))]
+use core::sync::atomic::compiler_fence;
 use core::sync::atomic::Ordering::*;
 
-use super::sealed::{CaS, InnerStrategy, Protected};
-use crate::debt::{Debt, LocalNode};
+use super::sealed::{CaS, InnerStrategy, Local, Protected};
+use crate::debt::{Debt, LocalNode, Replacement, Retirements};
 use crate::ref_cnt::RefCnt;
+use crate::sync::AtomicPtr;
 
 pub struct HybridProtection<T: RefCnt> {
     debt: Option<&'static Debt>,
@@ -67,8 +69,37 @@ impl<T: RefCnt> HybridProtection<T> {
         }
     }
 
+    /// Try getting a debt into a fast slot, without the SeqCst operations.
+    ///
+    /// This is the same as [`attempt`][HybridProtection::attempt], but the ordering between
+    /// storing the debt and confirming the pointer is ensured only by a compiler fence. That is
+    /// enough only if every writer issues a heavy fence (one forcing a full barrier on all the
+    /// threads running at the time, like `membarrier`) between changing the pointer and looking
+    /// at the debts. Either the writer sees our debt, or we see the new pointer.
+    #[cfg(all(feature = "experimental-strategies", not(loom)))]
+    #[inline]
+    pub(super) fn attempt_asymmetric(
+        node: &LocalNode,
+        storage: &AtomicPtr<T::Base>,
+    ) -> Option<Self> {
+        // Acquire to get the data.
+        let ptr = storage.load(Acquire);
+        let debt = node.new_fast_ordered(ptr as usize, Relaxed)?;
+        // The light side of the asymmetric fence.
+        compiler_fence(SeqCst);
+        let confirm = storage.load(Acquire);
+        if ptr == confirm {
+            // See attempt for why confirm and not ptr.
+            Some(unsafe { Self::new(confirm, Some(debt)) })
+        } else if debt.pay::<T>(ptr) {
+            None
+        } else {
+            Some(unsafe { Self::new(ptr, None) })
+        }
+    }
+
     /// Get a debt slot using the slower but always successful mechanism.
-    fn fallback(node: &LocalNode, storage: &AtomicPtr<T::Base>) -> Self {
+    pub(super) fn fallback(node: &LocalNode, storage: &AtomicPtr<T::Base>) -> Self {
         // First, we claim a debt slot and store the address of the atomic pointer there, so the
         // writer can optionally help us out with loading and protecting something.
         let gen = node.new_helping(storage as *const _ as usize);
@@ -187,14 +218,17 @@ where
 {
     type Protected = HybridProtection<T>;
     unsafe fn load(&self, storage: &AtomicPtr<T::Base>) -> Self::Protected {
-        LocalNode::with(|node| {
-            let fast = if Cfg::USE_FAST {
-                HybridProtection::attempt(node, storage)
-            } else {
-                None
-            };
-            fast.unwrap_or_else(|| HybridProtection::fallback(node, storage))
-        })
+        LocalNode::with(|node| self.load_local(Local(node), storage))
+    }
+    #[inline]
+    unsafe fn load_local(&self, local: Local, storage: &AtomicPtr<T::Base>) -> Self::Protected {
+        let node = local.0;
+        let fast = if Cfg::USE_FAST {
+            HybridProtection::attempt(node, storage)
+        } else {
+            None
+        };
+        fast.unwrap_or_else(|| HybridProtection::fallback(node, storage))
     }
     unsafe fn wait_for_readers(&self, old: *const T::Base, storage: &AtomicPtr<T::Base>) {
         // The pay_all may need to provide fresh replacement values if someone else is loading from
@@ -204,6 +238,23 @@ where
         let replacement = || self.load(storage).into_inner();
         Debt::pay_all::<T, _>(old, storage as *const _ as usize, replacement);
     }
+    unsafe fn retire<'a>(
+        &'a self,
+        old: T,
+        storage: &'a AtomicPtr<T::Base>,
+        retirements: &mut Retirements<'a>,
+    ) where
+        T: 'a,
+    {
+        // Same as in wait_for_readers, just postponed.
+        retirements.push(old, storage, self);
+    }
+}
+
+impl<T: RefCnt, Cfg: Config> Replacement<T> for HybridStrategy<Cfg> {
+    unsafe fn replacement(&self, storage: &AtomicPtr<T::Base>) -> T {
+        self.load(storage).into_inner()
+    }
 }
 
 impl<T: RefCnt, Cfg: Config> CaS<T> for HybridStrategy<Cfg> {
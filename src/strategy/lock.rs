@@ -0,0 +1,358 @@
+//! Strategies based on a read-write lock.
+//!
+//! The readers take the read lock only for the short time of loading the pointer and incrementing
+//! the reference count, so the guards they get hold full references. The writers change the
+//! pointer and then acquire the write lock, which makes sure no reader is in the middle of a load
+//! of the old value.
+//!
+//! This is much simpler than the [`DefaultStrategy`][super::DefaultStrategy] and contains very
+//! little `unsafe` code on our side (all of it is the manipulation of the reference counts), but
+//! neither the readers nor the writers are lock-free.
+//!
+//! Because the writers acquire a lock, there are variants of the write operations with a timeout
+//! (see [`try_swap_for`][ArcSwapAny::try_swap_for] and the neighbouring methods).
+
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+use std::sync::{PoisonError, RwLock, TryLockError};
+use std::thread;
+use std::time::Instant;
+
+use super::sealed::{CaS, InnerStrategy, TimedLock};
+use crate::as_raw::AsRaw;
+use crate::ref_cnt::RefCnt;
+use crate::sync::AtomicPtr;
+use crate::{notify_waiters, ptr_eq, ArcSwapAny, Guard};
+
+/// A strategy based on [`std::sync::RwLock`].
+///
+/// See the [`strategy`][super] module for the list of strategies. This one is for the cases where
+/// the lock-free code of the other strategies is not acceptable. The readers hold the lock only
+/// while loading the pointer and incrementing its reference count, the guards they get are full
+/// references. The writers acquire the write lock after changing the pointer, to make sure no
+/// reader is in the middle of loading the old one. Therefore, neither readers nor writers are
+/// lock-free, but all the `unsafe` code involved is the manipulation of the reference counts.
+///
+/// The lock allows writing with a timeout, see [`try_swap_for`][ArcSwapAny::try_swap_for] and the
+/// neighbouring methods.
+///
+/// # Poisoning
+///
+/// The lock protects no data, it only orders the readers and the writers. No user code (including
+/// destructors of the values) is run while it is held, so the operations of this crate never
+/// poison it. Even if it gets poisoned by some other means, the poisoning is ignored ‒ there's
+/// nothing that could have been left in an inconsistent state.
+///
+/// # Timeouts
+///
+/// The [`std::sync::RwLock`] doesn't support timeouts, so the write operations with a timeout
+/// poll it (with increasing pauses) until they succeed or the time runs out. See the
+/// `ParkingLotStrategy` (with the `parking_lot` feature) if that's a problem.
+///
+/// Available with the standard library.
+#[derive(Debug, Default)]
+pub struct RwLockStrategy {
+    lock: RwLock<()>,
+}
+
+impl TimedLock for RwLockStrategy {
+    fn read<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let _guard = self.lock.read().unwrap_or_else(PoisonError::into_inner);
+        f()
+    }
+
+    fn write<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let _guard = self.lock.write().unwrap_or_else(PoisonError::into_inner);
+        f()
+    }
+
+    fn write_for<R, F: FnOnce() -> R>(&self, timeout: Duration, f: F) -> Option<R> {
+        let start = Instant::now();
+        let mut attempt = 0u32;
+        loop {
+            let _guard = match self.lock.try_write() {
+                Ok(guard) => guard,
+                Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+                Err(TryLockError::WouldBlock) => {
+                    let elapsed = start.elapsed();
+                    if elapsed >= timeout {
+                        return None;
+                    }
+                    // The lock is held only for short times, so first give up the CPU a few
+                    // times and only then start sleeping.
+                    attempt += 1;
+                    if attempt <= 10 {
+                        thread::yield_now();
+                    } else {
+                        let pause = Duration::from_micros(u64::from(attempt.min(1_000)));
+                        thread::sleep(pause.min(timeout - elapsed));
+                    }
+                    continue;
+                }
+            };
+            return Some(f());
+        }
+    }
+}
+
+/// A strategy based on the [`parking_lot::RwLock`].
+///
+/// This is the same as the [`RwLockStrategy`], but with the lock from the
+/// [`parking_lot`](https://docs.rs/parking_lot) crate. That one supports timeouts directly and
+/// doesn't have any poisoning.
+///
+/// Available with the `parking_lot` feature.
+#[cfg(feature = "parking_lot")]
+#[derive(Debug, Default)]
+pub struct ParkingLotStrategy {
+    lock: parking_lot::RwLock<()>,
+}
+
+#[cfg(feature = "parking_lot")]
+impl TimedLock for ParkingLotStrategy {
+    fn read<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let _guard = self.lock.read();
+        f()
+    }
+
+    fn write<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let _guard = self.lock.write();
+        f()
+    }
+
+    fn write_for<R, F: FnOnce() -> R>(&self, timeout: Duration, f: F) -> Option<R> {
+        let _guard = self.lock.try_write_for(timeout)?;
+        Some(f())
+    }
+}
+
+/// Loads the value, holding the read lock for the time of incrementing the reference count.
+unsafe fn load<T: RefCnt, L: TimedLock>(lock: &L, storage: &AtomicPtr<T::Base>) -> T {
+    lock.read(|| {
+        let ptr = T::from_ptr(storage.load(Ordering::Acquire));
+        T::inc(&ptr);
+        ptr
+    })
+}
+
+/// Does the compare and swap, the caller holds the write lock.
+///
+/// Returns the previous value and the new one if it didn't go in. Both are to be dropped only
+/// after releasing the lock.
+unsafe fn compare_and_swap_locked<T: RefCnt>(
+    storage: &AtomicPtr<T::Base>,
+    current: *const T::Base,
+    new: T,
+) -> (T, Option<T>) {
+    let new = T::into_ptr(new);
+    match storage.compare_exchange(current as *mut _, new, Ordering::AcqRel, Ordering::Acquire) {
+        // The reference held by the storage is ours now. No reader is in the middle of loading
+        // it, they hold the read lock for that.
+        Ok(old) => (T::from_ptr(old), None),
+        Err(old) => {
+            // If some other writer swapped the value without the lock, it waits for the lock
+            // before releasing the old one. So it can't go away before we increment it.
+            let old = T::from_ptr(old);
+            T::inc(&old);
+            (old, Some(T::from_ptr(new)))
+        }
+    }
+}
+
+macro_rules! lock_strategy {
+    ($strategy: ty) => {
+        impl<T: RefCnt> InnerStrategy<T> for $strategy {
+            type Protected = T;
+            unsafe fn load(&self, storage: &AtomicPtr<T::Base>) -> T {
+                load(self, storage)
+            }
+            unsafe fn wait_for_readers(&self, _: *const T::Base, _: &AtomicPtr<T::Base>) {
+                // By acquiring the write lock, we make sure there are no readers in the middle of
+                // a load.
+                self.write(|| ());
+            }
+        }
+
+        impl<T: RefCnt> CaS<T> for $strategy {
+            unsafe fn compare_and_swap<C: AsRaw<T::Base>>(
+                &self,
+                storage: &AtomicPtr<T::Base>,
+                current: C,
+                new: T,
+            ) -> T {
+                let current = current.as_raw();
+                let (old, rejected) = self.write(|| compare_and_swap_locked(storage, current, new));
+                drop(rejected);
+                old
+            }
+        }
+    };
+}
+
+lock_strategy!(RwLockStrategy);
+#[cfg(feature = "parking_lot")]
+lock_strategy!(ParkingLotStrategy);
+
+impl<T, S> ArcSwapAny<T, S>
+where
+    T: RefCnt,
+    S: CaS<T> + super::TimedLock,
+{
+    /// Like [`compare_and_swap`][ArcSwapAny::compare_and_swap], but gives up if the lock can't
+    /// be acquired within the `timeout`.
+    ///
+    /// In such case the `new` value is returned back as the error.
+    ///
+    /// Available only with the lock-based strategies.
+    pub fn try_compare_and_swap_for<C>(
+        &self,
+        current: C,
+        new: T,
+        timeout: Duration,
+    ) -> Result<Guard<T, S>, T>
+    where
+        C: AsRaw<T::Base>,
+    {
+        let current = current.as_raw();
+        let mut new = Some(new);
+        // The lock strategies keep nothing but the pointer in the storage, so there's no need
+        // for entering and leaving it through the strategy.
+        let done = self.strategy.write_for(timeout, || unsafe {
+            let new = new.take().expect("Called at most once");
+            compare_and_swap_locked(&self.ptr, current, new)
+        });
+        match done {
+            Some((old, rejected)) => {
+                if rejected.is_none() {
+                    notify_waiters(&self.ptr);
+                }
+                drop(rejected);
+                Ok(Guard::from_inner(old))
+            }
+            None => Err(new.expect("Not used on timeout")),
+        }
+    }
+
+    /// Like [`swap`][ArcSwapAny::swap], but gives up if the lock can't be acquired within the
+    /// `timeout`.
+    ///
+    /// In such case the `new` value is returned back as the error.
+    ///
+    /// Available only with the lock-based strategies.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// use arc_swap::strategy::RwLockStrategy;
+    /// use arc_swap::ArcSwapAny;
+    ///
+    /// let shared = ArcSwapAny::<_, RwLockStrategy>::new(Arc::new(1));
+    /// let old = shared.try_swap_for(Arc::new(2), Duration::from_millis(100)).unwrap();
+    /// assert_eq!(1, *old);
+    /// assert_eq!(2, **shared.load());
+    /// ```
+    pub fn try_swap_for(&self, new: T, timeout: Duration) -> Result<T, T> {
+        let mut new = Some(new);
+        let old = self.strategy.write_for(timeout, || unsafe {
+            let new = T::into_ptr(new.take().expect("Called at most once"));
+            // We hold the write lock, so no reader is in the middle of loading the old value and
+            // we don't have to wait for anything.
+            T::from_ptr(self.ptr.swap(new, Ordering::SeqCst))
+        });
+        match old {
+            Some(old) => {
+                notify_waiters(&self.ptr);
+                Ok(old)
+            }
+            None => Err(new.expect("Not used on timeout")),
+        }
+    }
+
+    /// Like [`store`][ArcSwapAny::store], but gives up if the lock can't be acquired within the
+    /// `timeout`.
+    ///
+    /// In such case the `val` is returned back as the error.
+    ///
+    /// Available only with the lock-based strategies.
+    pub fn try_store_for(&self, val: T, timeout: Duration) -> Result<(), T> {
+        self.try_swap_for(val, timeout).map(drop)
+    }
+
+    /// Like [`rcu`][ArcSwapAny::rcu], but gives up if it doesn't succeed within the `timeout`.
+    ///
+    /// The `timeout` is for the whole operation, including all the retries. The closure is not
+    /// run under the lock. Returns `None` on timeout.
+    ///
+    /// Available only with the lock-based strategies.
+    pub fn try_rcu_for<R, F>(&self, mut f: F, timeout: Duration) -> Option<T>
+    where
+        F: FnMut(&T) -> R,
+        R: Into<T>,
+    {
+        let start = Instant::now();
+        let mut cur = self.load();
+        loop {
+            let new = f(&cur).into();
+            let remaining = timeout
+                .checked_sub(start.elapsed())
+                .unwrap_or_else(|| Duration::from_secs(0));
+            let prev = self.try_compare_and_swap_for(&*cur, new, remaining).ok()?;
+            if ptr_eq(&*cur, &*prev) {
+                return Some(Guard::into_inner(prev));
+            }
+            cur = prev;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    macro_rules! timeouts {
+        ($name: ident, $strategy: ty) => {
+            /// The timed writes fail while a reader holds the lock and succeed once it's released.
+            #[test]
+            fn $name() {
+                let shared = ArcSwapAny::<_, $strategy>::new(Arc::new(0));
+                let short = Duration::from_millis(10);
+                shared.strategy.read(|| {
+                    let val = shared.try_store_for(Arc::new(1), short).unwrap_err();
+                    assert_eq!(1, *val);
+                    assert!(shared.try_rcu_for(|v| **v + 1, short).is_none());
+                    let current = shared.ptr.load(Ordering::Relaxed);
+                    assert!(shared
+                        .try_compare_and_swap_for(current, Arc::new(2), short)
+                        .is_err());
+                });
+                assert_eq!(0, **shared.load());
+
+                let long = Duration::from_secs(60);
+                shared.try_store_for(Arc::new(1), long).unwrap();
+                assert_eq!(1, *shared.try_rcu_for(|v| **v + 1, long).unwrap());
+                let current = shared.load();
+                let swapped = shared
+                    .try_compare_and_swap_for(&*current, Arc::new(3), long)
+                    .unwrap();
+                assert!(Arc::ptr_eq(&current, &swapped));
+                drop(swapped);
+                let previous = shared
+                    .try_compare_and_swap_for(&*current, Arc::new(4), long)
+                    .unwrap();
+                assert_eq!(3, **previous);
+                assert_eq!(3, **shared.load());
+                drop(previous);
+                assert_eq!(1, Arc::strong_count(&current));
+            }
+        };
+    }
+
+    timeouts!(timeouts_std, RwLockStrategy);
+    #[cfg(feature = "parking_lot")]
+    timeouts!(timeouts_parking_lot, ParkingLotStrategy);
+}
@@ -0,0 +1,172 @@
+//! A strategy using asymmetric fences.
+//!
+//! This is the [`HybridStrategy`], but with the reader side made cheaper. The fast path of the
+//! readers needs the store of the debt to be ordered before the second load of the pointer, which
+//! normally takes `SeqCst` operations (full barriers on some platforms). Here the readers use only
+//! a compiler fence and the writers make up for it by calling
+//! `membarrier(MEMBARRIER_CMD_PRIVATE_EXPEDITED)` before looking at the debts. That forces a full
+//! barrier on all the threads of the process that are running at the time.
+//!
+//! This makes the writers considerably more expensive (a syscall with an IPI to the other CPUs),
+//! so it makes sense only for data that is read a lot and written rarely.
+//!
+//! The membarrier is available on Linux 4.14 and newer. If it is not available (older kernels,
+//! other systems), the strategy acts just like the [`HybridStrategy`].
+
+use core::borrow::Borrow;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering::*;
+
+use super::hybrid::{DefaultConfig, HybridProtection, HybridStrategy};
+use super::sealed::{CaS, InnerStrategy, Local};
+use crate::as_raw::AsRaw;
+use crate::debt::LocalNode;
+use crate::ref_cnt::RefCnt;
+use crate::sync::AtomicPtr;
+
+#[cfg(all(target_os = "linux", not(miri)))]
+mod sys {
+    use libc::{c_int, c_long, syscall, SYS_membarrier};
+
+    // From linux/membarrier.h
+    const MEMBARRIER_CMD_QUERY: c_int = 0;
+    const MEMBARRIER_CMD_PRIVATE_EXPEDITED: c_int = 1 << 3;
+    const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED: c_int = 1 << 4;
+
+    fn membarrier(cmd: c_int) -> c_long {
+        unsafe { syscall(SYS_membarrier, cmd, 0 as c_int) }
+    }
+
+    pub(super) fn register() -> bool {
+        let supported = membarrier(MEMBARRIER_CMD_QUERY);
+        supported >= 0
+            && supported & c_long::from(MEMBARRIER_CMD_PRIVATE_EXPEDITED) != 0
+            && membarrier(MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED) == 0
+    }
+
+    pub(super) fn heavy() {
+        let result = membarrier(MEMBARRIER_CMD_PRIVATE_EXPEDITED);
+        // It fails only if not supported or not registered, but we've checked both.
+        assert_eq!(0, result, "membarrier failed after successful registration");
+    }
+}
+
+#[cfg(not(all(target_os = "linux", not(miri))))]
+mod sys {
+    pub(super) fn register() -> bool {
+        false
+    }
+
+    pub(super) fn heavy() {
+        unreachable!("Asymmetric fences are never enabled here");
+    }
+}
+
+const UNKNOWN: u8 = 0;
+const AVAILABLE: u8 = 1;
+const UNAVAILABLE: u8 = 2;
+
+/// Did the registration for the membarrier succeed?
+///
+/// The registration is for the whole process, so it is done only once. If multiple threads race
+/// to do it, each of them registers, but that's harmless.
+static STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+fn available() -> bool {
+    match STATE.load(Relaxed) {
+        AVAILABLE => true,
+        UNAVAILABLE => false,
+        _ => {
+            let available = sys::register();
+            let state = if available { AVAILABLE } else { UNAVAILABLE };
+            STATE.store(state, Relaxed);
+            available
+        }
+    }
+}
+
+/// A strategy using asymmetric fences (`membarrier` on Linux) to make the readers cheaper.
+///
+/// It falls back to the behaviour of the [`DefaultStrategy`][super::DefaultStrategy] if the
+/// asymmetric fences are not available. The decision is made when creating the strategy, so both
+/// readers and writers of each storage always agree on it.
+///
+/// Note that each write (including each one in a [`batch`][crate::batch()]) issues a syscall,
+/// so this is suitable only for rarely changing data.
+///
+/// Needs the `experimental-strategies` feature.
+#[derive(Clone)]
+pub struct MembarrierStrategy {
+    asymmetric: bool,
+    hybrid: HybridStrategy<DefaultConfig>,
+}
+
+impl MembarrierStrategy {
+    /// Are the asymmetric fences actually used?
+    ///
+    /// This is false if they are not available on the system and the strategy falls back.
+    pub fn is_asymmetric(&self) -> bool {
+        self.asymmetric
+    }
+}
+
+impl Default for MembarrierStrategy {
+    fn default() -> Self {
+        MembarrierStrategy {
+            asymmetric: available(),
+            hybrid: HybridStrategy::default(),
+        }
+    }
+}
+
+impl<T: RefCnt> InnerStrategy<T> for MembarrierStrategy {
+    type Protected = HybridProtection<T>;
+    unsafe fn load(&self, storage: &AtomicPtr<T::Base>) -> Self::Protected {
+        LocalNode::with(|node| self.load_local(Local(node), storage))
+    }
+    #[inline]
+    unsafe fn load_local(&self, local: Local, storage: &AtomicPtr<T::Base>) -> Self::Protected {
+        if self.asymmetric {
+            HybridProtection::attempt_asymmetric(local.0, storage)
+                .unwrap_or_else(|| HybridProtection::fallback(local.0, storage))
+        } else {
+            InnerStrategy::<T>::load_local(&self.hybrid, local, storage)
+        }
+    }
+    unsafe fn wait_for_readers(&self, old: *const T::Base, storage: &AtomicPtr<T::Base>) {
+        if self.asymmetric {
+            // The heavy side of the fence. The pointer is already changed, so either we see the
+            // debts of readers that loaded the old one, or they see the new one.
+            sys::heavy();
+        }
+        InnerStrategy::<T>::wait_for_readers(&self.hybrid, old, storage);
+    }
+}
+
+impl<T: RefCnt> CaS<T> for MembarrierStrategy {
+    unsafe fn compare_and_swap<C: AsRaw<T::Base>>(
+        &self,
+        storage: &AtomicPtr<T::Base>,
+        current: C,
+        new: T,
+    ) -> Self::Protected {
+        // Same as with the HybridStrategy, only with our own load and waiting.
+        loop {
+            let old = <Self as InnerStrategy<T>>::load(self, storage);
+            let old_ptr = T::as_ptr(old.borrow());
+            if old_ptr != current.as_raw() {
+                return old;
+            }
+            let new_raw = T::as_ptr(&new);
+            if storage
+                .compare_exchange_weak(current.as_raw(), new_raw, SeqCst, Relaxed)
+                .is_ok()
+            {
+                T::into_ptr(new);
+                <Self as InnerStrategy<T>>::wait_for_readers(self, old_ptr, storage);
+                T::dec(old_ptr);
+                return old;
+            }
+        }
+    }
+}
@@ -17,7 +17,15 @@
 //! Currently, we have these strategies:
 //!
 //! * [`DefaultStrategy`] (this one is used implicitly)
-//! * [`RwLock<()>`][std::sync::RwLock]
+//! * `RwLockStrategy` and `ParkingLotStrategy` (the latter with the `parking_lot` feature), based
+//!   on a read-write lock, for the cases where lock-free `unsafe` code is not acceptable
+//! * [`RwLock<()>`][std::sync::RwLock] (for testing only, see below)
+//! * `MembarrierStrategy` (with the `experimental-strategies` feature), with cheaper readers and
+//!   more expensive writers, using asymmetric fences
+//! * `SplitCountStrategy` (with the `experimental-strategies` feature, on 64-bit x86 and ARM),
+//!   which needs no per-thread data and has writers with constant cost
+//!
+//! The [`DynStrategy`] allows choosing one of these at runtime.
 //!
 //! # Testing
 //!
@@ -31,15 +39,17 @@
 //! [`std`], that is the better option. This is provided mostly for investigation and testing of
 //! [`ArcSwap`] itself or algorithms written to use [`ArcSwap`].
 //!
-//! *This is not meant to be used in production code*.
+//! *This is not meant to be used in production code*. Use the `RwLockStrategy` or the
+//! `ParkingLotStrategy` if a lock-based one is desired.
 //!
 //! [`ArcSwap`]: crate::ArcSwap
 //! [`load`]: crate::ArcSwapAny::load
 
 use core::borrow::Borrow;
-use core::sync::atomic::AtomicPtr;
+use core::time::Duration;
 
 use crate::ref_cnt::RefCnt;
+use crate::sync::AtomicPtr;
 
 pub(crate) mod hybrid;
 
@@ -49,14 +59,55 @@ pub(crate) mod hybrid;
 ))]
 compile_error!("experimental-thread-local is incompatible with internal-test-strategies as it enables #[no_std]");
 
+#[cfg(all(feature = "internal-test-strategies", feature = "custom-thread-local"))]
+compile_error!(
+    "custom-thread-local is incompatible with internal-test-strategies as it enables #[no_std]"
+);
+
+#[cfg(all(
+    feature = "reload",
+    any(feature = "experimental-thread-local", feature = "custom-thread-local")
+))]
+compile_error!("reload needs std, which is incompatible with the no_std features");
+
+#[cfg(all(feature = "experimental-thread-local", feature = "custom-thread-local"))]
+compile_error!("experimental-thread-local and custom-thread-local are mutually exclusive");
+
+mod dynamic;
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+mod lock;
+#[cfg(all(feature = "experimental-strategies", not(loom)))]
+mod membarrier;
 #[cfg(feature = "internal-test-strategies")]
 mod rw_lock;
+#[cfg(all(
+    feature = "experimental-strategies",
+    target_pointer_width = "64",
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+))]
+mod split;
 // Do not use from outside of the crate.
 #[cfg(feature = "internal-test-strategies")]
 #[doc(hidden)]
 pub mod test_strategies;
 
+pub use self::dynamic::{DynStrategy, StrategyKind, UnknownStrategy};
 use self::hybrid::{DefaultConfig, HybridStrategy};
+#[cfg(all(
+    feature = "parking_lot",
+    not(any(feature = "experimental-thread-local", feature = "custom-thread-local"))
+))]
+pub use self::lock::ParkingLotStrategy;
+#[cfg(not(any(feature = "experimental-thread-local", feature = "custom-thread-local")))]
+pub use self::lock::RwLockStrategy;
+#[cfg(all(feature = "experimental-strategies", not(loom)))]
+pub use self::membarrier::MembarrierStrategy;
+#[cfg(all(
+    feature = "experimental-strategies",
+    target_pointer_width = "64",
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+))]
+pub use self::split::SplitCountStrategy;
 
 /// The default strategy.
 ///
@@ -123,17 +174,81 @@ pub type IndependentStrategy = DefaultStrategy;
 pub(crate) mod sealed {
     use super::*;
     use crate::as_raw::AsRaw;
+    use crate::debt::{LocalNode, Retirements};
 
     pub trait Protected<T>: Borrow<T> {
         fn into_inner(self) -> T;
         fn from_inner(ptr: T) -> Self;
     }
 
+    /// The strategies that hand out full reference counts don't need any protection.
+    impl<T: RefCnt> Protected<T> for T {
+        #[inline]
+        fn from_inner(ptr: T) -> Self {
+            ptr
+        }
+
+        #[inline]
+        fn into_inner(self) -> T {
+            self
+        }
+    }
+
+    /// The thread-local data of the current thread, looked up in advance.
+    ///
+    /// Opaque, it only passes the node through the strategies.
+    #[derive(Clone, Copy)]
+    pub struct Local<'a>(pub(crate) &'a LocalNode);
+
     pub trait InnerStrategy<T: RefCnt> {
         // Drop „unlocks“
         type Protected: Protected<T>;
         unsafe fn load(&self, storage: &AtomicPtr<T::Base>) -> Self::Protected;
+        /// Load with an already resolved thread-local node.
+        ///
+        /// Strategies that don't use the debt nodes simply ignore it.
+        unsafe fn load_local(
+            &self,
+            _local: Local,
+            storage: &AtomicPtr<T::Base>,
+        ) -> Self::Protected {
+            self.load(storage)
+        }
         unsafe fn wait_for_readers(&self, old: *const T::Base, storage: &AtomicPtr<T::Base>);
+        /// Prepares a pointer to be put into the storage.
+        ///
+        /// Strategies that keep some extra information in the storage next to the pointer set it
+        /// up here. The result is what actually goes into the storage. The default passes the
+        /// pointer through.
+        unsafe fn enter_storage(&self, ptr: *mut T::Base) -> *mut T::Base {
+            ptr
+        }
+        /// Turns a raw value taken out of the storage back into the pointer.
+        ///
+        /// This is the counterpart of [`enter_storage`][InnerStrategy::enter_storage], settling any
+        /// extra information kept in there.
+        unsafe fn leave_storage(&self, raw: *mut T::Base) -> *mut T::Base {
+            raw
+        }
+        /// Gets the pointer out of a raw value still in the storage, without settling anything.
+        fn peek_storage(&self, raw: *mut T::Base) -> *mut T::Base {
+            raw
+        }
+        /// Gets rid of a value just removed from the storage, once the readers are done with it.
+        ///
+        /// Unlike [`wait_for_readers`][InnerStrategy::wait_for_readers], the strategy may
+        /// postpone the waiting by putting it (together with the value) into the retirements, to
+        /// be done in bulk with others.
+        unsafe fn retire<'a>(
+            &'a self,
+            old: T,
+            storage: &'a AtomicPtr<T::Base>,
+            _retirements: &mut Retirements<'a>,
+        ) where
+            T: 'a,
+        {
+            self.wait_for_readers(T::as_ptr(&old), storage);
+        }
     }
 
     pub trait CaS<T: RefCnt>: InnerStrategy<T> {
@@ -144,6 +259,14 @@ pub(crate) mod sealed {
             new: T,
         ) -> Self::Protected;
     }
+
+    /// The strategies based on a read-write lock.
+    pub trait TimedLock {
+        fn read<R, F: FnOnce() -> R>(&self, f: F) -> R;
+        fn write<R, F: FnOnce() -> R>(&self, f: F) -> R;
+        /// Runs the closure under the write lock, unless it can't be acquired in time.
+        fn write_for<R, F: FnOnce() -> R>(&self, timeout: Duration, f: F) -> Option<R>;
+    }
 }
 
 /// A strategy for protecting the reference counted pointer `T`.
@@ -166,3 +289,11 @@ impl<T: RefCnt, S: sealed::InnerStrategy<T>> Strategy<T> for S {}
 /// Similarly, it is not yet made publicly usable or implementable and works only as a bound.
 pub trait CaS<T: RefCnt>: sealed::CaS<T> {}
 impl<T: RefCnt, S: sealed::CaS<T>> CaS<T> for S {}
+
+/// A strategy based on a read-write lock.
+///
+/// These allow writing with a timeout (for example
+/// [`try_store_for`][crate::ArcSwapAny::try_store_for]). Like the other traits here, it works
+/// only as a bound.
+pub trait TimedLock: sealed::TimedLock {}
+impl<S: sealed::TimedLock> TimedLock for S {}
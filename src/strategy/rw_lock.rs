@@ -1,22 +1,11 @@
-use core::sync::atomic::{AtomicPtr, Ordering};
+use core::sync::atomic::Ordering;
 
 use std::sync::RwLock;
 
-use super::sealed::{CaS, InnerStrategy, Protected};
+use super::sealed::{CaS, InnerStrategy};
 use crate::as_raw::AsRaw;
 use crate::ref_cnt::RefCnt;
-
-impl<T: RefCnt> Protected<T> for T {
-    #[inline]
-    fn from_inner(ptr: T) -> Self {
-        ptr
-    }
-
-    #[inline]
-    fn into_inner(self) -> T {
-        self
-    }
-}
+use crate::sync::AtomicPtr;
 
 impl<T: RefCnt> InnerStrategy<T> for RwLock<()> {
     type Protected = T;
@@ -0,0 +1,477 @@
+//! A strategy with split reference counts.
+//!
+//! This one doesn't use the debts, and therefore doesn't need any per-thread data. Instead, it
+//! keeps a number of spare references to the stored value (a reserve) and a counter of how many
+//! of them have already been taken by the readers. The counter lives in the unused high bits of
+//! the pointer inside the storage, so both the pointer and the counter are updated atomically
+//! together.
+//!
+//! * A reader takes one of the spare references by incrementing the counter, using a single CaS
+//!   on the storage. It then owns a full reference to the value (it never needs to return it to
+//!   the storage).
+//! * The reader that uses up half of the reserve adds the same number of new spare references
+//!   and decrements the counter accordingly.
+//! * The writer putting a value in creates the reserve. The one taking a value out returns the
+//!   spare references that were not used by anyone.
+//!
+//! The writers therefore do a constant amount of work, independent of how many threads there are.
+//! The price is that all the readers modify the same storage (and the same reference count), so
+//! they contend with each other on that cache line.
+//!
+//! If the whole reserve gets exhausted before it is refilled (which would take dozens of readers
+//! between taking a reference and the refill), the readers spin until the refill happens.
+//!
+//! # The bits
+//!
+//! The counter lives in the bits 48 to 54 and the bit 55 marks an indirect value (see below). These
+//! are above the usual 48-bit user address space, but below the top byte, which may hold a tag of
+//! the pointer (the top byte ignore or the memory tagging on ARM). The tag is kept as it is.
+//!
+//! A pointer that uses these bits itself (like with the 5-level paging on x86-64) is kept through a
+//! small allocated cell instead. The cell has its own reference count, which carries the reserve,
+//! and the readers get their reference to the value through it. This is a bit slower (and the
+//! [`Cache`][crate::Cache] can't tell such a value didn't change, so it reloads it every time),
+//! but it works with any pointer. Whether a pointer fits is checked for each stored value at
+//! runtime.
+
+use core::mem::ManuallyDrop;
+use core::ptr;
+use core::sync::atomic::{self, Ordering::*};
+
+use super::sealed::{CaS, InnerStrategy};
+use crate::as_raw::AsRaw;
+use crate::imports::Box;
+use crate::ref_cnt::RefCnt;
+use crate::sync::{AtomicPtr, AtomicUsize};
+
+/// Where in the raw value the counter starts.
+const SHIFT: u32 = 48;
+/// The bits holding the counter.
+const COUNT_MASK: usize = 0x7f << SHIFT;
+/// Marks a value kept through an [`Indirect`] cell.
+const INDIRECT: usize = 1 << 55;
+/// All the bits we put our own data into.
+const META_MASK: usize = COUNT_MASK | INDIRECT;
+/// How many spare references the storage keeps.
+///
+/// The counter can't go above it, so it must fit into the [`COUNT_MASK`].
+const RESERVE: usize = 1 << 6;
+/// How many references are added once half of the reserve is used up.
+const REFILL: usize = RESERVE / 2;
+
+/// Splits the raw value into the entry (the pointer or the marked cell) and the counter.
+fn split<B>(raw: *mut B) -> (*mut B, usize) {
+    let raw = raw as usize;
+    ((raw & !COUNT_MASK) as *mut B, (raw & COUNT_MASK) >> SHIFT)
+}
+
+fn with_count<B>(entry: *mut B, count: usize) -> *mut B {
+    (entry as usize | count << SHIFT) as *mut B
+}
+
+/// The cell of an indirect entry, or null for a direct one.
+fn cell<B>(entry: *mut B) -> *mut Indirect<B> {
+    if entry as usize & INDIRECT == 0 {
+        ptr::null_mut()
+    } else {
+        (entry as usize & !INDIRECT) as *mut Indirect<B>
+    }
+}
+
+/// Runs something with the value without touching its reference count.
+unsafe fn with_value<T: RefCnt, R, F: FnOnce(&T) -> R>(ptr: *mut T::Base, f: F) -> R {
+    let val = ManuallyDrop::new(T::from_ptr(ptr));
+    f(&val)
+}
+
+/// A cell holding a pointer that doesn't leave the bits for us free.
+struct Indirect<B> {
+    /// One reference to the value, owned by the cell.
+    ptr: *mut B,
+    /// The one of the storage, the spare ones and the ones taken by the readers that didn't get
+    /// to the value yet.
+    refs: AtomicUsize,
+}
+
+/// Gives up some references to the cell, freeing it (and its reference to the value) on the last
+/// one.
+unsafe fn release<T: RefCnt>(cell: *mut Indirect<T::Base>, refs: usize) {
+    if (*cell).refs.fetch_sub(refs, AcqRel) == refs {
+        let cell = Box::from_raw(cell);
+        T::dec(cell.ptr);
+    }
+}
+
+/// A strategy with split reference counts, needing no per-thread data.
+///
+/// See the [`strategy`][super] module documentation for the list of strategies. This one makes
+/// writers cheap (and their cost independent of the number of threads) and doesn't need to
+/// register the threads anywhere, so it suits applications with a lot of short-lived threads.
+/// The readers, on the other hand, contend with each other.
+///
+/// The guards hold a full reference count, so there's no limit on how many of them a thread may
+/// hold. Note that the spare references (up to 64 of them) are visible in [`Arc::strong_count`]
+/// of the stored value. Each store adds them and settles the unused ones of the previous value,
+/// one at a time.
+///
+/// [`Arc::strong_count`]: crate::imports::Arc::strong_count
+///
+/// Available on 64-bit x86 and ARM, with the `experimental-strategies` feature.
+#[derive(Clone, Default)]
+pub struct SplitCountStrategy;
+
+impl SplitCountStrategy {
+    /// Takes one of the spare references.
+    ///
+    /// Returns the value together with the entry it was taken from. If it is an indirect one, the
+    /// reference to the cell is kept too (so the cell stays in place) and the caller needs to
+    /// [`release`] it.
+    unsafe fn take<T: RefCnt>(storage: &AtomicPtr<T::Base>) -> (T, *mut T::Base) {
+        let mut raw = storage.load(Acquire);
+        loop {
+            let (entry, count) = split(raw);
+            if entry.is_null() {
+                // No reference counts to take care of.
+                return (T::from_ptr(entry), entry);
+            }
+            if count >= RESERVE {
+                // Someone is refilling it right now.
+                atomic::spin_loop_hint();
+                raw = storage.load(Acquire);
+                continue;
+            }
+            // Acquire to get the data and the reference counts in the reserve.
+            match storage.compare_exchange_weak(raw, with_count(entry, count + 1), Acquire, Acquire)
+            {
+                Ok(_) => {
+                    // One of the spare references is ours now.
+                    let cell = cell(entry);
+                    let val = if cell.is_null() {
+                        T::from_ptr(entry)
+                    } else {
+                        // We hold the cell, and the cell holds the value.
+                        with_value((*cell).ptr, |val: &T| T::from_ptr(T::inc(val)))
+                    };
+                    if count + 1 == REFILL {
+                        Self::refill(storage, entry, &val);
+                    }
+                    return (val, entry);
+                }
+                Err(changed) => raw = changed,
+            }
+        }
+    }
+
+    /// Adds more spare references to the reserve.
+    ///
+    /// Called by the reader which took the reference exactly at the half of the reserve, so
+    /// there's only one of them at a time for each reserve.
+    unsafe fn refill<T: RefCnt>(storage: &AtomicPtr<T::Base>, entry: *mut T::Base, val: &T) {
+        let cell = cell(entry);
+        if cell.is_null() {
+            for _ in 0..REFILL {
+                T::inc(val);
+            }
+        } else {
+            // We hold a reference to the cell, so it's alive.
+            (*cell).refs.fetch_add(REFILL, Relaxed);
+        }
+        let mut raw = storage.load(Relaxed);
+        loop {
+            let (current, count) = split(raw);
+            if current != entry || count < REFILL {
+                // The value was replaced in the meantime (and the reserve settled by the writer).
+                // It may have been put back in since, but that's a new reserve that doesn't need
+                // our refill.
+                if cell.is_null() {
+                    for _ in 0..REFILL {
+                        T::dec(entry);
+                    }
+                } else {
+                    // We still hold one, so this is never the last.
+                    release::<T>(cell, REFILL);
+                }
+                return;
+            }
+            // Release to publish our increments to whoever settles the reserve.
+            match storage.compare_exchange_weak(
+                raw,
+                with_count(entry, count - REFILL),
+                AcqRel,
+                Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(changed) => raw = changed,
+            }
+        }
+    }
+}
+
+impl<T: RefCnt> InnerStrategy<T> for SplitCountStrategy {
+    type Protected = T;
+    unsafe fn load(&self, storage: &AtomicPtr<T::Base>) -> T {
+        let (val, entry) = Self::take(storage);
+        let cell = cell(entry);
+        if !cell.is_null() {
+            release::<T>(cell, 1);
+        }
+        val
+    }
+    unsafe fn wait_for_readers(&self, _: *const T::Base, _: &AtomicPtr<T::Base>) {
+        // The readers own full references, they don't need anything from us.
+    }
+    unsafe fn enter_storage(&self, ptr: *mut T::Base) -> *mut T::Base {
+        if ptr.is_null() {
+            return ptr;
+        }
+        if ptr as usize & META_MASK == 0 {
+            with_value(ptr, |val: &T| {
+                for _ in 0..RESERVE {
+                    T::inc(val);
+                }
+            });
+            return ptr;
+        }
+        // The pointer doesn't leave us the bits, keep it through a cell. The reference of the
+        // storage is moved into the cell.
+        let cell = Box::into_raw(Box::new(Indirect {
+            ptr,
+            refs: AtomicUsize::new(1 + RESERVE),
+        }));
+        if cell as usize & META_MASK != 0 {
+            // Not even our own allocation fits, there's nothing left to fall back to. Don't leak
+            // the value at least.
+            release::<T>(cell, 1 + RESERVE);
+            panic!("The allocated pointers use the high bits needed by SplitCountStrategy");
+        }
+        (cell as usize | INDIRECT) as *mut T::Base
+    }
+    unsafe fn leave_storage(&self, raw: *mut T::Base) -> *mut T::Base {
+        let (entry, count) = split(raw);
+        let cell = cell(entry);
+        if cell.is_null() {
+            if !entry.is_null() {
+                // The storage keeps its own reference, so this never drops to 0.
+                for _ in count..RESERVE {
+                    T::dec(entry);
+                }
+            }
+            entry
+        } else {
+            // The reference of the storage goes to the value itself, then the cell is released
+            // (the unused spare references and the one of the storage).
+            let ptr = (*cell).ptr;
+            with_value(ptr, |val: &T| T::inc(val));
+            release::<T>(cell, RESERVE - count + 1);
+            ptr
+        }
+    }
+    fn peek_storage(&self, raw: *mut T::Base) -> *mut T::Base {
+        let (entry, _) = split(raw);
+        // For an indirect entry, this is the address of the cell. It is not dereferenced (the
+        // cell might be gone already) and it can't be equal to any value still alive.
+        (entry as usize & !INDIRECT) as *mut T::Base
+    }
+}
+
+impl<T: RefCnt> CaS<T> for SplitCountStrategy {
+    unsafe fn compare_and_swap<C: AsRaw<T::Base>>(
+        &self,
+        storage: &AtomicPtr<T::Base>,
+        current: C,
+        new: T,
+    ) -> T {
+        let new = InnerStrategy::<T>::enter_storage(self, T::into_ptr(new));
+        loop {
+            let (old, entry) = Self::take(storage);
+            let cell = cell(entry);
+            if T::as_ptr(&old) != current.as_raw() {
+                T::dec(InnerStrategy::<T>::leave_storage(self, new));
+                if !cell.is_null() {
+                    release::<T>(cell, 1);
+                }
+                return old;
+            }
+            // We hold the old value (and the cell, if any), so the entry can't get reused. If it's
+            // still there, only the counter may change under our hands.
+            let mut swapped = false;
+            let mut raw = storage.load(Relaxed);
+            while split(raw).0 == entry {
+                match storage.compare_exchange_weak(raw, new, SeqCst, Relaxed) {
+                    Ok(_) => {
+                        // Get rid of the reference held by the storage, we have one in old.
+                        T::dec(InnerStrategy::<T>::leave_storage(self, raw));
+                        swapped = true;
+                        break;
+                    }
+                    Err(changed) => raw = changed,
+                }
+            }
+            if !cell.is_null() {
+                release::<T>(cell, 1);
+            }
+            if swapped {
+                return old;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::imports::{Arc, Vec};
+    use crate::ArcSwapAny;
+
+    use super::*;
+
+    type SplitArcSwap<T> = ArcSwapAny<Arc<T>, SplitCountStrategy>;
+
+    /// An [`Arc`] with some of the high bits of the pointer set.
+    #[derive(Clone, Debug)]
+    struct Tagged<const TAG: usize>(Arc<usize>);
+
+    unsafe impl<const TAG: usize> RefCnt for Tagged<TAG> {
+        type Base = usize;
+        fn into_ptr(me: Self) -> *mut usize {
+            (Arc::into_raw(me.0) as usize | TAG) as *mut usize
+        }
+        fn as_ptr(me: &Self) -> *mut usize {
+            (Arc::as_ptr(&me.0) as usize | TAG) as *mut usize
+        }
+        unsafe fn from_ptr(ptr: *const usize) -> Self {
+            assert_eq!(TAG, ptr as usize & TAG);
+            Tagged(Arc::from_raw((ptr as usize & !TAG) as *const usize))
+        }
+    }
+
+    /// Loads, stores and compare-and-swaps of the tagged pointers keep the counts right.
+    fn tagged<const TAG: usize>() {
+        let first = Arc::new(1);
+        let second = Arc::new(2);
+        let shared = ArcSwapAny::<_, SplitCountStrategy>::new(Tagged::<TAG>(Arc::clone(&first)));
+        let held = (0..RESERVE * 3).map(|_| shared.load()).collect::<Vec<_>>();
+        assert!(held.iter().all(|guard| *guard.0 == 1));
+        let old = shared.compare_and_swap(&*held[0], Tagged(Arc::clone(&second)));
+        assert!(Arc::ptr_eq(&first, &old.0));
+        drop(old);
+        // This one fails, the value is no longer there.
+        let old = shared.compare_and_swap(&*held[0], Tagged(Arc::clone(&first)));
+        assert!(Arc::ptr_eq(&second, &old.0));
+        drop(old);
+        assert_eq!(2, *shared.swap(Tagged(Arc::clone(&first))).0);
+        assert_eq!(1, Arc::strong_count(&second));
+        drop(held);
+        drop(shared);
+        assert_eq!(1, Arc::strong_count(&first));
+    }
+
+    /// The same as [`concurrent`], through the indirect cells.
+    #[test]
+    fn concurrent_indirect() {
+        let values = (0..4).map(Arc::new).collect::<Vec<_>>();
+        let tagged = |i: usize| Tagged::<{ 1 << 55 }>(Arc::clone(&values[i % 4]));
+        let shared = ArcSwapAny::<_, SplitCountStrategy>::new(tagged(0));
+        crossbeam_utils::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|_| {
+                    for _ in 0..10_000 {
+                        let guard = shared.load();
+                        assert!(*guard.0 < 4);
+                    }
+                });
+            }
+            s.spawn(|_| {
+                for i in 0..1_000 {
+                    shared.store(tagged(i));
+                }
+            });
+            s.spawn(|_| {
+                for i in 0..1_000 {
+                    let current = shared.load();
+                    shared.compare_and_swap(&*current, tagged(i));
+                }
+            });
+        })
+        .unwrap();
+        drop(shared);
+        for value in &values {
+            assert_eq!(1, Arc::strong_count(value));
+        }
+    }
+
+    /// A tag in the top byte is kept, the counter fits below it.
+    #[test]
+    fn top_byte_tag() {
+        tagged::<{ 1 << 60 }>();
+    }
+
+    /// A pointer using the bits of the counter goes through the indirect cell.
+    #[test]
+    fn indirect() {
+        tagged::<{ 1 << 55 }>();
+        tagged::<{ 1 << 50 }>();
+    }
+
+    /// The reserve is settled when the value is taken out.
+    #[test]
+    fn counts_settled() {
+        let first = Arc::new(1);
+        let shared = SplitArcSwap::new(Arc::clone(&first));
+        assert_eq!(RESERVE + 2, Arc::strong_count(&first));
+        let guards = (0..10).map(|_| shared.load()).collect::<Vec<_>>();
+        assert_eq!(RESERVE + 2, Arc::strong_count(&first));
+        shared.store(Arc::new(2));
+        assert_eq!(11, Arc::strong_count(&first));
+        drop(guards);
+        assert_eq!(1, Arc::strong_count(&first));
+    }
+
+    /// Going over the refill threshold several times keeps the counts right.
+    #[test]
+    fn refills() {
+        let first = Arc::new(1);
+        let shared = SplitArcSwap::new(Arc::clone(&first));
+        let mut held = Vec::new();
+        for _ in 0..RESERVE * 3 {
+            held.push(shared.load());
+        }
+        assert!(Arc::strong_count(&first) > RESERVE * 3 + 2);
+        assert_eq!(1, **shared.load());
+        drop(held);
+        drop(shared);
+        assert_eq!(1, Arc::strong_count(&first));
+    }
+
+    /// Concurrent loads, stores and compare-and-swaps don't lose or leak references.
+    #[test]
+    fn concurrent() {
+        let values = (0..4).map(Arc::new).collect::<Vec<_>>();
+        let shared = SplitArcSwap::new(Arc::clone(&values[0]));
+        crossbeam_utils::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|_| {
+                    for _ in 0..10_000 {
+                        let guard = shared.load();
+                        assert!(**guard < 4);
+                    }
+                });
+            }
+            s.spawn(|_| {
+                for i in 0..1_000 {
+                    shared.store(Arc::clone(&values[i % 4]));
+                }
+            });
+            s.spawn(|_| {
+                for i in 0..1_000 {
+                    let current = shared.load();
+                    shared.compare_and_swap(&*current, Arc::clone(&values[i % 4]));
+                }
+            });
+        })
+        .unwrap();
+        drop(shared);
+        for value in &values {
+            assert_eq!(1, Arc::strong_count(value));
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! The atomic types used by the core algorithms.
+//!
+//! Normally, these are just the ones from [`core`]. When built with `--cfg loom`, they are replaced
+//! with the [`loom`](https://docs.rs/loom) ones, so the model checker can explore all the
+//! interleavings (see `tests/loom.rs`). With the `portable-atomic` feature, they come from the
+//! [`portable-atomic`](https://docs.rs/portable-atomic) crate, which provides them (including the
+//! compare and swap) even on targets that don't have them natively.
+//!
+//! The loom atomics lack few things the core ones have (`get_mut`, being constructible in const
+//! context), so there are small helpers for that too.
+
+#[cfg(loom)]
+pub(crate) use self::loom_shim::{AtomicPtr, AtomicUsize};
+#[cfg(all(not(loom), not(feature = "portable-atomic")))]
+pub(crate) use core::sync::atomic::{AtomicPtr, AtomicUsize};
+#[cfg(all(not(loom), feature = "portable-atomic"))]
+pub(crate) use portable_atomic::{AtomicPtr, AtomicUsize};
+
+/// Reads the value of an atomic pointer we have exclusive access to.
+#[inline]
+pub(crate) fn ptr_get_mut<T>(ptr: &mut AtomicPtr<T>) -> *mut T {
+    #[cfg(not(loom))]
+    {
+        *ptr.get_mut()
+    }
+    #[cfg(loom)]
+    {
+        ptr.0.with_mut(|p| *p)
+    }
+}
+
+/// Writes the value of an atomic pointer we have exclusive access to.
+#[inline]
+pub(crate) fn ptr_set_mut<T>(ptr: &mut AtomicPtr<T>, val: *mut T) {
+    #[cfg(not(loom))]
+    {
+        *ptr.get_mut() = val;
+    }
+    #[cfg(loom)]
+    {
+        ptr.0.with_mut(|p| *p = val);
+    }
+}
+
+/// Loom atomics with `SeqCst` operations strengthened.
+///
+/// Loom models `SeqCst` operations only as `AcqRel`, while our algorithms rely on the single total
+/// order of them (and loom reports false use-after-free in them). It does, however, model `SeqCst`
+/// fences. Surrounding each such operation with the fences is at least as strong as the real
+/// thing, so the model checker doesn't report false alarms. The price is it may miss a problem
+/// that relies on `SeqCst` being weaker than the fences.
+#[cfg(loom)]
+mod loom_shim {
+    use core::sync::atomic::Ordering::{self, SeqCst};
+
+    use loom::sync::atomic::{self as inner, fence};
+
+    fn around<R, F: FnOnce() -> R>(strong: bool, f: F) -> R {
+        if strong {
+            fence(SeqCst);
+        }
+        let result = f();
+        if strong {
+            fence(SeqCst);
+        }
+        result
+    }
+
+    macro_rules! shim {
+        ($name: ident, $($gen: ident)?, $val: ty) => {
+            #[derive(Debug)]
+            // pub because it shows in the sealed strategy traits, not reachable from outside.
+            pub struct $name<$($gen)?>(pub(super) inner::$name<$($gen)?>);
+
+            // Not every method is used with both types.
+            #[allow(dead_code)]
+            impl<$($gen)?> $name<$($gen)?> {
+                pub(crate) fn new(val: $val) -> Self {
+                    $name(inner::$name::new(val))
+                }
+
+                pub(crate) fn load(&self, order: Ordering) -> $val {
+                    around(order == SeqCst, || self.0.load(order))
+                }
+
+                pub(crate) fn store(&self, val: $val, order: Ordering) {
+                    around(order == SeqCst, || self.0.store(val, order))
+                }
+
+                pub(crate) fn swap(&self, val: $val, order: Ordering) -> $val {
+                    around(order == SeqCst, || self.0.swap(val, order))
+                }
+
+                pub(crate) fn compare_exchange(
+                    &self,
+                    current: $val,
+                    new: $val,
+                    success: Ordering,
+                    failure: Ordering,
+                ) -> Result<$val, $val> {
+                    around(success == SeqCst || failure == SeqCst, || {
+                        self.0.compare_exchange(current, new, success, failure)
+                    })
+                }
+
+                pub(crate) fn compare_exchange_weak(
+                    &self,
+                    current: $val,
+                    new: $val,
+                    success: Ordering,
+                    failure: Ordering,
+                ) -> Result<$val, $val> {
+                    around(success == SeqCst || failure == SeqCst, || {
+                        self.0.compare_exchange_weak(current, new, success, failure)
+                    })
+                }
+            }
+        };
+    }
+
+    shim!(AtomicPtr, T, *mut T);
+    shim!(AtomicUsize, , usize);
+
+    impl Default for AtomicUsize {
+        fn default() -> Self {
+            Self::new(0)
+        }
+    }
+
+    impl AtomicUsize {
+        pub(crate) fn fetch_add(&self, val: usize, order: Ordering) -> usize {
+            around(order == SeqCst, || self.0.fetch_add(val, order))
+        }
+
+        pub(crate) fn fetch_sub(&self, val: usize, order: Ordering) -> usize {
+            around(order == SeqCst, || self.0.fetch_sub(val, order))
+        }
+
+        pub(crate) fn fetch_or(&self, val: usize, order: Ordering) -> usize {
+            around(order == SeqCst, || self.0.fetch_or(val, order))
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! Blocking waits for a change of the value.
+//!
+//! Waiting threads register themselves in a global counter and in one of few shards, picked by the
+//! address of the storage. Writers check the counter after each change and only if there are
+//! some waiters, they wake up the ones in the shard of their storage through its condition
+//! variable. The woken up waiters check their own storage and go back to sleep if it's not the one
+//! they are interested in (multiple storages share a shard). Therefore, this is cheap for writers
+//! if no one waits (one atomic load), but not suitable for many waiters on busy storages.
+//!
+//! Available only with the standard library.
+
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use crate::ref_cnt::RefCnt;
+use crate::strategy::Strategy;
+use crate::{ArcSwapAny, Guard};
+
+/// Number of the shards.
+const SHARDS: usize = 64;
+
+/// Number of currently waiting threads, on any storage.
+static WAITERS: AtomicUsize = AtomicUsize::new(0);
+
+/// The waiting place of the storages on some of the addresses.
+struct Shard {
+    /// Number of threads currently waiting in this shard.
+    waiters: AtomicUsize,
+    /// Generation of the changes, incremented by a writer whenever it wakes someone up.
+    ///
+    /// Waiters check this didn't change between looking at the value and going to sleep, so
+    /// they don't miss a wake up.
+    generation: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl Shard {
+    fn generation(&self) -> MutexGuard<'_, u64> {
+        // Only incrementing happens under the lock, nothing can be left broken by a panic.
+        self.generation
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Lazily allocated on the first use and never freed.
+///
+/// Not a `static` array directly, because creating the mutexes in a static needs a newer
+/// compiler.
+static SHARED: AtomicPtr<Vec<Shard>> = AtomicPtr::new(ptr::null_mut());
+
+/// The shard of the storage on the given address.
+fn shard(addr: usize) -> &'static Shard {
+    let mut current = SHARED.load(Ordering::Acquire);
+    if current.is_null() {
+        let shards = (0..SHARDS)
+            .map(|_| Shard {
+                waiters: AtomicUsize::new(0),
+                generation: Mutex::new(0),
+                changed: Condvar::new(),
+            })
+            .collect();
+        let new = Box::into_raw(Box::new(shards));
+        current = match SHARED.compare_exchange(
+            ptr::null_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new,
+            Err(other) => {
+                // Someone was faster, use theirs.
+                drop(unsafe { Box::from_raw(new) });
+                other
+            }
+        };
+    }
+    // Once set, it is never changed nor freed.
+    let shards = unsafe { &*current };
+    // The storages are at least pointer-aligned, the lowest bits don't carry anything.
+    &shards[(addr / mem::align_of::<usize>()) % SHARDS]
+}
+
+/// Wakes up the waiters of the storage on the given address, if there are any.
+///
+/// Called by the writers after each change of any storage.
+#[inline]
+pub(crate) fn notify(storage_addr: usize) {
+    // SeqCst: Pairs with the registration in Registration::new. Either the waiter registers after
+    // our change of the pointer and then sees the change when looking at the value, or we see it
+    // registered and wake it up.
+    if WAITERS.load(Ordering::SeqCst) != 0 {
+        notify_slow(storage_addr);
+    }
+}
+
+#[cold]
+fn notify_slow(storage_addr: usize) {
+    let shard = shard(storage_addr);
+    // SeqCst: The same as above, the waiters in other shards don't count.
+    if shard.waiters.load(Ordering::SeqCst) != 0 {
+        *shard.generation() += 1;
+        shard.changed.notify_all();
+    }
+}
+
+/// Keeps the waiter counted for its lifetime.
+struct Registration(&'static Shard);
+
+impl Registration {
+    fn new(shard: &'static Shard) -> Self {
+        shard.waiters.fetch_add(1, Ordering::SeqCst);
+        WAITERS.fetch_add(1, Ordering::SeqCst);
+        Registration(shard)
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        WAITERS.fetch_sub(1, Ordering::SeqCst);
+        self.0.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// When the timeout elapses, `None` if it is too far in the future to represent (never).
+#[rustversion::since(1.34)]
+fn deadline(timeout: Duration) -> Option<Instant> {
+    Instant::now().checked_add(timeout)
+}
+
+#[rustversion::before(1.34)]
+fn deadline(timeout: Duration) -> Option<Instant> {
+    // Instant::checked_add needs a newer compiler. Anything this long is as good as forever.
+    if timeout >= Duration::from_secs(u64::from(u32::max_value())) {
+        None
+    } else {
+        Some(Instant::now() + timeout)
+    }
+}
+
+impl<T: RefCnt, S: Strategy<T>> ArcSwapAny<T, S> {
+    /// Waits until the stored value satisfies the predicate.
+    ///
+    /// The predicate is checked right away and then every time a writer changes the value (it
+    /// can also be called spuriously). Returns the value that satisfied it, or `None` if the
+    /// `timeout` elapsed first. A timeout too long to represent (like [`Duration::MAX`]) waits
+    /// forever.
+    ///
+    /// The writers wake up the waiting threads on [`store`][ArcSwapAny::store],
+    /// [`swap`][ArcSwapAny::swap], [`compare_and_swap`][ArcSwapAny::compare_and_swap] (and the
+    /// methods built on top of these). This costs them close to nothing if no thread waits.
+    ///
+    /// Available only with the standard library.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// use arc_swap::ArcSwap;
+    ///
+    /// let state = Arc::new(ArcSwap::from_pointee("starting"));
+    /// let writer = {
+    ///     let state = Arc::clone(&state);
+    ///     thread::spawn(move || state.store(Arc::new("running")))
+    /// };
+    /// let running = state.wait_until(|s| **s == "running", Duration::from_secs(10));
+    /// assert_eq!("running", **running.unwrap());
+    /// writer.join().unwrap();
+    /// ```
+    pub fn wait_until<P>(&self, mut pred: P, timeout: Duration) -> Option<Guard<T, S>>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let deadline = deadline(timeout);
+        let shard = shard(&self.ptr as *const _ as usize);
+        let _registration = Registration::new(shard);
+        loop {
+            let seen = *shard.generation();
+            let current = self.load();
+            if pred(&current) {
+                return Some(current);
+            }
+            // Don't hold the value while sleeping, it could block its release for a long time.
+            drop(current);
+
+            let mut gen = shard.generation();
+            while *gen == seen {
+                gen = match deadline {
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            return None;
+                        }
+                        shard
+                            .changed
+                            .wait_timeout(gen, deadline - now)
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .0
+                    }
+                    None => shard
+                        .changed
+                        .wait(gen)
+                        .unwrap_or_else(PoisonError::into_inner),
+                };
+            }
+        }
+    }
+
+    /// Waits until the stored value is different from the `current` one.
+    ///
+    /// Returns the new value, or `None` if the `timeout` elapsed first. This is a shortcut for
+    /// [`wait_until`][ArcSwapAny::wait_until] comparing the pointers. The guard keeps the old
+    /// value alive, so a new value can't be mistaken for it just because it got the same address.
+    ///
+    /// Available only with the standard library.
+    pub fn wait_for_change_from(
+        &self,
+        current: &Guard<T, S>,
+        timeout: Duration,
+    ) -> Option<Guard<T, S>> {
+        let current = T::as_ptr(current);
+        self.wait_until(|new| T::as_ptr(new) != current, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+    use crate::ArcSwap;
+
+    #[test]
+    fn already_there() {
+        let shared = ArcSwap::from_pointee(42);
+        let found = shared.wait_until(|v| **v == 42, Duration::from_secs(0));
+        assert_eq!(42, **found.unwrap());
+    }
+
+    #[test]
+    fn timeout() {
+        let shared = ArcSwap::from_pointee(0);
+        let guard = shared.load();
+        assert!(shared
+            .wait_for_change_from(&guard, Duration::from_millis(10))
+            .is_none());
+    }
+
+    #[test]
+    fn woken_up() {
+        let shared = Arc::new(ArcSwap::from_pointee(0));
+        let old = shared.load();
+        let writer = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                for i in 1..=10 {
+                    thread::sleep(Duration::from_millis(1));
+                    shared.rcu(|_| i);
+                }
+            })
+        };
+        let changed = shared.wait_for_change_from(&old, Duration::from_secs(60));
+        assert_ne!(0, **changed.unwrap());
+        let last = shared.wait_until(|v| **v == 10, Duration::from_secs(60));
+        assert_eq!(10, **last.unwrap());
+        writer.join().unwrap();
+    }
+
+    /// Changes of other storages don't wake the waiter up.
+    #[test]
+    fn unrelated() {
+        // Next to each other, so they are in different shards.
+        let shared = [ArcSwap::from_pointee(0), ArcSwap::from_pointee(0)];
+        let done = AtomicBool::new(false);
+        let checks = crossbeam_utils::thread::scope(|s| {
+            s.spawn(|_| {
+                while !done.load(Ordering::Relaxed) {
+                    shared[1].store(Arc::new(1));
+                }
+            });
+            let mut checks = 0;
+            let found = shared[0].wait_until(
+                |_| {
+                    checks += 1;
+                    false
+                },
+                Duration::from_millis(50),
+            );
+            assert!(found.is_none());
+            done.store(true, Ordering::Relaxed);
+            checks
+        })
+        .unwrap();
+        // Only the initial check, not a single wake up.
+        assert_eq!(1, checks);
+    }
+
+    /// A timeout that doesn't fit into an `Instant` waits for as long as it takes.
+    #[test]
+    fn unlimited() {
+        let shared = Arc::new(ArcSwap::from_pointee(0));
+        let old = shared.load();
+        let writer = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                shared.store(Arc::new(1));
+            })
+        };
+        let changed = shared.wait_for_change_from(&old, Duration::MAX);
+        assert_eq!(1, **changed.unwrap());
+        writer.join().unwrap();
+    }
+}
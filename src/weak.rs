@@ -1,9 +1,10 @@
 use core::ptr;
 
 use alloc::rc::Weak as RcWeak;
-use alloc::sync::Weak;
 
-use crate::RefCnt;
+use crate::imports::{Arc, Weak};
+use crate::strategy::{CaS, Strategy};
+use crate::{ArcSwapAny, Guard, RefCnt};
 
 unsafe impl<T> RefCnt for Weak<T> {
     type Base = T;
@@ -55,16 +56,249 @@ unsafe impl<T> RefCnt for RcWeak<T> {
     }
 }
 
+/// Either a strong or a weak reference to a value.
+///
+/// This is what is stored inside the [`ArcSwapWeakOrStrong`][crate::ArcSwapWeakOrStrong]. It
+/// allows a cache to keep a value alive while there's enough memory and to
+/// [demote][ArcSwapAny::demote] it to a weak reference (without losing it if someone else still
+/// uses it) when the memory gets scarce.
+///
+/// Needs the `weak` feature turned on.
+#[derive(Debug)]
+pub enum StrongOrWeak<T> {
+    /// A strong reference, keeping the value alive.
+    Strong(Arc<T>),
+    /// A weak reference, not keeping the value alive.
+    Weak(Weak<T>),
+}
+
+// Derive would require T: Clone
+impl<T> Clone for StrongOrWeak<T> {
+    fn clone(&self) -> Self {
+        match self {
+            StrongOrWeak::Strong(strong) => StrongOrWeak::Strong(Arc::clone(strong)),
+            StrongOrWeak::Weak(weak) => StrongOrWeak::Weak(Weak::clone(weak)),
+        }
+    }
+}
+
+impl<T> StrongOrWeak<T> {
+    /// Is this the strong variant?
+    pub fn is_strong(&self) -> bool {
+        matches!(self, StrongOrWeak::Strong(_))
+    }
+
+    /// Gets a strong reference to the value, if it is still alive.
+    pub fn upgrade(&self) -> Option<Arc<T>> {
+        match self {
+            StrongOrWeak::Strong(strong) => Some(Arc::clone(strong)),
+            StrongOrWeak::Weak(weak) => weak.upgrade(),
+        }
+    }
+
+    /// Gets a weak reference to the value.
+    pub fn downgrade(&self) -> Weak<T> {
+        match self {
+            StrongOrWeak::Strong(strong) => Arc::downgrade(strong),
+            StrongOrWeak::Weak(weak) => Weak::clone(weak),
+        }
+    }
+}
+
+impl<T> Default for StrongOrWeak<T> {
+    fn default() -> Self {
+        StrongOrWeak::Weak(Weak::new())
+    }
+}
+
+impl<T> From<Arc<T>> for StrongOrWeak<T> {
+    fn from(strong: Arc<T>) -> Self {
+        StrongOrWeak::Strong(strong)
+    }
+}
+
+impl<T> From<Weak<T>> for StrongOrWeak<T> {
+    fn from(weak: Weak<T>) -> Self {
+        StrongOrWeak::Weak(weak)
+    }
+}
+
+/// Marks the strong variant in the pointer.
+///
+/// The pointer inside an `Arc` points behind the two reference counts, so it is aligned at least
+/// to an `usize` and the lowest bit is free. Note that the tagged pointer can never be the
+/// `Debt::NONE` marker.
+const STRONG_TAG: usize = 0b01;
+
+unsafe impl<T> RefCnt for StrongOrWeak<T> {
+    type Base = T;
+    fn as_ptr(me: &Self) -> *mut T {
+        match me {
+            StrongOrWeak::Strong(strong) => {
+                let ptr = Arc::as_ptr(strong) as usize;
+                debug_assert_eq!(ptr & STRONG_TAG, 0);
+                (ptr | STRONG_TAG) as *mut T
+            }
+            StrongOrWeak::Weak(weak) => RefCnt::as_ptr(weak),
+        }
+    }
+    fn into_ptr(me: Self) -> *mut T {
+        let ptr = Self::as_ptr(&me);
+        match me {
+            StrongOrWeak::Strong(strong) => drop(Arc::into_raw(strong)),
+            StrongOrWeak::Weak(weak) => drop(RefCnt::into_ptr(weak)),
+        }
+        ptr
+    }
+    unsafe fn from_ptr(ptr: *const T) -> Self {
+        let addr = ptr as usize;
+        if addr & STRONG_TAG == 0 {
+            StrongOrWeak::Weak(RefCnt::from_ptr(ptr))
+        } else {
+            StrongOrWeak::Strong(Arc::from_raw((addr & !STRONG_TAG) as *const T))
+        }
+    }
+}
+
+impl<T, S: Strategy<Weak<T>>> ArcSwapAny<Weak<T>, S> {
+    /// Loads the value and upgrades it to a strong reference.
+    ///
+    /// Returns `None` if the storage is empty or if the value is no longer alive.
+    ///
+    /// Needs the `weak` feature turned on.
+    pub fn load_upgrade(&self) -> Option<Guard<Arc<T>>> {
+        self.load().upgrade().map(Guard::from_inner)
+    }
+
+    /// Compares the live value and swaps it, treating a dead weak as empty.
+    ///
+    /// This is similar to [`compare_and_swap`][ArcSwapAny::compare_and_swap], but the `current`
+    /// is compared to the upgraded value. A `None` matches both an empty weak and one whose value
+    /// is already gone.
+    ///
+    /// Returns the upgraded previous value. The swap happened if it is the same as `current`.
+    ///
+    /// Needs the `weak` feature turned on.
+    pub fn compare_and_swap_live(&self, current: Option<&Arc<T>>, new: Weak<T>) -> Option<Arc<T>>
+    where
+        S: CaS<Weak<T>>,
+    {
+        loop {
+            // The guard keeps the allocation (not the value) alive, so the address can't be
+            // reused by something else while we compare against it.
+            let loaded = self.load();
+            let live = loaded.upgrade();
+            let matches = match (current, &live) {
+                (None, None) => true,
+                (Some(current), Some(live)) => Arc::ptr_eq(current, live),
+                _ => false,
+            };
+            if !matches {
+                return live;
+            }
+            let previous = self.compare_and_swap(&*loaded, Weak::clone(&new));
+            if RefCnt::as_ptr(&*previous) == RefCnt::as_ptr(&*loaded) {
+                return live;
+            }
+            // Changed in the meantime, try again with the new value.
+        }
+    }
+
+    /// Stores the new value, but only if the current one is empty or dead.
+    ///
+    /// Returns if the value was stored.
+    ///
+    /// Needs the `weak` feature turned on.
+    pub fn store_if_dead(&self, new: Weak<T>) -> bool
+    where
+        S: CaS<Weak<T>>,
+    {
+        self.compare_and_swap_live(None, new).is_none()
+    }
+}
+
+impl<T, S: Strategy<StrongOrWeak<T>>> ArcSwapAny<StrongOrWeak<T>, S> {
+    /// Loads the value and upgrades it to a strong reference.
+    ///
+    /// Returns `None` if the value is weak and no longer alive.
+    ///
+    /// Needs the `weak` feature turned on.
+    pub fn load_upgrade(&self) -> Option<Guard<Arc<T>>> {
+        self.load().upgrade().map(Guard::from_inner)
+    }
+
+    fn replace_with<F>(&self, mut f: F) -> bool
+    where
+        F: FnMut(&StrongOrWeak<T>) -> Option<StrongOrWeak<T>>,
+        S: CaS<StrongOrWeak<T>>,
+    {
+        loop {
+            let loaded = self.load();
+            let new = match f(&loaded) {
+                Some(new) => new,
+                None => return false,
+            };
+            let previous = self.compare_and_swap(&*loaded, new);
+            if RefCnt::as_ptr(&*previous) == RefCnt::as_ptr(&*loaded) {
+                return true;
+            }
+        }
+    }
+
+    /// Turns a strong reference inside into a weak one.
+    ///
+    /// This happens atomically ‒ if someone stores a different value concurrently, it is not
+    /// overwritten by the weak version of the old one. The value stays available as long as anyone
+    /// else holds it.
+    ///
+    /// Returns `false` if the value already was weak.
+    ///
+    /// Needs the `weak` feature turned on.
+    pub fn demote(&self) -> bool
+    where
+        S: CaS<StrongOrWeak<T>>,
+    {
+        self.replace_with(|current| match current {
+            StrongOrWeak::Strong(strong) => Some(StrongOrWeak::Weak(Arc::downgrade(strong))),
+            StrongOrWeak::Weak(_) => None,
+        })
+    }
+
+    /// Turns a weak reference inside into a strong one, if the value is still alive.
+    ///
+    /// Returns the value if it is strong after the call.
+    ///
+    /// Needs the `weak` feature turned on.
+    pub fn promote(&self) -> Option<Arc<T>>
+    where
+        S: CaS<StrongOrWeak<T>>,
+    {
+        let mut promoted = None;
+        self.replace_with(|current| {
+            promoted = current.upgrade();
+            match (current, &promoted) {
+                (StrongOrWeak::Weak(_), Some(strong)) => {
+                    Some(StrongOrWeak::Strong(Arc::clone(strong)))
+                }
+                _ => None,
+            }
+        });
+        promoted
+    }
+}
+
 macro_rules! t {
     ($name: ident, $strategy: ty) => {
         #[cfg(test)]
         mod $name {
             use alloc::sync::{Arc, Weak};
 
-            use crate::ArcSwapAny;
+            use crate::{ArcSwapAny, StrongOrWeak};
 
             #[allow(deprecated)] // We use "deprecated" testing strategies in here.
             type ArcSwapWeak<T> = ArcSwapAny<Weak<T>, $strategy>;
+            #[allow(deprecated)]
+            type ArcSwapWeakOrStrong<T> = ArcSwapAny<StrongOrWeak<T>, $strategy>;
 
             // Convert to weak, push it through the shared and pull it out again.
             #[test]
@@ -106,6 +340,82 @@ macro_rules! t {
                 let weak = shared.load();
                 assert!(weak.upgrade().is_none());
             }
+
+            #[test]
+            fn load_upgrade() {
+                let data = Arc::new("Hello");
+                let shared = ArcSwapWeak::new(Arc::downgrade(&data));
+                assert_eq!("Hello", **shared.load_upgrade().unwrap());
+
+                drop(data);
+                assert!(shared.load_upgrade().is_none());
+            }
+
+            // A dead weak is treated as an empty one
+            #[test]
+            fn store_if_dead() {
+                let data = Arc::new("Hello");
+                let other = Arc::new("World");
+                let shared = ArcSwapWeak::new(Weak::new());
+                assert!(shared.store_if_dead(Arc::downgrade(&data)));
+                assert!(!shared.store_if_dead(Arc::downgrade(&other)));
+                assert_eq!("Hello", **shared.load_upgrade().unwrap());
+
+                drop(data);
+                assert!(shared.store_if_dead(Arc::downgrade(&other)));
+                assert_eq!("World", **shared.load_upgrade().unwrap());
+            }
+
+            #[test]
+            fn compare_and_swap_live() {
+                let data = Arc::new("Hello");
+                let other = Arc::new("World");
+                let shared = ArcSwapWeak::new(Arc::downgrade(&data));
+
+                let previous = shared.compare_and_swap_live(None, Arc::downgrade(&other));
+                assert!(Arc::ptr_eq(&data, &previous.unwrap()));
+                assert_eq!("Hello", **shared.load_upgrade().unwrap());
+
+                let previous = shared.compare_and_swap_live(Some(&data), Arc::downgrade(&other));
+                assert!(Arc::ptr_eq(&data, &previous.unwrap()));
+                assert_eq!("World", **shared.load_upgrade().unwrap());
+            }
+
+            #[test]
+            fn demote_promote() {
+                let shared = ArcSwapWeakOrStrong::new(StrongOrWeak::from(Arc::new("Hello")));
+                assert!(shared.load().is_strong());
+                let held = shared.promote().unwrap();
+                assert_eq!("Hello", *held);
+
+                assert!(shared.demote());
+                assert!(!shared.demote());
+                assert!(!shared.load().is_strong());
+                // Still alive, because we hold it
+                assert_eq!("Hello", **shared.load_upgrade().unwrap());
+                assert!(Arc::ptr_eq(&held, &shared.promote().unwrap()));
+                assert!(shared.load().is_strong());
+
+                assert!(shared.demote());
+                drop(held);
+                assert!(shared.load_upgrade().is_none());
+                assert!(shared.promote().is_none());
+                assert!(!shared.load().is_strong());
+            }
+
+            // Values of both kinds are released properly
+            #[test]
+            fn strong_or_weak_counts() {
+                let data = Arc::new("Hello");
+                let shared = ArcSwapWeakOrStrong::new(StrongOrWeak::from(Arc::clone(&data)));
+                assert_eq!(2, Arc::strong_count(&data));
+                assert!(shared.demote());
+                assert_eq!(1, Arc::strong_count(&data));
+                assert_eq!(1, Arc::weak_count(&data));
+                shared.store(StrongOrWeak::default());
+                assert_eq!(1, Arc::strong_count(&data));
+                assert_eq!(0, Arc::weak_count(&data));
+            }
         }
     };
 }
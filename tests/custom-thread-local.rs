@@ -0,0 +1,45 @@
+//! Using the library with the thread-local storage provided from the outside.
+#![cfg(feature = "custom-thread-local")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use arc_swap::local_storage::{self, LocalStorage};
+use arc_swap::ArcSwap;
+use crossbeam_utils::thread;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+std::thread_local! {
+    static STORAGE: LocalStorage = const { LocalStorage::new() };
+}
+
+fn provide(f: &mut dyn FnMut(&LocalStorage)) {
+    CALLS.fetch_add(1, Ordering::Relaxed);
+    let _ = STORAGE.try_with(|s| f(s));
+}
+
+#[test]
+fn registered() {
+    // Works even before registering, just slowly
+    let shared = ArcSwap::from_pointee(0);
+    assert_eq!(0, **shared.load());
+
+    unsafe { local_storage::register(provide) };
+
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|_| {
+                for i in 0..100 {
+                    let guard = shared.load();
+                    shared.rcu(|v| **v + 1);
+                    assert!(**guard >= i);
+                }
+            });
+        }
+    })
+    .unwrap();
+    assert_eq!(400, **shared.load());
+    assert!(CALLS.load(Ordering::Relaxed) > 0);
+    assert_eq!(2, Arc::strong_count(&shared.load_full()));
+}
@@ -0,0 +1,75 @@
+//! The limits of the debug-guards feature.
+//!
+//! These are global, so they live in their own test binary (and the tests here take turns).
+#![cfg(feature = "debug-guards")]
+
+use std::panic;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use arc_swap::debug_guards::{self, Action};
+use arc_swap::ArcSwap;
+
+static LIMITS: Mutex<()> = Mutex::new(());
+
+fn lock_limits() -> MutexGuard<'static, ()> {
+    LIMITS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn message(payload: Box<dyn std::any::Any + Send>) -> String {
+    payload.downcast_ref::<String>().unwrap().clone()
+}
+
+#[test]
+fn too_many() {
+    let _lock = lock_limits();
+    debug_guards::set_max_guards(Some(2));
+    debug_guards::set_action(Action::Panic);
+    let shared = ArcSwap::from_pointee(42);
+    let result = panic::catch_unwind(|| {
+        let _guards = (0..3).map(|_| shared.load()).collect::<Vec<_>>();
+    });
+    // Two are still fine.
+    let guards = (0..2).map(|_| shared.load()).collect::<Vec<_>>();
+    debug_guards::set_max_guards(None);
+    debug_guards::set_action(Action::Warn);
+    let message = message(result.unwrap_err());
+    assert!(message.contains("more than 2 guards"), "{}", message);
+    assert!(message.contains(file!()), "{}", message);
+    assert_eq!(2, debug_guards::dump().len());
+    drop(guards);
+    assert!(debug_guards::dump().is_empty());
+}
+
+#[test]
+fn held_too_long() {
+    let _lock = lock_limits();
+    debug_guards::set_max_hold(Some(Duration::from_millis(1)));
+    debug_guards::set_action(Action::Panic);
+    let shared = ArcSwap::from_pointee(42);
+    let guard = shared.load();
+    thread::sleep(Duration::from_millis(10));
+    let result = panic::catch_unwind(debug_guards::check);
+    // Reported only once, so these don't panic.
+    debug_guards::check();
+    drop(guard);
+    debug_guards::set_max_hold(None);
+    debug_guards::set_action(Action::Warn);
+    let message = message(result.unwrap_err());
+    assert!(message.contains("held for more than"), "{}", message);
+}
+
+#[test]
+fn backtraces() {
+    let _lock = lock_limits();
+    debug_guards::set_backtraces(true);
+    let shared = ArcSwap::from_pointee(42);
+    let guard = shared.load();
+    debug_guards::set_backtraces(false);
+    let without = shared.load();
+    let infos = debug_guards::dump();
+    assert!(infos[0].backtrace().is_some());
+    assert!(infos[1].backtrace().is_none());
+    drop((guard, without));
+}
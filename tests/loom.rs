@@ -0,0 +1,263 @@
+//! Model checking of the reader-writer interactions.
+//!
+//! Unlike the stress tests, these explore (within the preemption bound) all the possible
+//! interleavings of the threads. They need to be compiled with the `loom` cfg, which replaces the
+//! atomics inside the library:
+//!
+//! ```sh
+//! RUSTFLAGS="--cfg loom" cargo test --test loom --release --features internal-test-strategies
+//! ```
+//!
+//! The preemption bound can be changed by the `LOOM_MAX_PREEMPTIONS` environment variable.
+//!
+//! Note that loom doesn't model `SeqCst` operations fully, so the library surrounds them with
+//! `SeqCst` fences under loom (see `src/sync.rs`). That is stronger than the real thing, so a
+//! problem relying on the difference can be missed. Neither does it explore beyond the preemption
+//! bound. A pass is not a proof.
+//!
+//! The values are in the loom's `Arc`, so the model checker also reports them leaking.
+//!
+//! The collisions inside the debt slots are checked in `src/debt/loom_tests.rs`, which needs access
+//! to the internals.
+#![cfg(loom)]
+
+use std::sync::Arc as StdArc;
+
+use arc_swap::strategy::CaS;
+use arc_swap::{ArcSwapAny, DefaultStrategy, Guard};
+use loom::model::Builder;
+use loom::sync::atomic::{AtomicUsize, Ordering};
+use loom::sync::Arc;
+use loom::thread;
+
+/// Counts how many times it got dropped, so we can check nothing leaked or got freed twice.
+struct Tracked {
+    value: usize,
+    drops: Arc<AtomicUsize>,
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.drops.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn tracked(value: usize, drops: &Arc<AtomicUsize>) -> Arc<Tracked> {
+    Arc::new(Tracked {
+        value,
+        drops: Arc::clone(drops),
+    })
+}
+
+/// Loom runs the threads on tiny stacks by default, which the library (especially the helping
+/// path) doesn't fit in.
+fn spawn<R, F>(f: F) -> thread::JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    thread::Builder::new().stack_size(1 << 20).spawn(f).unwrap()
+}
+
+fn model<F: Fn() + Sync + Send + 'static>(f: F) {
+    let mut builder = Builder::new();
+    // The full exploration is way too big, the bound keeps it within minutes.
+    if builder.preemption_bound.is_none() {
+        builder.preemption_bound = Some(3);
+    }
+    // Outside of the model, so not the loom's one.
+    let f = StdArc::new(f);
+    builder.check(move || {
+        // The main thread of the model has a small stack too, so run the test body elsewhere.
+        let f = StdArc::clone(&f);
+        spawn(move || f()).join().unwrap();
+    });
+}
+
+type Shared<S> = Arc<ArcSwapAny<Arc<Tracked>, S>>;
+
+/// A reader loads while a writer replaces the value.
+///
+/// This goes through the collision between the reader acquiring its debt and the writer paying
+/// the debts of the replaced value.
+fn load_store<S>()
+where
+    S: CaS<Arc<Tracked>> + Default + Send + Sync + 'static,
+{
+    model(|| {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let shared: Shared<S> = Arc::new(ArcSwapAny::new(tracked(0, &drops)));
+
+        let reader = {
+            let shared = Arc::clone(&shared);
+            spawn(move || {
+                let guard = shared.load();
+                let value = guard.value;
+                assert!(value == 0 || value == 1);
+                if value == 0 {
+                    // The guard keeps it alive even if the writer already replaced it.
+                    assert_eq!(0, guard.drops.load(Ordering::Relaxed));
+                }
+                value
+            })
+        };
+
+        shared.store(tracked(1, &drops));
+        reader.join().unwrap();
+
+        // The old one is gone exactly once, the new one is still alive.
+        assert_eq!(1, drops.load(Ordering::Relaxed));
+        assert_eq!(1, shared.load().value);
+        drop(shared);
+        assert_eq!(2, drops.load(Ordering::Relaxed));
+    });
+}
+
+#[test]
+fn load_store_default() {
+    load_store::<DefaultStrategy>();
+}
+
+/// The same, but through the helping (fallback) slots.
+#[test]
+#[cfg(feature = "internal-test-strategies")]
+#[allow(deprecated)]
+fn load_store_helping() {
+    load_store::<arc_swap::strategy::test_strategies::FillFastSlots>();
+}
+
+/// The reader keeps the guard while the writer replaces the value twice.
+///
+/// The writer has to pay the debt on the first value, so the reader keeps it alive on its own.
+fn hold_across_stores<S>()
+where
+    S: CaS<Arc<Tracked>> + Default + Send + Sync + 'static,
+{
+    model(|| {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let shared: Shared<S> = Arc::new(ArcSwapAny::new(tracked(0, &drops)));
+
+        let guard = shared.load();
+
+        let writer = {
+            let shared = Arc::clone(&shared);
+            let drops = Arc::clone(&drops);
+            spawn(move || {
+                shared.store(tracked(1, &drops));
+                shared.store(tracked(2, &drops));
+            })
+        };
+
+        assert_eq!(0, guard.value);
+        let full = Guard::into_inner(guard);
+        assert_eq!(0, full.value);
+        writer.join().unwrap();
+
+        assert_eq!(1, drops.load(Ordering::Relaxed));
+        drop(full);
+        assert_eq!(2, drops.load(Ordering::Relaxed));
+        assert_eq!(2, shared.load().value);
+    });
+}
+
+#[test]
+fn hold_across_stores_default() {
+    hold_across_stores::<DefaultStrategy>();
+}
+
+#[test]
+#[cfg(feature = "internal-test-strategies")]
+#[allow(deprecated)]
+fn hold_across_stores_helping() {
+    hold_across_stores::<arc_swap::strategy::test_strategies::FillFastSlots>();
+}
+
+/// Two concurrent rcu updates don't lose any of the updates.
+fn concurrent_rcu<S>()
+where
+    S: CaS<Arc<Tracked>> + Default + Send + Sync + 'static,
+{
+    model(|| {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let shared: Shared<S> = Arc::new(ArcSwapAny::new(tracked(0, &drops)));
+
+        let other = {
+            let shared = Arc::clone(&shared);
+            let drops = Arc::clone(&drops);
+            spawn(move || {
+                shared.rcu(|old| tracked(old.value + 1, &drops));
+            })
+        };
+        shared.rcu(|old| tracked(old.value + 1, &drops));
+        other.join().unwrap();
+
+        assert_eq!(2, shared.load().value);
+        // Some unsuccessful attempts might have been created and dropped, but all except the
+        // current one must be gone.
+        let created = shared.load().value + 1;
+        drop(shared);
+        assert!(drops.load(Ordering::Relaxed) >= created);
+    });
+}
+
+#[test]
+fn concurrent_rcu_default() {
+    concurrent_rcu::<DefaultStrategy>();
+}
+
+#[test]
+#[cfg(feature = "internal-test-strategies")]
+#[allow(deprecated)]
+fn concurrent_rcu_helping() {
+    concurrent_rcu::<arc_swap::strategy::test_strategies::FillFastSlots>();
+}
+
+/// A reader loads while a writer replaces the value by `compare_and_swap`.
+///
+/// The writer has to pay the debts (or help the reader) the same way as with a plain store, but
+/// it also holds its own guard of the current value while doing so.
+fn cas_with_load<S>()
+where
+    S: CaS<Arc<Tracked>> + Default + Send + Sync + 'static,
+{
+    model(|| {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let shared: Shared<S> = Arc::new(ArcSwapAny::new(tracked(0, &drops)));
+
+        let reader = {
+            let shared = Arc::clone(&shared);
+            spawn(move || {
+                let guard = shared.load();
+                assert!(guard.value == 0 || guard.value == 1);
+                if guard.value == 0 {
+                    assert_eq!(0, guard.drops.load(Ordering::Relaxed));
+                }
+            })
+        };
+
+        let current = shared.load();
+        let previous = shared.compare_and_swap(&*current, tracked(1, &drops));
+        // Nobody else writes, so it must have succeeded.
+        assert!(std::ptr::eq(&**current, &**previous));
+        drop(current);
+        drop(previous);
+        reader.join().unwrap();
+
+        assert_eq!(1, drops.load(Ordering::Relaxed));
+        assert_eq!(1, shared.load().value);
+        drop(shared);
+        assert_eq!(2, drops.load(Ordering::Relaxed));
+    });
+}
+
+#[test]
+fn cas_with_load_default() {
+    cas_with_load::<DefaultStrategy>();
+}
+
+#[test]
+#[cfg(feature = "internal-test-strategies")]
+#[allow(deprecated)]
+fn cas_with_load_helping() {
+    cas_with_load::<arc_swap::strategy::test_strategies::FillFastSlots>();
+}
@@ -0,0 +1,87 @@
+//! Placement of the debt nodes on a simulated NUMA machine.
+//!
+//! The topology is global, so it lives in its own test binary.
+#![cfg(feature = "numa")]
+
+use std::cell::Cell;
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+use arc_swap::numa::{self, Topology};
+use arc_swap::ArcSwap;
+
+const NODES: usize = 4;
+const THREADS_PER_NODE: usize = 3;
+const ITERATIONS: usize = 1000;
+
+thread_local! {
+    static SIMULATED_NODE: Cell<usize> = const { Cell::new(0) };
+    static LOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+fn install() {
+    let distances = (0..NODES)
+        .map(|from| {
+            (0..NODES)
+                .map(|to| if from == to { 10 } else { 20 })
+                .collect()
+        })
+        .collect();
+    numa::install(Topology::new(distances, || {
+        LOCATED.with(|located| located.set(located.get() + 1));
+        SIMULATED_NODE.with(Cell::get)
+    }));
+}
+
+/// Threads on different nodes hold guards while writers on other nodes replace the value. The
+/// writers have to find the debts in all the groups.
+#[test]
+fn across_nodes() {
+    install();
+    let first = Arc::new(0);
+    let shared = ArcSwap::new(Arc::clone(&first));
+    let barrier = Barrier::new(NODES * THREADS_PER_NODE);
+    thread::scope(|s| {
+        for node in 0..NODES {
+            for thread in 0..THREADS_PER_NODE {
+                let shared = &shared;
+                let barrier = &barrier;
+                s.spawn(move || {
+                    SIMULATED_NODE.with(|simulated| simulated.set(node));
+                    let held = shared.load();
+                    barrier.wait();
+                    for i in 0..ITERATIONS {
+                        if thread == 0 {
+                            shared.store(Arc::new(i));
+                        } else {
+                            let guard = shared.load();
+                            assert!(**guard < ITERATIONS);
+                        }
+                    }
+                    // The value loaded before the writers started is still alive.
+                    assert_eq!(0, **held);
+                });
+            }
+        }
+    });
+    drop(shared);
+    assert_eq!(1, Arc::strong_count(&first));
+}
+
+/// The thread is located when it claims its node, not on every load and store.
+#[test]
+fn located_rarely() {
+    install();
+    let shared = ArcSwap::from_pointee(0);
+    thread::spawn(move || {
+        SIMULATED_NODE.with(|simulated| simulated.set(2));
+        for i in 0..ITERATIONS {
+            shared.store(Arc::new(i));
+            assert_eq!(i, **shared.load());
+        }
+        // Once for the node, maybe few more times if it had to claim another one.
+        assert!(LOCATED.with(Cell::get) < 10);
+    })
+    .join()
+    .unwrap();
+}
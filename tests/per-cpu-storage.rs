@@ -0,0 +1,82 @@
+//! The custom thread-local storage provided per CPU, shared by threads taking turns.
+#![cfg(feature = "custom-thread-local")]
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use arc_swap::local_storage::{self, LocalStorage};
+use arc_swap::{ArcSwap, Cache};
+use crossbeam_utils::thread;
+
+const CPUS: usize = 2;
+
+/// The storage of one "CPU".
+///
+/// Only the thread holding the lock uses the storage, which stands in for the CPU not being
+/// preempted during the call.
+struct Cpu {
+    lock: Mutex<()>,
+    storage: LocalStorage,
+}
+
+// The storage is used only under the lock.
+unsafe impl Sync for Cpu {}
+
+static CPU_STORAGES: [Cpu; CPUS] = [
+    Cpu {
+        lock: Mutex::new(()),
+        storage: LocalStorage::new(),
+    },
+    Cpu {
+        lock: Mutex::new(()),
+        storage: LocalStorage::new(),
+    },
+];
+
+/// Which CPU the next call "runs" on, the threads migrate between them all the time.
+static NEXT_CPU: AtomicUsize = AtomicUsize::new(0);
+
+std::thread_local! {
+    /// The CPU the thread is on, while inside the provider.
+    static CURRENT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+fn provide(f: &mut dyn FnMut(&LocalStorage)) {
+    if let Some(cpu) = CURRENT.with(Cell::get) {
+        // A nested call, we are still on the same CPU.
+        return f(&CPU_STORAGES[cpu].storage);
+    }
+    let cpu = NEXT_CPU.fetch_add(1, Ordering::Relaxed) % CPUS;
+    let _lock = CPU_STORAGES[cpu].lock.lock().unwrap();
+    CURRENT.with(|current| current.set(Some(cpu)));
+    f(&CPU_STORAGES[cpu].storage);
+    CURRENT.with(|current| current.set(None));
+}
+
+#[test]
+fn per_cpu() {
+    unsafe { local_storage::register(provide) };
+
+    let shared = ArcSwap::from_pointee(0);
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|_| {
+                let mut cache = Cache::new(&shared);
+                let mut held = Vec::new();
+                for i in 0..100 {
+                    // The guards outlive the calls and get used from the other CPUs.
+                    held.push(shared.load());
+                    shared.rcu(|v| **v + 1);
+                    assert!(**cache.load() > i);
+                    if held.len() > 10 {
+                        held.clear();
+                    }
+                }
+            });
+        }
+    })
+    .unwrap();
+    assert_eq!(400, **shared.load());
+    assert_eq!(2, Arc::strong_count(&shared.load_full()));
+}